@@ -0,0 +1,14 @@
+--- Links between bank statement entries and the transactions or redemptions they pay for.
+--- The bank entry is identified by its date, amount and description, the record by its kind and id.
+CREATE TABLE IF NOT EXISTS bank_link (
+    id                  UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id             UUID NOT NULL,
+    kind                VARCHAR(32) NOT NULL,
+    record_id           UUID NOT NULL,
+    bank_date           DATE NOT NULL,
+    bank_amount         NUMERIC NOT NULL,
+    bank_description    VARCHAR(2048) NOT NULL,
+    created_at          TIMESTAMP NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES "user"(id) ON DELETE CASCADE,
+    UNIQUE (user_id, kind, record_id)
+);
@@ -0,0 +1,68 @@
+-- SQLite version of migrations/20240827210820_casino_buddy_schema.sql
+-- UUIDs are stored as 16 byte blobs, NUMERIC values as TEXT so they keep their exact precision.
+
+CREATE TABLE IF NOT EXISTS casino (
+    id                      BLOB PRIMARY KEY NOT NULL DEFAULT (randomblob(16)),
+    name                    TEXT NOT NULL,
+    url                     TEXT NOT NULL,
+    description             TEXT NOT NULL,
+    created_at              TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at              TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS "user" (
+    id                  BLOB PRIMARY KEY NOT NULL DEFAULT (randomblob(16)),
+    created_at          TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at          TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE TABLE IF NOT EXISTS "transaction" (
+    id              BLOB PRIMARY KEY NOT NULL DEFAULT (randomblob(16)),
+    user_id         BLOB NOT NULL,
+    casino_id       BLOB NOT NULL,
+    cost            TEXT NOT NULL,
+    benefit         TEXT NOT NULL,
+    created_at      TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at      TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    notes           TEXT,
+    FOREIGN KEY (user_id) REFERENCES "user"(id) ON DELETE CASCADE,
+    FOREIGN KEY (casino_id) REFERENCES casino(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS redemption (
+    id                      BLOB PRIMARY KEY NOT NULL DEFAULT (randomblob(16)),
+    user_id                 BLOB NOT NULL,
+    casino_id               BLOB NOT NULL,
+    amount                  TEXT NOT NULL,
+    created_at              TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    received_at             TEXT,
+    FOREIGN KEY (user_id)   REFERENCES "user"(id),
+    FOREIGN KEY (casino_id) REFERENCES casino(id)
+);
+
+CREATE TABLE IF NOT EXISTS user_casino (
+    id                      BLOB PRIMARY KEY NOT NULL DEFAULT (randomblob(16)),
+    user_id                 BLOB NOT NULL,
+    casino_id               BLOB NOT NULL,
+    is_vip                  BOOLEAN NOT NULL,
+    is_verified             BOOLEAN NOT NULL,
+    is_self_excluded        BOOLEAN NOT NULL,
+    created_at              TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id)   REFERENCES "user"(id) ON DELETE CASCADE,
+    FOREIGN KEY (casino_id) REFERENCES casino(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS daily_bonus (
+    id                      BLOB PRIMARY KEY NOT NULL DEFAULT (randomblob(16)),
+    user_id                 BLOB NOT NULL,
+    casino_id               BLOB NOT NULL,
+    amount_sc               TEXT NOT NULL,
+    amount_gc               TEXT NOT NULL,
+    amount_other1           TEXT NOT NULL,
+    amount_other2           TEXT NOT NULL,
+    amount_other3           TEXT NOT NULL,
+    amount_other4           TEXT NOT NULL,
+    created_at              TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id)   REFERENCES "user"(id) ON DELETE CASCADE,
+    FOREIGN KEY (casino_id) REFERENCES casino(id) ON DELETE CASCADE
+);
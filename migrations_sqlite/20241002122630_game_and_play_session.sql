@@ -0,0 +1,41 @@
+-- SQLite version of migrations/20241002122630_game_and_play_session.sql
+CREATE TABLE IF NOT EXISTS play_session (
+    id                      BLOB PRIMARY KEY NOT NULL DEFAULT (randomblob(16)),
+    user_id                 BLOB NOT NULL,
+    casino_id               BLOB NOT NULL,
+    game_id                 BLOB NOT NULL,
+    beg_amount              TEXT NOT NULL,
+    end_amount              TEXT NOT NULL,
+    sc_per_spin             TEXT NOT NULL,
+    num_spins               TEXT NOT NULL,
+    play_date               TEXT NOT NULL,
+    created_at              TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at              TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id)   REFERENCES "user"(id),
+    FOREIGN KEY (casino_id) REFERENCES casino(id)
+);
+
+--- Create a table to store the information about each developer.
+CREATE TABLE IF NOT EXISTS developer (
+    id          BLOB PRIMARY KEY NOT NULL DEFAULT (randomblob(16)),
+    name        TEXT NOT NULL,
+    created_at  TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at  TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+--- Create a table to store the information abou each game. There is an entry to each game on each site.
+CREATE TABLE IF NOT EXISTS game (
+    id                          BLOB PRIMARY KEY NOT NULL DEFAULT (randomblob(16)),
+    casino_id                   BLOB NOT NULL,
+    developer_id                BLOB NOT NULL,
+    name                        TEXT NOT NULL,
+    feature_hold_and_spin       BOOLEAN NOT NULL,
+    feature_1                   BOOLEAN NOT NULL,
+    feature_2                   BOOLEAN NOT NULL,
+    feature_3                   BOOLEAN NOT NULL,
+    descriptions                TEXT,
+    created_at                  TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at                  TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (casino_id)     REFERENCES casino(id) ON DELETE CASCADE,
+    FOREIGN KEY (developer_id)  REFERENCES developer(id) ON DELETE CASCADE
+);
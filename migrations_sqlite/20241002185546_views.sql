@@ -0,0 +1,36 @@
+---
+--- SQLite version of migrations/20241002185546_views.sql
+--- The amounts are stored as TEXT, so they are cast to REAL before summing.
+---
+
+-- Create a view to get the spend and benefit for each user
+CREATE VIEW IF NOT EXISTS user_spend_benefit AS SELECT
+    user_id,
+    SUM(CAST(cost AS REAL)) AS spend,
+    SUM(CAST(benefit AS REAL)) AS benefit,
+    (SUM(CAST(benefit AS REAL)) - SUM(CAST(cost AS REAL))) / SUM(CAST(cost AS REAL)) AS average_bonus,
+    COUNT(*) AS transactions,
+    MAX(created_at) AS last_transaction,
+    MIN(created_at) AS first_transaction
+FROM "transaction"
+GROUP BY user_id;
+
+-- Create a view to get the spend and benefit for each user for each casino
+CREATE VIEW IF NOT EXISTS user_casino_spend_benefit AS SELECT
+    user_id,
+    casino_id,
+    SUM(CAST(cost AS REAL)) AS spend,
+    SUM(CAST(benefit AS REAL)) AS benefit,
+    (SUM(CAST(benefit AS REAL)) - SUM(CAST(cost AS REAL))) / SUM(CAST(cost AS REAL)) AS average_bonus,
+    COUNT(*) AS transactions,
+    MAX(created_at) AS last_transaction,
+    MIN(created_at) AS first_transaction
+FROM "transaction"
+GROUP BY user_id, casino_id;
+
+-- Create a view to get the list of casinos and their names for each user
+CREATE VIEW IF NOT EXISTS user_casino_name AS SELECT
+    user_id,
+    casino_id,
+    casino.name AS casino_name
+FROM (user_casino JOIN casino ON user_casino.casino_id = casino.id);
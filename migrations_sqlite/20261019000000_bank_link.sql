@@ -0,0 +1,14 @@
+-- SQLite version of migrations/20261019000000_bank_link.sql
+
+CREATE TABLE IF NOT EXISTS bank_link (
+    id                  BLOB PRIMARY KEY NOT NULL DEFAULT (randomblob(16)),
+    user_id             BLOB NOT NULL,
+    kind                TEXT NOT NULL,
+    record_id           BLOB NOT NULL,
+    bank_date           TEXT NOT NULL,
+    bank_amount         TEXT NOT NULL,
+    bank_description    TEXT NOT NULL,
+    created_at          TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (user_id) REFERENCES "user"(id) ON DELETE CASCADE,
+    UNIQUE (user_id, kind, record_id)
+);
@@ -0,0 +1,287 @@
+use bigdecimal::BigDecimal;
+use casino_buddy::cli::{bonus_percent, Backend, CliConfig, CliError};
+use casino_buddy::email::DEFAULT_DEDUP_WINDOW_HOURS;
+use casino_buddy::import::{ColumnMapping, ImportStatus};
+use chrono::Duration;
+use clap::{Args, Parser, Subcommand};
+use std::io::Write;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+/// Log casino purchases, redemptions and bonuses from the terminal.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Config file, defaults to `casino-buddy/cli.toml` in the user config directory.
+    #[arg(long, global = true, env = "CASINO_BUDDY_CLI_CONFIG")]
+    config: Option<PathBuf>,
+    #[command(flatten)]
+    overrides: Overrides,
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Settings that override the config file.
+#[derive(Debug, Args)]
+struct Overrides {
+    /// Base url of the API server.
+    #[arg(long, global = true, env = "CASINO_BUDDY_SERVER")]
+    server: Option<String>,
+    /// Use this database directly instead of the API.
+    #[arg(long, global = true, env = "CASINO_BUDDY_DATABASE_URL")]
+    database_url: Option<String>,
+    /// The user to log for.
+    #[arg(long = "user", global = true, env = "CASINO_BUDDY_USER")]
+    user_id: Option<Uuid>,
+    /// Token sent with API requests.
+    #[arg(long, global = true, env = "CASINO_BUDDY_TOKEN", hide_env_values = true)]
+    token: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Save the server, user and token to the config file.
+    Init {
+        /// Create a new user when none is given.
+        #[arg(long)]
+        create_user: bool,
+    },
+    /// List the casinos.
+    Casinos,
+    /// Log a purchase of `benefit` coins for `cost`.
+    Buy {
+        /// Casino name or url, a unique part of the name is enough.
+        casino: String,
+        cost: BigDecimal,
+        benefit: BigDecimal,
+        #[arg(long)]
+        notes: Option<String>,
+    },
+    /// Log a redemption.
+    Redeem {
+        casino: String,
+        amount: BigDecimal,
+        /// The money arrived, now or on the given `YYYY-MM-DD` date.
+        #[arg(long, num_args = 0..=1, default_missing_value = "now", value_parser = parse_date)]
+        received: Option<chrono::NaiveDateTime>,
+    },
+    /// Daily bonuses.
+    #[command(subcommand)]
+    Bonus(BonusCommand),
+    /// Spend and benefit per casino.
+    Summary,
+    /// Import purchases from a CSV file with a header row.
+    Import {
+        file: PathBuf,
+        #[command(flatten)]
+        columns: Columns,
+    },
+    /// Log purchases from emailed receipts, `.eml` files or mbox archives.
+    ImportEmail {
+        #[arg(required = true)]
+        files: Vec<PathBuf>,
+        /// Hours around a receipt in which a purchase with the same casino and cost is a duplicate.
+        #[arg(long, default_value_t = DEFAULT_DEDUP_WINDOW_HOURS)]
+        window_hours: i64,
+        /// Log every new purchase without asking.
+        #[arg(long)]
+        yes: bool,
+    },
+}
+
+/// Header names of the columns to import.
+#[derive(Debug, Args)]
+struct Columns {
+    /// Casino name or url column.
+    #[arg(long, default_value = "casino")]
+    casino_column: String,
+    /// Money spent column.
+    #[arg(long, default_value = "cost")]
+    cost_column: String,
+    /// Coins received column.
+    #[arg(long, default_value = "benefit")]
+    benefit_column: String,
+    /// Purchase date column.
+    #[arg(long, default_value = "date")]
+    date_column: String,
+    /// Notes column, optional in the file.
+    #[arg(long, default_value = "notes")]
+    notes_column: String,
+}
+
+#[derive(Debug, Subcommand)]
+enum BonusCommand {
+    /// Log a claimed daily bonus.
+    Claim {
+        casino: String,
+        /// Sweeps coins received.
+        sc: BigDecimal,
+        /// Gold coins received.
+        #[arg(long, default_value = "0")]
+        gc: BigDecimal,
+    },
+}
+
+/// Parse `now`, a `YYYY-MM-DD` date or a `YYYY-MM-DDTHH:MM:SS` time.
+fn parse_date(value: &str) -> Result<chrono::NaiveDateTime, String> {
+    if value == "now" {
+        return Ok(chrono::Utc::now().naive_utc());
+    }
+    if let Ok(date) = value.parse::<chrono::NaiveDate>() {
+        return Ok(date.and_time(chrono::NaiveTime::MIN));
+    }
+    value
+        .parse::<chrono::NaiveDateTime>()
+        .map_err(|_| format!("{value:?} is not `now`, YYYY-MM-DD or YYYY-MM-DDTHH:MM:SS"))
+}
+
+async fn run(cli: Cli) -> Result<(), CliError> {
+    let path = cli.config.or_else(CliConfig::default_path).unwrap_or_else(|| PathBuf::from("cli.toml"));
+    let overrides = CliConfig {
+        server: cli.overrides.server,
+        database_url: cli.overrides.database_url,
+        user_id: cli.overrides.user_id,
+        token: cli.overrides.token,
+    };
+    let config = CliConfig::read(&path)?.merge(overrides);
+    let backend = config.backend()?;
+
+    match cli.command {
+        Command::Init { create_user } => {
+            let mut config = config;
+            if config.user_id.is_none() && create_user {
+                config.user_id = Some(backend.create_user().await?);
+            }
+            config.write(&path)?;
+            println!("Saved {}", path.display());
+            match config.user_id {
+                Some(user_id) => println!("Logging for user {user_id}"),
+                None => println!("No user set, pass --user or --create-user"),
+            }
+        }
+        Command::Casinos => {
+            for casino in backend.casinos().await? {
+                println!("{:<24} {}", casino.name, casino.url);
+            }
+        }
+        Command::Buy { casino, cost, benefit, notes } => {
+            let casino = backend.casino(&casino).await?;
+            let transaction = backend.buy(config.user()?, casino.id, cost, benefit, notes).await?;
+            println!(
+                "Bought {} for {} at {} ({})",
+                transaction.benefit, transaction.cost, casino.name, transaction.id
+            );
+        }
+        Command::Redeem { casino, amount, received } => {
+            let casino = backend.casino(&casino).await?;
+            let redemption = backend.redeem(config.user()?, casino.id, amount, received).await?;
+            let state = if redemption.received_at.is_some() { "received" } else { "pending" };
+            println!("Redeemed {} at {}, {state} ({})", redemption.amount, casino.name, redemption.id);
+        }
+        Command::Bonus(BonusCommand::Claim { casino, sc, gc }) => {
+            let casino = backend.casino(&casino).await?;
+            let bonus = backend.claim_bonus(config.user()?, casino.id, sc, gc).await?;
+            println!("Claimed {} SC and {} GC at {}", bonus.amount_sc, bonus.amount_gc, casino.name);
+        }
+        Command::Summary => print_summary(&backend, config.user()?).await?,
+        Command::Import { file, columns } => {
+            let csv = std::fs::read_to_string(&file).map_err(|e| CliError::Io(file.clone(), e))?;
+            let columns = ColumnMapping {
+                casino: columns.casino_column,
+                cost: columns.cost_column,
+                benefit: columns.benefit_column,
+                date: columns.date_column,
+                notes: columns.notes_column,
+            };
+            let report = backend.import_transactions(config.user()?, csv, &columns).await?;
+            for row in &report.rows {
+                match (row.status, &row.error, row.transaction_id) {
+                    (ImportStatus::Rejected, Some(error), _) => println!("line {}: rejected, {error}", row.line),
+                    (ImportStatus::Duplicate, _, Some(id)) => println!("line {}: duplicate of {id}", row.line),
+                    _ => {}
+                }
+            }
+            println!(
+                "{} accepted, {} duplicates, {} rejected",
+                report.accepted, report.duplicates, report.rejected
+            );
+        }
+        Command::ImportEmail { files, window_hours, yes } => {
+            let files = files
+                .iter()
+                .map(|file| std::fs::read(file).map_err(|e| CliError::Io(file.clone(), e)))
+                .collect::<Result<Vec<_>, _>>()?;
+            let user_id = config.user()?;
+            let scan = backend.propose_email_transactions(user_id, &files, Duration::hours(window_hours)).await?;
+            for reason in &scan.unreadable {
+                println!("skipped {reason}");
+            }
+            let mut confirmed = vec![];
+            for proposal in scan.proposals {
+                let t = &proposal.transaction;
+                let line = format!("{} {} paid {} for {} SC", t.created_at, proposal.casino_name, t.cost, t.benefit);
+                if let Some(id) = proposal.duplicate_of {
+                    println!("{line}: duplicate of {id}");
+                } else if yes || confirm(&format!("{line}, log it?"))? {
+                    confirmed.push(proposal);
+                }
+            }
+            let report = backend.accept_proposals(user_id, &confirmed).await?;
+            for row in report.rows.iter().filter(|row| row.status == ImportStatus::Rejected) {
+                // The first proposal is on line 2, after the header.
+                let Some(proposal) = confirmed.get(row.line.saturating_sub(2) as usize) else { continue };
+                let t = &proposal.transaction;
+                let reason = row.error.as_deref().unwrap_or("rejected");
+                println!("{} {} paid {}: {reason}", t.created_at, proposal.casino_name, t.cost);
+            }
+            println!(
+                "{} logged, {} rejected, {} other emails ignored",
+                report.accepted, report.rejected, scan.ignored
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Ask a yes or no question on the terminal, no unless answered `y`.
+fn confirm(question: &str) -> Result<bool, CliError> {
+    let io = |e| CliError::Io(PathBuf::from("<stdin>"), e);
+    print!("{question} [y/N] ");
+    std::io::stdout().flush().map_err(io)?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer).map_err(io)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+async fn print_summary(backend: &Backend, user_id: Uuid) -> Result<(), CliError> {
+    let summaries = backend.summary(user_id).await?;
+    if summaries.is_empty() {
+        println!("No transactions yet");
+        return Ok(());
+    }
+    println!("{:<24} {:>10} {:>10} {:>8} {:>6}", "casino", "spend", "benefit", "bonus", "count");
+    let (mut spend, mut benefit) = (BigDecimal::from(0), BigDecimal::from(0));
+    for summary in &summaries {
+        println!(
+            "{:<24} {:>10} {:>10} {:>8} {:>6}",
+            summary.casino_name,
+            summary.spend.round(2),
+            summary.benefit.round(2),
+            bonus_percent(&summary.spend, &summary.benefit),
+            summary.transactions
+        );
+        spend += &summary.spend;
+        benefit += &summary.benefit;
+    }
+    println!("{:<24} {:>10} {:>10} {:>8}", "total", spend.round(2), benefit.round(2), bonus_percent(&spend, &benefit));
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    if let Err(e) = run(cli).await {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,83 @@
+use casino_buddy::cli::CliConfig;
+use casino_buddy::tui::{log_entry, Action, App};
+use casino_buddy::{CasinoContext, Db};
+use clap::Parser;
+use ratatui::crossterm::event::{self, Event, KeyEventKind};
+use ratatui::DefaultTerminal;
+use std::path::PathBuf;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Dashboard of spend, pending redemptions and unclaimed daily bonuses.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    /// Config file shared with `casino-buddy-cli`.
+    #[arg(long, env = "CASINO_BUDDY_CLI_CONFIG")]
+    config: Option<PathBuf>,
+    /// The database to read and log to.
+    #[arg(long, env = "CASINO_BUDDY_DATABASE_URL")]
+    database_url: Option<String>,
+    /// The user to show.
+    #[arg(long = "user", env = "CASINO_BUDDY_USER")]
+    user_id: Option<Uuid>,
+}
+
+type BoxError = Box<dyn std::error::Error>;
+
+/// Draw and handle keys until the user quits. Only the first load is fatal,
+/// later database errors are shown in the footer and the data left as it was.
+async fn event_loop(terminal: &mut DefaultTerminal, ctx: &CasinoContext, user_id: Uuid) -> Result<(), BoxError> {
+    let today = || chrono::Utc::now().date_naive();
+    let mut app = App::default();
+    app.refresh(ctx, user_id, today()).await?;
+    loop {
+        terminal.draw(|frame| app.render(frame))?;
+        if !event::poll(Duration::from_millis(250))? {
+            continue;
+        }
+        let Event::Key(key) = event::read()? else { continue };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+        match app.handle_key(key) {
+            None => {}
+            Some(Action::Quit) => return Ok(()),
+            Some(Action::Refresh) => {
+                if let Err(e) = app.refresh(ctx, user_id, today()).await {
+                    app.set_status(format!("Error: {e}"));
+                }
+            }
+            Some(Action::Submit(entry)) => match log_entry(ctx, user_id, entry).await {
+                Ok(logged) => match app.refresh(ctx, user_id, today()).await {
+                    Ok(()) => app.set_status(logged),
+                    Err(e) => app.set_status(format!("{logged}, refresh failed: {e}")),
+                },
+                Err(e) => app.set_status(format!("Error: {e}")),
+            },
+        }
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), BoxError> {
+    let path = cli.config.or_else(CliConfig::default_path).unwrap_or_else(|| PathBuf::from("cli.toml"));
+    let overrides = CliConfig { database_url: cli.database_url, user_id: cli.user_id, ..CliConfig::default() };
+    let config = CliConfig::read(&path)?.merge(overrides);
+    let user_id = config.user()?;
+    let url = config.database_url.ok_or("the dashboard reads the database directly, set --database-url")?;
+    let ctx = CasinoContext::new(Db::connect_lazy(&url, 1)?);
+
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, &ctx, user_id).await;
+    ratatui::restore();
+    result
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    if let Err(e) = run(cli).await {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+}
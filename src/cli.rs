@@ -0,0 +1,510 @@
+//! Shared pieces of the command line clients, behind the `client` feature.
+//!
+//! The clients keep a small TOML config with the server, the user they log for
+//! and an optional token, and talk to either the HTTP API through
+//! [`Client`] or straight to a database through [`CasinoContext`].
+
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+use bigdecimal::{BigDecimal, Zero};
+use uuid::Uuid;
+
+use crate::client::{Client, ClientError};
+use crate::email::{EmailScan, Proposal};
+use crate::import::{url_host, ColumnMapping, ImportError, ImportReport};
+use crate::{
+    Casino, CasinoContext, CasinoSummary, DailyBonus, DailyBonusCreate, Db, Redemption, RedemptionCreate,
+    Transaction, TransactionCreate,
+};
+
+/// Server used when the config doesn't name one.
+pub const DEFAULT_SERVER: &str = "http://localhost:3030";
+
+/// Local settings of a command line client.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CliConfig {
+    /// Base url of the API, [`DEFAULT_SERVER`] when unset.
+    pub server: Option<String>,
+    /// Talk to this database instead of the API.
+    pub database_url: Option<String>,
+    /// The user entries are logged for.
+    pub user_id: Option<Uuid>,
+    /// Sent as a bearer token with every API request.
+    pub token: Option<String>,
+}
+
+/// Errors from the command line clients.
+#[derive(Debug)]
+pub enum CliError {
+    Io(PathBuf, std::io::Error),
+    Toml(PathBuf, String),
+    /// No user id is configured.
+    NoUser,
+    /// No casino, or more than one, matches a name.
+    Casino(String),
+    Client(ClientError),
+    Import(ImportError),
+    Sqlx(sqlx::Error),
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "can't access {}: {e}", path.display()),
+            Self::Toml(path, e) => write!(f, "invalid config file {}: {e}", path.display()),
+            Self::NoUser => f.write_str("no user id configured, run `init` or pass --user"),
+            Self::Casino(msg) => f.write_str(msg),
+            Self::Client(e) => write!(f, "{e}"),
+            Self::Import(e) => write!(f, "{e}"),
+            Self::Sqlx(e) => write!(f, "database error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for CliError {}
+
+impl From<ClientError> for CliError {
+    fn from(e: ClientError) -> Self {
+        Self::Client(e)
+    }
+}
+
+impl From<ImportError> for CliError {
+    fn from(e: ImportError) -> Self {
+        Self::Import(e)
+    }
+}
+
+impl From<sqlx::Error> for CliError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Sqlx(e)
+    }
+}
+
+impl CliConfig {
+    /// `casino-buddy/cli.toml` in the user's config directory.
+    #[must_use]
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("casino-buddy").join("cli.toml"))
+    }
+
+    /// Read the config at `path`, a missing file is an empty config.
+    ///
+    /// # Errors
+    /// Will return `Err` if the file exists but can't be read or parsed.
+    pub fn read(path: &Path) -> Result<Self, CliError> {
+        match std::fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents).map_err(|e| CliError::Toml(path.to_path_buf(), e.to_string())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(CliError::Io(path.to_path_buf(), e)),
+        }
+    }
+
+    /// Write the config to `path`, creating its directory.
+    ///
+    /// # Errors
+    /// Will return `Err` if the file can't be written.
+    pub fn write(&self, path: &Path) -> Result<(), CliError> {
+        let io = |e| CliError::Io(path.to_path_buf(), e);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(io)?;
+        }
+        let contents = toml::to_string_pretty(self).map_err(|e| CliError::Toml(path.to_path_buf(), e.to_string()))?;
+        std::fs::write(path, contents).map_err(io)
+    }
+
+    /// Values from `other` replace the ones here.
+    #[must_use]
+    pub fn merge(self, other: Self) -> Self {
+        Self {
+            server: other.server.or(self.server),
+            database_url: other.database_url.or(self.database_url),
+            user_id: other.user_id.or(self.user_id),
+            token: other.token.or(self.token),
+        }
+    }
+
+    /// The configured user.
+    ///
+    /// # Errors
+    /// Will return `Err` if no user is configured.
+    pub fn user(&self) -> Result<Uuid, CliError> {
+        self.user_id.ok_or(CliError::NoUser)
+    }
+
+    /// Connect to the configured database, or the API when there is none.
+    ///
+    /// # Errors
+    /// Will return `Err` if the server or database url is invalid.
+    pub fn backend(&self) -> Result<Backend, CliError> {
+        if let Some(url) = &self.database_url {
+            return Ok(Backend::Db(CasinoContext::new(Db::connect_lazy(url, 1)?)));
+        }
+        let mut client = Client::new(self.server.as_deref().unwrap_or(DEFAULT_SERVER))?;
+        if let Some(token) = &self.token {
+            client = client.with_token(token.clone());
+        }
+        Ok(Backend::Api(client))
+    }
+}
+
+/// Find the casino `query` names: an exact name or url match, then the only
+/// casino whose name contains it, case insensitive.
+///
+/// # Errors
+/// Will return `Err` naming the candidates when nothing or more than one casino matches,
+/// or when `query` is blank.
+pub fn resolve_casino<'a>(casinos: &'a [Casino], query: &str) -> Result<&'a Casino, CliError> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return Err(CliError::Casino("no casino given".to_string()));
+    }
+    let host = url_host(&query);
+    let exact: Vec<&Casino> = casinos
+        .iter()
+        .filter(|casino| casino.name.to_lowercase() == query || (!host.is_empty() && url_host(&casino.url) == host))
+        .collect();
+    let candidates = if exact.is_empty() {
+        casinos.iter().filter(|casino| casino.name.to_lowercase().contains(&query)).collect()
+    } else {
+        exact
+    };
+    match candidates.as_slice() {
+        [casino] => Ok(casino),
+        [] => Err(CliError::Casino(format!("no casino matches {query:?}"))),
+        many => {
+            let names: Vec<&str> = many.iter().map(|casino| casino.name.as_str()).collect();
+            Err(CliError::Casino(format!("{query:?} matches {}", names.join(", "))))
+        }
+    }
+}
+
+/// Benefit above spend as a percentage of spend, `-` without spend.
+#[must_use]
+pub fn bonus_percent(spend: &BigDecimal, benefit: &BigDecimal) -> String {
+    if spend.is_zero() {
+        return "-".to_string();
+    }
+    format!("{}%", ((benefit - spend) * BigDecimal::from(100) / spend).round(1))
+}
+
+/// Where a command line client reads and logs entries.
+#[derive(Debug, Clone)]
+pub enum Backend {
+    Api(Client),
+    Db(CasinoContext),
+}
+
+impl Backend {
+    /// Every casino.
+    ///
+    /// # Errors
+    /// Will return `Err` if the request or query fails.
+    pub async fn casinos(&self) -> Result<Vec<Casino>, CliError> {
+        Ok(match self {
+            Self::Api(client) => client.casinos().await?,
+            Self::Db(ctx) => ctx.get_all_casinos().await?,
+        })
+    }
+
+    /// The casino `query` names, see [`resolve_casino`].
+    ///
+    /// # Errors
+    /// Will return `Err` if the casinos can't be listed or the name doesn't resolve.
+    pub async fn casino(&self, query: &str) -> Result<Casino, CliError> {
+        resolve_casino(&self.casinos().await?, query).cloned()
+    }
+
+    /// Log a purchase.
+    ///
+    /// # Errors
+    /// Will return `Err` if the request or insert fails.
+    pub async fn buy(
+        &self,
+        user_id: Uuid,
+        casino_id: Uuid,
+        cost: BigDecimal,
+        benefit: BigDecimal,
+        notes: Option<String>,
+    ) -> Result<Transaction, CliError> {
+        Ok(match self {
+            Self::Api(client) => {
+                client
+                    .create_transaction(&TransactionCreate {
+                        user_id: user_id.to_string(),
+                        casino_id: casino_id.to_string(),
+                        cost,
+                        benefit,
+                        notes,
+                    })
+                    .await?
+            }
+            Self::Db(ctx) => ctx.create_transaction(user_id, casino_id, cost, benefit, notes).await?,
+        })
+    }
+
+    /// Log a redemption.
+    ///
+    /// # Errors
+    /// Will return `Err` if the request or insert fails.
+    pub async fn redeem(
+        &self,
+        user_id: Uuid,
+        casino_id: Uuid,
+        amount: BigDecimal,
+        received_at: Option<chrono::NaiveDateTime>,
+    ) -> Result<Redemption, CliError> {
+        Ok(match self {
+            Self::Api(client) => {
+                client
+                    .create_redemption(user_id, casino_id, &RedemptionCreate { amount, received_at })
+                    .await?
+            }
+            Self::Db(ctx) => ctx.create_redemption(user_id, casino_id, amount, received_at).await?,
+        })
+    }
+
+    /// Log a daily bonus claim.
+    ///
+    /// # Errors
+    /// Will return `Err` if the request or insert fails.
+    pub async fn claim_bonus(
+        &self,
+        user_id: Uuid,
+        casino_id: Uuid,
+        amount_sc: BigDecimal,
+        amount_gc: BigDecimal,
+    ) -> Result<DailyBonus, CliError> {
+        Ok(match self {
+            Self::Api(client) => {
+                client
+                    .claim_bonus(user_id, casino_id, &DailyBonusCreate { amount_sc, amount_gc })
+                    .await?
+            }
+            Self::Db(ctx) => ctx.create_daily_bonus(user_id, casino_id, amount_sc, amount_gc).await?,
+        })
+    }
+
+    /// Spend and benefit per casino.
+    ///
+    /// # Errors
+    /// Will return `Err` if the request or query fails.
+    pub async fn summary(&self, user_id: Uuid) -> Result<Vec<CasinoSummary>, CliError> {
+        Ok(match self {
+            Self::Api(client) => client.summary(user_id).await?,
+            Self::Db(ctx) => ctx.get_casino_summaries(user_id).await?,
+        })
+    }
+
+    /// Import purchases from a CSV file.
+    ///
+    /// # Errors
+    /// Will return `Err` if the file is rejected as a whole or the request fails.
+    pub async fn import_transactions(
+        &self,
+        user_id: Uuid,
+        csv: String,
+        columns: &ColumnMapping,
+    ) -> Result<ImportReport, CliError> {
+        Ok(match self {
+            Self::Api(client) => client.import_transactions(user_id, csv, columns).await?,
+            Self::Db(ctx) => ctx.import_transactions(user_id, &csv, columns).await?,
+        })
+    }
+
+    /// Propose transactions of `user_id` for the email receipts in `files`.
+    ///
+    /// # Errors
+    /// Will return `Err` if the casinos or transactions can't be read.
+    pub async fn propose_email_transactions(
+        &self,
+        user_id: Uuid,
+        files: &[Vec<u8>],
+        window: chrono::Duration,
+    ) -> Result<EmailScan, CliError> {
+        Ok(match self {
+            Self::Api(client) => {
+                let (casinos, existing) = tokio::try_join!(client.casinos(), client.transactions(user_id))?;
+                crate::email::propose(files, user_id, &casinos, &existing, window)
+            }
+            Self::Db(ctx) => ctx.propose_email_transactions(user_id, files, window).await?,
+        })
+    }
+
+    /// Log confirmed email `proposals` of `user_id`. The API takes them as a
+    /// CSV import and reports rejected proposals by their line, the first
+    /// proposal is line 2; the database logs all of them or fails.
+    ///
+    /// # Errors
+    /// Will return `Err` if the request or insert fails.
+    pub async fn accept_proposals(&self, user_id: Uuid, proposals: &[Proposal]) -> Result<ImportReport, CliError> {
+        Ok(match self {
+            Self::Api(client) => {
+                let mut writer = csv::Writer::from_writer(vec![]);
+                let columns = ColumnMapping::default();
+                writer
+                    .write_record([&columns.casino, &columns.cost, &columns.benefit, &columns.date, &columns.notes])
+                    .map_err(ImportError::Csv)?;
+                for proposal in proposals {
+                    let t = &proposal.transaction;
+                    writer
+                        .write_record([
+                            proposal.casino_name.clone(),
+                            t.cost.to_string(),
+                            t.benefit.to_string(),
+                            t.created_at.and_utc().to_rfc3339(),
+                            t.notes.clone().unwrap_or_default(),
+                        ])
+                        .map_err(ImportError::Csv)?;
+                }
+                let csv = writer.into_inner().map_err(|e| ImportError::Csv(e.into_error().into()))?;
+                let csv = String::from_utf8_lossy(&csv).into_owned();
+                client.import_transactions(user_id, csv, &columns).await?
+            }
+            Self::Db(ctx) => {
+                let accepted = ctx.accept_proposals(user_id, proposals).await?;
+                ImportReport { accepted, duplicates: proposals.len() as u64 - accepted, ..ImportReport::default() }
+            }
+        })
+    }
+
+    /// Create a user to log for.
+    ///
+    /// # Errors
+    /// Will return `Err` if the request or insert fails.
+    pub async fn create_user(&self) -> Result<Uuid, CliError> {
+        Ok(match self {
+            Self::Api(client) => {
+                let user = crate::UserCreate { email: String::new(), username: String::new() };
+                client.create_user(&user).await?.id
+            }
+            Self::Db(ctx) => ctx.create_user().await?.id,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    backend_tests!(test_db_backend, test_api_proposals);
+
+    fn casino(name: &str, url: &str) -> Casino {
+        Casino {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            url: url.to_string(),
+            description: String::new(),
+            created_at: chrono::NaiveDateTime::default(),
+            updated_at: chrono::NaiveDateTime::default(),
+        }
+    }
+
+    #[test]
+    fn test_resolve_casino() {
+        let casinos = [
+            casino("Chumba Casino", "chumbacasino.com"),
+            casino("Pulsz", "https://www.pulsz.com/"),
+            casino("Pulsz Bingo", "pulszbingo.com"),
+        ];
+        assert_eq!("Chumba Casino", resolve_casino(&casinos, "chumba").unwrap().name);
+        assert_eq!("Chumba Casino", resolve_casino(&casinos, "CHUMBA CASINO").unwrap().name);
+        // An exact name wins over other names containing it.
+        assert_eq!("Pulsz", resolve_casino(&casinos, "pulsz").unwrap().name);
+        assert_eq!("Pulsz", resolve_casino(&casinos, "pulsz.com").unwrap().name);
+        assert_eq!("Pulsz Bingo", resolve_casino(&casinos, "https://pulszbingo.com").unwrap().name);
+        assert!(resolve_casino(&casinos, "casino x").is_err());
+        let err = resolve_casino(&[casino("Stake", ""), casino("Stake Plus", "")], "sta").unwrap_err();
+        assert_eq!("\"sta\" matches Stake, Stake Plus", err.to_string());
+        // A blank name matches nothing, not the casinos without a url or every name.
+        for blank in ["", "  "] {
+            let err = resolve_casino(&[casino("Stake", ""), casino("Pulsz", "")], blank).unwrap_err();
+            assert_eq!("no casino given", err.to_string());
+        }
+    }
+
+    #[test]
+    fn test_cli_config() {
+        let path = std::env::temp_dir().join(format!("casino-buddy-{}", Uuid::new_v4())).join("cli.toml");
+        assert_eq!(CliConfig::default(), CliConfig::read(&path).unwrap());
+        assert!(matches!(CliConfig::default().user(), Err(CliError::NoUser)));
+
+        let config = CliConfig {
+            server: Some("https://api.casinobuddy.app".to_string()),
+            user_id: Some(Uuid::new_v4()),
+            ..CliConfig::default()
+        };
+        config.write(&path).unwrap();
+        assert_eq!(config, CliConfig::read(&path).unwrap());
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        let merged = config.clone().merge(CliConfig { token: Some("t".to_string()), ..CliConfig::default() });
+        assert_eq!(config.server, merged.server);
+        assert_eq!(Some("t".to_string()), merged.token);
+        assert!(matches!(merged.backend(), Ok(Backend::Api(_))));
+    }
+
+    async fn test_db_backend(ctx: CasinoContext) -> sqlx::Result<()> {
+        let backend = Backend::Db(ctx);
+        let user_id = backend.create_user().await.unwrap();
+        let casino = backend.casino("test").await.unwrap();
+        backend
+            .buy(user_id, casino.id, BigDecimal::from(10), BigDecimal::from(15), None)
+            .await
+            .unwrap();
+        backend.redeem(user_id, casino.id, BigDecimal::from(50), None).await.unwrap();
+        backend
+            .claim_bonus(user_id, casino.id, BigDecimal::from(1), BigDecimal::from(0))
+            .await
+            .unwrap();
+        let summary = backend.summary(user_id).await.unwrap();
+        assert_eq!(1, summary.len());
+        assert_eq!(BigDecimal::from(15), summary[0].benefit);
+        Ok(())
+    }
+
+    async fn test_api_proposals(ctx: CasinoContext) -> sqlx::Result<()> {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let stop = std::sync::Arc::new(tokio::sync::Notify::new());
+        let signal = {
+            let stop = stop.clone();
+            async move { stop.notified().await }
+        };
+        let app = crate::get_app(&ctx).await;
+        let timeout = std::time::Duration::from_secs(1);
+        let server = tokio::spawn(crate::server::serve_with_shutdown(app, addr, signal, timeout));
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let backend = Backend::Api(Client::new(&format!("http://{addr}")).unwrap());
+
+        // Email proposals are logged over the API as a CSV import, the server
+        // rejects the one whose casino it doesn't know.
+        let user_id = backend.create_user().await.unwrap();
+        let receipt = chrono::NaiveDate::from_ymd_opt(2026, 3, 1).unwrap().and_hms_opt(14, 22, 10).unwrap();
+        let proposal = |casino_name: &str| Proposal {
+            transaction: Transaction {
+                id: Uuid::new_v4(),
+                user_id,
+                casino_id: Uuid::nil(),
+                cost: BigDecimal::from(20),
+                benefit: BigDecimal::from(25),
+                created_at: receipt,
+                updated_at: receipt,
+                notes: None,
+            },
+            casino_name: casino_name.to_string(),
+            subject: "Purchase Confirmation".to_string(),
+            duplicate_of: None,
+        };
+        let report = backend.accept_proposals(user_id, &[proposal("Test"), proposal("Nowhere")]).await.unwrap();
+        assert_eq!((1, 0, 1), (report.accepted, report.duplicates, report.rejected));
+        assert_eq!(3, report.rows.iter().find(|row| row.error.is_some()).unwrap().line);
+        let logged = ctx.get_transactions(user_id).await?;
+        assert_eq!(vec![receipt], logged.iter().map(|t| t.created_at).collect::<Vec<_>>());
+
+        stop.notify_one();
+        server.await.unwrap().unwrap();
+        Ok(())
+    }
+}
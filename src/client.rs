@@ -0,0 +1,589 @@
+//! Typed async client for the HTTP API, behind the `client` feature.
+//!
+//! The client speaks the `/v2` API with the same structs the server serializes,
+//! so a change to a request or reply body shows up as a compile error in the
+//! tools built on it rather than a broken script. Every `/v2` route, the
+//! health checks, metrics and the OpenAPI document have a method, the exports
+//! and metrics return the body as text.
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), casino_buddy::client::ClientError> {
+//! let client = casino_buddy::client::Client::new("http://localhost:3030")?;
+//! for casino in client.casinos().await? {
+//!     println!("{} {}", casino.name, casino.url);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::fmt::Display;
+
+use bigdecimal::BigDecimal;
+use reqwest::{Method, RequestBuilder, Response, StatusCode, Url};
+use serde::de::DeserializeOwned;
+use uuid::Uuid;
+
+use crate::export::{ExportFilter, ExportFormat, ExportKind};
+use crate::import::{ColumnMapping, ImportReport};
+use crate::importers::HistoryImportReport;
+use crate::journal::{JournalAccounts, JournalFormat};
+use crate::reconcile::{BankColumns, BankLink, BankLinkCreate, ReconcileReport};
+use crate::request_id::REQUEST_ID_HEADER;
+use crate::statement::StatementFormat;
+use crate::tax::{TaxFormat, TaxReport};
+use crate::{
+    CBUserId, Casino, CasinoListingReplyBody, CasinoSummary, DailyBonus, DailyBonusCreate, ExportQuery,
+    HistoryImportQuery, JournalQuery, LivenessReport, ReadinessReport, ReconcileQuery, Redemption, RedemptionCreate,
+    StatementQuery, SummaryReplyBody, TaxQuery, Transaction, TransactionCreate, TransactionsReplyBody, User,
+    UserCreate, UserReplyBody,
+};
+
+/// API version the client speaks.
+const API_VERSION: &str = "v2";
+
+/// Error from a [`Client`] call.
+#[derive(Debug)]
+pub enum ClientError {
+    /// The base url can't be used for requests.
+    Url(String),
+    /// The request couldn't be sent or the reply couldn't be decoded.
+    Http(reqwest::Error),
+    /// The server answered with an error.
+    Api {
+        status: StatusCode,
+        /// The error type from the body, like `BAD_REQUEST`.
+        error: String,
+        /// Why the request was rejected, when the server says.
+        message: Option<String>,
+        request_id: Option<String>,
+    },
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Url(url) => write!(f, "invalid base url {url:?}"),
+            Self::Http(e) => write!(f, "request failed: {e}"),
+            Self::Api { status, error, message, request_id } => {
+                write!(f, "{status} {error}")?;
+                if let Some(message) = message {
+                    write!(f, ": {message}")?;
+                }
+                match request_id {
+                    Some(id) => write!(f, " (request id {id})"),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Http(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Http(e)
+    }
+}
+
+/// Json body of an error reply, see [`crate::ErrorBody`].
+#[derive(serde::Deserialize)]
+struct ApiError {
+    error: String,
+    message: Option<String>,
+    request_id: Option<String>,
+}
+
+/// Client for one casino buddy server, cheap to clone.
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base: Url,
+    token: Option<String>,
+}
+
+impl Client {
+    /// A client for the server at `base_url`, like `http://localhost:3030`.
+    ///
+    /// # Errors
+    /// Will return `Err` if `base_url` isn't an absolute http(s) url.
+    pub fn new(base_url: &str) -> Result<Self, ClientError> {
+        Self::with_http_client(base_url, reqwest::Client::new())
+    }
+
+    /// Like [`Client::new`] with a configured [`reqwest::Client`], e.g. for timeouts.
+    ///
+    /// # Errors
+    /// Will return `Err` if `base_url` isn't an absolute http(s) url.
+    pub fn with_http_client(base_url: &str, http: reqwest::Client) -> Result<Self, ClientError> {
+        let base = Url::parse(base_url).map_err(|_| ClientError::Url(base_url.to_string()))?;
+        if !matches!(base.scheme(), "http" | "https") || base.cannot_be_a_base() {
+            return Err(ClientError::Url(base_url.to_string()));
+        }
+        Ok(Self { http, base, token: None })
+    }
+
+    /// Send `token` as a bearer token with every request.
+    #[must_use]
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    /// Liveness report, see `/health/live`.
+    ///
+    /// # Errors
+    /// Will return `Err` if the server can't be reached or answers with an error.
+    pub async fn liveness(&self) -> Result<LivenessReport, ClientError> {
+        self.send(self.request(Method::GET, &["health", "live"])).await
+    }
+
+    /// Readiness report, see `/health/ready`. An instance that isn't ready still
+    /// describes why, so this only fails when the server can't be reached.
+    ///
+    /// # Errors
+    /// Will return `Err` if the server can't be reached or the report can't be decoded.
+    pub async fn readiness(&self) -> Result<ReadinessReport, ClientError> {
+        let res = self.request(Method::GET, &["health", "ready"]).send().await?;
+        if res.status() == StatusCode::SERVICE_UNAVAILABLE {
+            return Ok(res.json().await?);
+        }
+        Self::decode(res).await
+    }
+
+    /// Create a user.
+    ///
+    /// # Errors
+    /// Will return `Err` if the server can't be reached or answers with an error.
+    pub async fn create_user(&self, user: &UserCreate) -> Result<CBUserId, ClientError> {
+        self.send(self.api(Method::POST, &["user"]).json(user)).await
+    }
+
+    /// Get a user, `None` when it doesn't exist.
+    ///
+    /// # Errors
+    /// Will return `Err` if the server can't be reached or answers with an error.
+    pub async fn user(&self, user_id: Uuid) -> Result<Option<User>, ClientError> {
+        let reply: UserReplyBody = self.send(self.api(Method::GET, &["user", &user_id.to_string()])).await?;
+        Ok(reply.body.into_iter().next())
+    }
+
+    /// Every casino.
+    ///
+    /// # Errors
+    /// Will return `Err` if the server can't be reached or answers with an error.
+    pub async fn casinos(&self) -> Result<Vec<Casino>, ClientError> {
+        let reply: CasinoListingReplyBody = self.send(self.api(Method::GET, &["casino"])).await?;
+        Ok(reply.casinos)
+    }
+
+    /// Every transaction of a user.
+    ///
+    /// # Errors
+    /// Will return `Err` if the server can't be reached or answers with an error.
+    pub async fn transactions(&self, user_id: Uuid) -> Result<Vec<Transaction>, ClientError> {
+        let reply: TransactionsReplyBody =
+            self.send(self.api(Method::GET, &["transaction", &user_id.to_string()])).await?;
+        Ok(reply.body)
+    }
+
+    /// Create a transaction for the user and casino named in `transaction`.
+    ///
+    /// # Errors
+    /// Will return `Err` if the server can't be reached or answers with an error.
+    pub async fn create_transaction(&self, transaction: &TransactionCreate) -> Result<Transaction, ClientError> {
+        let path = ["transaction", transaction.user_id.as_str(), transaction.casino_id.as_str()];
+        self.send(self.api(Method::POST, &path).json(transaction)).await
+    }
+
+    /// Log a redemption.
+    ///
+    /// # Errors
+    /// Will return `Err` if the server can't be reached or answers with an error.
+    pub async fn create_redemption(
+        &self,
+        user_id: Uuid,
+        casino_id: Uuid,
+        redemption: &RedemptionCreate,
+    ) -> Result<Redemption, ClientError> {
+        let path = ["redemption", &user_id.to_string(), &casino_id.to_string()];
+        self.send(self.api(Method::POST, &path).json(redemption)).await
+    }
+
+    /// Log a daily bonus claim.
+    ///
+    /// # Errors
+    /// Will return `Err` if the server can't be reached or answers with an error.
+    pub async fn claim_bonus(
+        &self,
+        user_id: Uuid,
+        casino_id: Uuid,
+        bonus: &DailyBonusCreate,
+    ) -> Result<DailyBonus, ClientError> {
+        let path = ["bonus", &user_id.to_string(), &casino_id.to_string()];
+        self.send(self.api(Method::POST, &path).json(bonus)).await
+    }
+
+    /// Spend and benefit of a user per casino.
+    ///
+    /// # Errors
+    /// Will return `Err` if the server can't be reached or answers with an error.
+    pub async fn summary(&self, user_id: Uuid) -> Result<Vec<CasinoSummary>, ClientError> {
+        let reply: SummaryReplyBody = self.send(self.api(Method::GET, &["summary", &user_id.to_string()])).await?;
+        Ok(reply.body)
+    }
+
+    /// Import the transactions of a user from a CSV file, see [`crate::import`].
+    ///
+    /// # Errors
+    /// Will return `Err` if the server can't be reached or rejects the file as a whole.
+    pub async fn import_transactions(
+        &self,
+        user_id: Uuid,
+        csv: String,
+        columns: &ColumnMapping,
+    ) -> Result<ImportReport, ClientError> {
+        let req = self
+            .api(Method::POST, &["import", "transactions"])
+            .query(&[("user_id", user_id.to_string())])
+            .query(columns)
+            .header(reqwest::header::CONTENT_TYPE, "text/csv")
+            .body(csv);
+        self.send(req).await
+    }
+
+    /// Import a casino's history export for a user, see [`crate::importers`].
+    /// The importer is detected from the file when `format` is `None`.
+    ///
+    /// # Errors
+    /// Will return `Err` if the server can't be reached or rejects the file as a whole.
+    pub async fn import_history(
+        &self,
+        user_id: Uuid,
+        contents: String,
+        format: Option<&str>,
+    ) -> Result<HistoryImportReport, ClientError> {
+        let query = HistoryImportQuery { user_id: user_id.to_string(), format: format.map(str::to_string) };
+        let req = self.api(Method::POST, &["import", "history"]).query(&query).body(contents);
+        self.send(req).await
+    }
+
+    /// The `kind` records of a user matching `filter`, as a CSV or json
+    /// document, see [`crate::export`].
+    ///
+    /// # Errors
+    /// Will return `Err` if the server can't be reached or answers with an error.
+    pub async fn export(
+        &self,
+        kind: ExportKind,
+        user_id: Uuid,
+        filter: ExportFilter,
+        format: ExportFormat,
+    ) -> Result<String, ClientError> {
+        let query = ExportQuery { user_id: user_id.to_string(), format, filter };
+        self.send_text(self.api(Method::GET, &["export", kind.name()]).query(&query)).await
+    }
+
+    /// The purchases and received redemptions of a user as a journal, see [`crate::journal`].
+    ///
+    /// # Errors
+    /// Will return `Err` if the server can't be reached or answers with an error.
+    pub async fn export_journal(
+        &self,
+        user_id: Uuid,
+        filter: ExportFilter,
+        format: JournalFormat,
+        accounts: JournalAccounts,
+    ) -> Result<String, ClientError> {
+        let query = JournalQuery { user_id: user_id.to_string(), format, accounts, filter };
+        self.send_text(self.api(Method::GET, &["export", "journal"]).query(&query)).await
+    }
+
+    /// The purchases and received redemptions of a user as an OFX or QIF
+    /// statement of `account`, see [`crate::statement`].
+    ///
+    /// # Errors
+    /// Will return `Err` if the server can't be reached or answers with an error.
+    pub async fn export_statement(
+        &self,
+        user_id: Uuid,
+        filter: ExportFilter,
+        format: StatementFormat,
+        account: &str,
+    ) -> Result<String, ClientError> {
+        let query = StatementQuery { user_id: user_id.to_string(), format, account: account.to_string(), filter };
+        self.send_text(self.api(Method::GET, &["export", "statement"]).query(&query)).await
+    }
+
+    /// Match a bank statement CSV with the records of a user, see [`crate::reconcile`].
+    ///
+    /// # Errors
+    /// Will return `Err` if the server can't be reached or rejects the file as a whole.
+    pub async fn reconcile(
+        &self,
+        user_id: Uuid,
+        csv: String,
+        columns: BankColumns,
+        window_days: u32,
+    ) -> Result<ReconcileReport, ClientError> {
+        let query = ReconcileQuery { user_id: user_id.to_string(), window_days, columns };
+        let req = self
+            .api(Method::POST, &["reconcile"])
+            .query(&query)
+            .header(reqwest::header::CONTENT_TYPE, "text/csv")
+            .body(csv);
+        self.send(req).await
+    }
+
+    /// Link a bank row to a transaction or redemption, so later uploads match them.
+    ///
+    /// # Errors
+    /// Will return `Err` if the server can't be reached or answers with an error,
+    /// a 404 when the record isn't one of the user's.
+    pub async fn link_bank_entry(&self, link: &BankLinkCreate) -> Result<BankLink, ClientError> {
+        self.send(self.api(Method::POST, &["reconcile", "link"]).json(link)).await
+    }
+
+    /// Net winnings per casino of a user in `year`, listing the redemptions of
+    /// at least `threshold`, see [`crate::tax`].
+    ///
+    /// # Errors
+    /// Will return `Err` if the server can't be reached or answers with an error.
+    pub async fn tax_report(&self, user_id: Uuid, year: i32, threshold: BigDecimal) -> Result<TaxReport, ClientError> {
+        let query = TaxQuery { user_id: user_id.to_string(), format: TaxFormat::Json, threshold };
+        self.send(self.api(Method::GET, &["report", "tax", &year.to_string()]).query(&query)).await
+    }
+
+    /// The Prometheus metrics of the server, see `/metrics`.
+    ///
+    /// # Errors
+    /// Will return `Err` if the server can't be reached or answers with an error.
+    pub async fn metrics(&self) -> Result<String, ClientError> {
+        self.send_text(self.request(Method::GET, &["metrics"])).await
+    }
+
+    /// The OpenAPI document of the server, see `/openapi.json`.
+    ///
+    /// # Errors
+    /// Will return `Err` if the server can't be reached or answers with an error.
+    pub async fn openapi(&self) -> Result<utoipa::openapi::OpenApi, ClientError> {
+        self.send(self.request(Method::GET, &["openapi.json"])).await
+    }
+
+    /// A request to a versioned API route.
+    fn api(&self, method: Method, segments: &[&str]) -> RequestBuilder {
+        let mut path = vec![API_VERSION];
+        path.extend_from_slice(segments);
+        self.request(method, &path)
+    }
+
+    /// A request to `segments` below the base url, each segment is escaped.
+    fn request(&self, method: Method, segments: &[&str]) -> RequestBuilder {
+        let mut url = self.base.clone();
+        // Checked to be a base in the constructor.
+        if let Ok(mut path) = url.path_segments_mut() {
+            path.pop_if_empty().extend(segments);
+        }
+        let req = self.http.request(method, url);
+        match &self.token {
+            Some(token) => req.bearer_auth(token),
+            None => req,
+        }
+    }
+
+    async fn send<T: DeserializeOwned>(&self, req: RequestBuilder) -> Result<T, ClientError> {
+        Self::decode(req.send().await?).await
+    }
+
+    /// Send a request whose reply body is text rather than json.
+    async fn send_text(&self, req: RequestBuilder) -> Result<String, ClientError> {
+        let res = req.send().await?;
+        if res.status().is_success() {
+            return Ok(res.text().await?);
+        }
+        Err(Self::error(res).await)
+    }
+
+    /// Decode a successful reply, or turn an error reply into [`ClientError::Api`].
+    async fn decode<T: DeserializeOwned>(res: Response) -> Result<T, ClientError> {
+        if res.status().is_success() {
+            return Ok(res.json().await?);
+        }
+        Err(Self::error(res).await)
+    }
+
+    /// The [`ClientError::Api`] of an error reply.
+    async fn error(res: Response) -> ClientError {
+        let status = res.status();
+        let header_id = res
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|id| id.to_str().ok())
+            .map(str::to_string);
+        let body = match res.bytes().await {
+            Ok(body) => body,
+            Err(e) => return ClientError::Http(e),
+        };
+        let (error, message, request_id) = match serde_json::from_slice::<ApiError>(&body) {
+            Ok(body) => (body.error, body.message, body.request_id.or(header_id)),
+            Err(_) => (String::from_utf8_lossy(&body).into_owned(), None, header_id),
+        };
+        ClientError::Api { status, error, message, request_id }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reconcile::RecordKind;
+    use crate::server::serve_with_shutdown;
+    use crate::{CasinoContext, HealthStatus};
+    use bigdecimal::BigDecimal;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::Notify;
+
+    backend_tests!(test_client);
+
+    #[test]
+    fn test_client_urls() {
+        assert!(Client::new("localhost:3030").is_err());
+        assert!(Client::new("ftp://localhost").is_err());
+        let client = Client::new("http://localhost:3030/api/").unwrap();
+        let req = client.api(Method::GET, &["user", "a/b"]).build().unwrap();
+        assert_eq!("http://localhost:3030/api/v2/user/a%2Fb", req.url().as_str());
+    }
+
+    async fn test_client(ctx: CasinoContext) -> sqlx::Result<()> {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let stop = Arc::new(Notify::new());
+        let signal = {
+            let stop = stop.clone();
+            async move { stop.notified().await }
+        };
+        let app = crate::get_app(&ctx).await;
+        let server = tokio::spawn(serve_with_shutdown(app, addr, signal, Duration::from_secs(1)));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let client = Client::new(&format!("http://{addr}")).unwrap();
+
+        assert_eq!(HealthStatus::Ok, client.liveness().await.unwrap().status);
+        assert_eq!(HealthStatus::Ok, client.readiness().await.unwrap().status);
+
+        let casinos = client.casinos().await.unwrap();
+        let casino = casinos.iter().find(|casino| casino.id.is_nil()).unwrap();
+
+        let user = client
+            .create_user(&UserCreate { email: "test@example.com".to_string(), username: "test".to_string() })
+            .await
+            .unwrap();
+        assert_eq!(Some(user.id), client.user(user.id).await.unwrap().map(|user| user.id));
+        assert_eq!(None, client.user(Uuid::new_v4()).await.unwrap());
+        assert!(client.transactions(user.id).await.unwrap().is_empty());
+
+        let transaction = client
+            .create_transaction(&TransactionCreate {
+                user_id: user.id.to_string(),
+                casino_id: casino.id.to_string(),
+                cost: BigDecimal::from(20),
+                benefit: BigDecimal::from(25),
+                notes: Some("client".to_string()),
+            })
+            .await
+            .unwrap();
+        assert_eq!(vec![transaction.clone()], client.transactions(user.id).await.unwrap());
+
+        let redemption = client
+            .create_redemption(
+                user.id,
+                casino.id,
+                &RedemptionCreate { amount: BigDecimal::from(100), received_at: None },
+            )
+            .await
+            .unwrap();
+        assert_eq!(BigDecimal::from(100), redemption.amount);
+
+        let bonus = client
+            .claim_bonus(user.id, casino.id, &DailyBonusCreate { amount_sc: BigDecimal::from(1), amount_gc: BigDecimal::from(0) })
+            .await
+            .unwrap();
+        assert_eq!(BigDecimal::from(1), bonus.amount_sc);
+        let summary = client.summary(user.id).await.unwrap();
+        assert_eq!(1, summary.len());
+        assert_eq!(BigDecimal::from(20), summary[0].spend);
+
+        let transactions = client
+            .export(ExportKind::Transactions, user.id, ExportFilter::default(), ExportFormat::Csv)
+            .await
+            .unwrap();
+        assert!(transactions.contains(&transaction.id.to_string()));
+        let journal = client
+            .export_journal(user.id, ExportFilter::default(), JournalFormat::Ledger, JournalAccounts::default())
+            .await
+            .unwrap();
+        assert!(journal.contains(&format!("; id: {}", transaction.id)));
+        let statement =
+            client.export_statement(user.id, ExportFilter::default(), StatementFormat::Ofx, "casino-buddy").await.unwrap();
+        assert!(statement.contains("<ACCTID>casino-buddy"));
+        let year = chrono::Datelike::year(&transaction.created_at);
+        let report = client.tax_report(user.id, year, BigDecimal::from(600)).await.unwrap();
+        assert_eq!((year, BigDecimal::from(20)), (report.year, report.totals.purchases));
+
+        let csv = format!("date,description,amount\n{},TEST CASINO,-20\n", transaction.created_at.date());
+        let report = client.reconcile(user.id, csv, BankColumns::default(), 1).await.unwrap();
+        assert_eq!(vec![transaction.id], report.matched.iter().map(|m| m.record.id).collect::<Vec<_>>());
+        let link = BankLinkCreate {
+            user_id: user.id.to_string(),
+            kind: RecordKind::Redemption,
+            record_id: redemption.id.to_string(),
+            date: transaction.created_at.date(),
+            amount: BigDecimal::from(100),
+            description: "TEST CASINO".to_string(),
+        };
+        assert_eq!(redemption.id, client.link_bank_entry(&link).await.unwrap().record_id);
+        let err = client
+            .link_bank_entry(&BankLinkCreate { record_id: Uuid::new_v4().to_string(), ..link })
+            .await
+            .unwrap_err();
+        assert!(matches!(err, ClientError::Api { status: StatusCode::NOT_FOUND, .. }));
+
+        ctx.create_casino("Stake.us", "https://stake.us", "").await?;
+        let history = include_str!("../test_fixtures/importers/stake.json").to_string();
+        let report = client.import_history(user.id, history, Some("stake")).await.unwrap();
+        assert_eq!((2, 2), (report.transactions.accepted, report.redemptions.accepted));
+
+        assert!(client.metrics().await.unwrap().contains("http_requests_total"));
+        let spec = client.openapi().await.unwrap();
+        assert!(spec.paths.paths.contains_key("/v2/reconcile/link"));
+
+        // Error replies keep the error type and request id.
+        let err = client
+            .create_transaction(&TransactionCreate {
+                user_id: "not-a-uuid".to_string(),
+                casino_id: casino.id.to_string(),
+                cost: BigDecimal::from(1),
+                benefit: BigDecimal::from(1),
+                notes: None,
+            })
+            .await
+            .unwrap_err();
+        match err {
+            ClientError::Api { status, error, request_id, .. } => {
+                assert_eq!(StatusCode::BAD_REQUEST, status);
+                assert_eq!("BAD_REQUEST", error);
+                assert!(request_id.is_some());
+            }
+            err => panic!("unexpected error {err}"),
+        }
+
+        stop.notify_one();
+        server.await.unwrap().unwrap();
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+//! CORS for browser clients like the casinobuddy.app front end.
+//!
+//! CORS is off unless `cors_origins` is set. When on, preflight requests are
+//! answered before any route or rate limit runs, and every response, errors
+//! included, carries the CORS headers.
+
+use warp::http::{header::HeaderName, Method};
+
+use crate::request_id::REQUEST_ID_HEADER;
+use crate::versioning::DEPRECATION_HEADER;
+use crate::{Config, ConfigError};
+
+/// Headers browsers may read from our responses.
+const EXPOSED_HEADERS: &[&str] = &[REQUEST_ID_HEADER, "retry-after", DEPRECATION_HEADER, "link"];
+
+/// Preflight responses are cached by browsers for this many seconds.
+const MAX_AGE: u32 = 60 * 60;
+
+/// Resolved CORS settings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorsConfig {
+    /// Allowed origins, `*` allows any origin.
+    pub origins: Vec<String>,
+    pub methods: Vec<String>,
+    pub headers: Vec<String>,
+    pub credentials: bool,
+}
+
+impl CorsConfig {
+    /// The CORS settings from `config`, `None` when no origins are allowed.
+    #[must_use]
+    pub fn from_config(config: &Config) -> Option<Self> {
+        if config.cors_origins.is_empty() {
+            return None;
+        }
+        Some(Self {
+            origins: config.cors_origins.clone(),
+            methods: config.cors_methods.clone(),
+            headers: config.cors_headers.clone(),
+            credentials: config.cors_credentials,
+        })
+    }
+
+    /// Check the values can be turned into a warp CORS filter, which panics on invalid ones.
+    ///
+    /// # Errors
+    /// Will return `Err` describing the first invalid value.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let any_origin = self.origins.iter().any(|origin| origin == "*");
+        if any_origin && self.credentials {
+            return Err(ConfigError::Invalid(
+                "cors_credentials can't be used with the `*` cors origin".to_string(),
+            ));
+        }
+        for origin in self.origins.iter().filter(|origin| *origin != "*") {
+            let valid = origin
+                .split_once("://")
+                .is_some_and(|(scheme, host)| {
+                    matches!(scheme, "http" | "https") && !host.is_empty() && !host.contains('/')
+                });
+            if !valid {
+                return Err(ConfigError::Invalid(format!(
+                    "cors origin {origin:?} must look like `https://example.com`"
+                )));
+            }
+        }
+        for method in &self.methods {
+            if Method::from_bytes(method.as_bytes()).is_err() {
+                return Err(ConfigError::Invalid(format!("cors method {method:?} is invalid")));
+            }
+        }
+        for header in &self.headers {
+            if HeaderName::from_bytes(header.as_bytes()).is_err() {
+                return Err(ConfigError::Invalid(format!("cors header {header:?} is invalid")));
+            }
+        }
+        Ok(())
+    }
+
+    /// Build the warp CORS filter, the config must have been validated.
+    #[must_use]
+    pub fn build(&self) -> warp::cors::Builder {
+        let mut cors = warp::cors()
+            .allow_methods(self.methods.iter().map(String::as_str))
+            .allow_headers(self.headers.iter().map(String::as_str))
+            .expose_headers(EXPOSED_HEADERS.iter().copied())
+            .allow_credentials(self.credentials)
+            .max_age(MAX_AGE);
+        if self.origins.iter().any(|origin| origin == "*") {
+            cors = cors.allow_any_origin();
+        } else {
+            cors = cors.allow_origins(self.origins.iter().map(String::as_str));
+        }
+        cors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CasinoContext;
+    use warp::http::StatusCode;
+
+    backend_tests!(test_req_cors);
+
+    fn cors(origins: &[&str]) -> CorsConfig {
+        CorsConfig {
+            origins: origins.iter().map(ToString::to_string).collect(),
+            ..CorsConfig::from_config(&Config { cors_origins: vec!["*".to_string()], ..Config::default() }).unwrap()
+        }
+    }
+
+    #[test]
+    fn test_cors_validation() {
+        assert!(CorsConfig::from_config(&Config::default()).is_none());
+        assert!(cors(&["https://casinobuddy.app", "http://localhost:5173"]).validate().is_ok());
+        assert!(cors(&["*"]).validate().is_ok());
+        assert!(cors(&["casinobuddy.app"]).validate().is_err());
+        assert!(cors(&["https://casinobuddy.app/path"]).validate().is_err());
+        assert!(CorsConfig { credentials: true, ..cors(&["*"]) }.validate().is_err());
+        assert!(CorsConfig { methods: vec!["NOT A METHOD".to_string()], ..cors(&["*"]) }.validate().is_err());
+    }
+
+    async fn test_req_cors(mut ctx: CasinoContext) -> sqlx::Result<()> {
+        ctx.cors = Some(cors(&["https://casinobuddy.app"]));
+        let app = crate::get_app(&ctx).await;
+
+        // Preflights are answered instead of falling through to a 404.
+        let res = warp::test::request()
+            .method("OPTIONS")
+            .path("/transaction/d61b6bba-61ba-4cab-b8b7-74a880968ec6/00000000-0000-0000-0000-000000000000")
+            .header("origin", "https://casinobuddy.app")
+            .header("access-control-request-method", "POST")
+            .header("access-control-request-headers", "content-type")
+            .reply(&app)
+            .await;
+        assert_eq!(StatusCode::OK, res.status());
+        assert_eq!("https://casinobuddy.app", res.headers()["access-control-allow-origin"]);
+
+        // Error responses carry the headers so the front end can read them.
+        let res = warp::test::request()
+            .path("/nope")
+            .header("origin", "https://casinobuddy.app")
+            .reply(&app)
+            .await;
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+        assert_eq!("https://casinobuddy.app", res.headers()["access-control-allow-origin"]);
+
+        let res = warp::test::request()
+            .path("/casino")
+            .header("origin", "https://evil.example")
+            .reply(&app)
+            .await;
+        assert_eq!(StatusCode::FORBIDDEN, res.status());
+        let body: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!("FORBIDDEN", body["error"]);
+        Ok(())
+    }
+}
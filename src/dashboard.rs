@@ -0,0 +1,152 @@
+//! Queries behind the terminal dashboard: what a user spent, what they are owed
+//! and which daily bonuses they haven't claimed yet.
+
+use chrono::{NaiveDate, NaiveDateTime};
+use uuid::Uuid;
+
+use crate::{Casino, CasinoContext, CasinoSummary, Db, Redemption, Transaction};
+
+/// How many recent transactions the dashboard shows.
+pub const RECENT_TRANSACTIONS: i64 = 10;
+
+/// Everything the dashboard shows for a user.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Dashboard {
+    pub summaries:              Vec<CasinoSummary>,
+    pub pending_redemptions:    Vec<Redemption>,
+    /// Casinos the user plays at without a bonus claimed on the day.
+    pub unclaimed_bonuses:      Vec<Casino>,
+    pub recent_transactions:    Vec<Transaction>,
+}
+
+/// The start of `day` and of the day after.
+fn day_bounds(day: NaiveDate) -> (NaiveDateTime, NaiveDateTime) {
+    let start = day.and_time(chrono::NaiveTime::MIN);
+    (start, start + chrono::Duration::days(1))
+}
+
+/// Dashboard queries for [`CasinoContext`]
+impl CasinoContext {
+    /// Load the dashboard of a user for `day`.
+    ///
+    /// # Errors
+    /// Will return `Err` if any of the queries fail.
+    pub async fn dashboard(&self, user_id: Uuid, day: NaiveDate) -> Result<Dashboard, sqlx::Error> {
+        let (summaries, pending_redemptions, unclaimed_bonuses, recent_transactions) = tokio::try_join!(
+            self.get_casino_summaries(user_id),
+            self.get_pending_redemptions(user_id),
+            self.get_unclaimed_bonuses(user_id, day),
+            self.get_recent_transactions(user_id, RECENT_TRANSACTIONS),
+        )?;
+        Ok(Dashboard { summaries, pending_redemptions, unclaimed_bonuses, recent_transactions })
+    }
+
+    /// Get the redemptions of a user that haven't been received, oldest first.
+    ///
+    /// # Errors
+    /// Will return `Err` if the query fails.
+    #[tracing::instrument(skip(self), fields(db.system = self.db.backend()), err)]
+    pub async fn get_pending_redemptions(&self, user_id: Uuid) -> Result<Vec<Redemption>, sqlx::Error> {
+        match &*self.db {
+            Db::Postgres(pool) => sqlx::query_as!(
+                    Redemption,
+                    r#"SELECT * FROM redemption WHERE user_id = $1 AND received_at IS NULL ORDER BY created_at"#,
+                    user_id
+                )
+                .fetch_all(pool)
+                .await,
+            #[cfg(feature = "sqlite")]
+            Db::Sqlite(pool) => crate::sqlite::get_pending_redemptions(pool, user_id).await,
+        }
+    }
+
+    /// Get the casinos a user has an account or transactions with and no daily bonus claimed on `day`.
+    ///
+    /// # Errors
+    /// Will return `Err` if the query fails.
+    #[tracing::instrument(skip(self), fields(db.system = self.db.backend()), err)]
+    pub async fn get_unclaimed_bonuses(&self, user_id: Uuid, day: NaiveDate) -> Result<Vec<Casino>, sqlx::Error> {
+        let (start, end) = day_bounds(day);
+        match &*self.db {
+            Db::Postgres(pool) => sqlx::query_as!(
+                    Casino,
+                    r#"SELECT * FROM casino c
+                    WHERE (
+                        c.id IN (SELECT casino_id FROM user_casino WHERE user_id = $1 AND NOT is_self_excluded)
+                        OR c.id IN (SELECT casino_id FROM "transaction" WHERE user_id = $1)
+                    )
+                    AND NOT EXISTS (
+                        SELECT 1 FROM daily_bonus b
+                        WHERE b.user_id = $1 AND b.casino_id = c.id AND b.created_at >= $2 AND b.created_at < $3
+                    )
+                    ORDER BY c.name"#,
+                    user_id,
+                    start,
+                    end
+                )
+                .fetch_all(pool)
+                .await,
+            #[cfg(feature = "sqlite")]
+            Db::Sqlite(pool) => crate::sqlite::get_unclaimed_bonuses(pool, user_id, start, end).await,
+        }
+    }
+
+    /// Get the latest `limit` transactions of a user, newest first.
+    ///
+    /// # Errors
+    /// Will return `Err` if the query fails.
+    #[tracing::instrument(skip(self), fields(db.system = self.db.backend()), err)]
+    pub async fn get_recent_transactions(&self, user_id: Uuid, limit: i64) -> Result<Vec<Transaction>, sqlx::Error> {
+        match &*self.db {
+            Db::Postgres(pool) => sqlx::query_as!(
+                    Transaction,
+                    r#"SELECT * FROM "transaction" WHERE user_id = $1 ORDER BY created_at DESC LIMIT $2"#,
+                    user_id,
+                    limit
+                )
+                .fetch_all(pool)
+                .await,
+            #[cfg(feature = "sqlite")]
+            Db::Sqlite(pool) => crate::sqlite::get_recent_transactions(pool, user_id, limit).await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bigdecimal::BigDecimal;
+
+    backend_tests!(test_dashboard);
+
+    async fn test_dashboard(ctx: CasinoContext) -> sqlx::Result<()> {
+        let user_id = Uuid::parse_str("d61b6bba-61ba-4cab-b8b7-74a880968ec6").unwrap();
+        let today = chrono::Utc::now().date_naive();
+
+        let dashboard = ctx.dashboard(user_id, today).await?;
+        assert_eq!(1, dashboard.summaries.len());
+        assert!(dashboard.pending_redemptions.is_empty());
+        // The fixture transaction makes the test casino one the user plays at.
+        assert_eq!(vec![Uuid::nil()], dashboard.unclaimed_bonuses.iter().map(|c| c.id).collect::<Vec<_>>());
+        assert_eq!(1, dashboard.recent_transactions.len());
+
+        let pending = ctx.create_redemption(user_id, Uuid::nil(), BigDecimal::from(100), None).await?;
+        let received = chrono::Utc::now().naive_utc();
+        ctx.create_redemption(user_id, Uuid::nil(), BigDecimal::from(50), Some(received)).await?;
+        ctx.create_daily_bonus(user_id, Uuid::nil(), BigDecimal::from(1), BigDecimal::from(0)).await?;
+        for cost in 1..=RECENT_TRANSACTIONS {
+            ctx.create_transaction(user_id, Uuid::nil(), BigDecimal::from(cost), BigDecimal::from(cost), None)
+                .await?;
+        }
+
+        let dashboard = ctx.dashboard(user_id, today).await?;
+        assert_eq!(vec![pending], dashboard.pending_redemptions);
+        assert!(dashboard.unclaimed_bonuses.is_empty());
+        assert_eq!(RECENT_TRANSACTIONS as usize, dashboard.recent_transactions.len());
+        assert!(dashboard.recent_transactions.iter().all(|t| t.notes.is_none()));
+        // Today's claim doesn't count for tomorrow.
+        let tomorrow = today.succ_opt().unwrap();
+        assert_eq!(1, ctx.get_unclaimed_bonuses(user_id, tomorrow).await?.len());
+        Ok(())
+    }
+}
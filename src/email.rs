@@ -0,0 +1,337 @@
+//! Purchase confirmations read from email receipts.
+//!
+//! Receipts are `.eml` files or mbox archives. Each [`EmailRule`] recognises a
+//! casino's receipts by sender and subject and extracts the amount paid and the
+//! sweeps coins received, the purchase time is the `Date` header. Receipts are
+//! turned into [`Proposal`]s to confirm before anything is logged. A proposal
+//! with the same casino and cost as an existing transaction, or an earlier
+//! receipt, within the dedup window is marked as a duplicate of it, since
+//! purchases logged by hand rarely have the exact receipt time.
+
+use std::sync::LazyLock;
+
+use bigdecimal::BigDecimal;
+use chrono::{Duration, NaiveDateTime};
+use mail_parser::mailbox::mbox::MessageIterator;
+use mail_parser::MessageParser;
+use regex::Regex;
+use uuid::Uuid;
+
+use crate::import::{find_casino, parse_amount, ImportRecords};
+use crate::{Casino, CasinoContext, Transaction};
+
+/// Hours around a receipt in which a transaction with the same casino and cost is a duplicate.
+pub const DEFAULT_DEDUP_WINDOW_HOURS: i64 = 6;
+
+/// How to read one casino's purchase receipts.
+#[derive(Debug, Clone, Copy)]
+pub struct EmailRule {
+    /// Url of the casino, matched against the casino table.
+    pub casino_url:     &'static str,
+    /// Receipts come from this domain or a subdomain of it.
+    pub sender_domain:  &'static str,
+    /// Receipts have this in the subject, case insensitive.
+    pub subject:        &'static str,
+    /// Regex capturing the amount paid in its first group.
+    pub paid:           &'static str,
+    /// Regex capturing the sweeps coins received in its first group.
+    pub coins:          &'static str,
+}
+
+/// Receipt rules of the supported casinos.
+pub static EMAIL_RULES: &[EmailRule] = &[
+    EmailRule {
+        casino_url: "chumbacasino.com",
+        sender_domain: "chumbacasino.com",
+        subject: "purchase confirmation",
+        paid: r"(?i)total paid:\s*\$?([\d,]+(?:\.\d+)?)",
+        coins: r"(?i)([\d,]+(?:\.\d+)?)\s+(?:free\s+)?sweeps coins",
+    },
+    EmailRule {
+        casino_url: "pulsz.com",
+        sender_domain: "pulsz.com",
+        subject: "receipt",
+        paid: r"(?i)amount charged:\s*\$?([\d,]+(?:\.\d+)?)",
+        coins: r"(?i)sweepstakes coins:\s*([\d,]+(?:\.\d+)?)",
+    },
+    EmailRule {
+        casino_url: "stake.us",
+        sender_domain: "stake.us",
+        subject: "purchase",
+        paid: r"(?i)price:\s*\$?([\d,]+(?:\.\d+)?)",
+        coins: r"(?i)\+\s*([\d,]+(?:\.\d+)?)\s*(?:sc|stake cash)\b",
+    },
+    EmailRule {
+        casino_url: "wowvegas.com",
+        sender_domain: "wowvegas.com",
+        subject: "order confirmation",
+        paid: r"(?i)order total:\s*\$?([\d,]+(?:\.\d+)?)",
+        coins: r"(?i)([\d,]+(?:\.\d+)?)\s+sweepstakes coins",
+    },
+];
+
+/// [`EMAIL_RULES`] with their regexes compiled, the patterns are fixed so they compile.
+static COMPILED_RULES: LazyLock<Vec<(&EmailRule, Regex, Regex)>> = LazyLock::new(|| {
+    EMAIL_RULES
+        .iter()
+        .map(|rule| (rule, Regex::new(rule.paid).unwrap(), Regex::new(rule.coins).unwrap()))
+        .collect()
+});
+
+/// A purchase read from a receipt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmailPurchase {
+    pub casino_url: &'static str,
+    pub cost:       BigDecimal,
+    pub benefit:    BigDecimal,
+    pub created_at: NaiveDateTime,
+    pub subject:    String,
+}
+
+/// A transaction read from a receipt, waiting for confirmation.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Proposal {
+    pub transaction:    Transaction,
+    pub casino_name:    String,
+    /// Subject of the receipt.
+    pub subject:        String,
+    /// The existing transaction, or earlier proposal, this one duplicates.
+    pub duplicate_of:   Option<Uuid>,
+}
+
+/// Everything found in a set of email files.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct EmailScan {
+    pub proposals:  Vec<Proposal>,
+    /// Receipts matching a rule that couldn't be read, with the reason.
+    pub unreadable: Vec<String>,
+    /// Messages that aren't receipts of a supported casino.
+    pub ignored:    usize,
+}
+
+/// Split an email file into messages, an mbox when it starts with a `From ` line.
+#[must_use]
+pub fn split_messages(raw: &[u8]) -> Vec<Vec<u8>> {
+    if raw.starts_with(b"From ") {
+        MessageIterator::new(raw).filter_map(Result::ok).map(|message| message.unwrap_contents()).collect()
+    } else {
+        vec![raw.to_vec()]
+    }
+}
+
+/// Read the purchase from a receipt. `Ok(None)` when no rule matches the
+/// message, `Err` when one does but the receipt can't be read.
+///
+/// # Errors
+/// Will return `Err` naming what's missing from a matching receipt.
+pub fn extract(raw: &[u8]) -> Result<Option<EmailPurchase>, String> {
+    let Some(message) = MessageParser::default().parse(raw) else { return Ok(None) };
+    let subject = message.subject().unwrap_or_default().to_string();
+    let sender = message
+        .from()
+        .and_then(|from| from.first())
+        .and_then(|addr| addr.address())
+        .unwrap_or_default()
+        .to_lowercase();
+    let domain = sender.rsplit_once('@').map_or("", |(_, domain)| domain);
+    let Some((rule, paid, coins)) = COMPILED_RULES.iter().find(|(rule, _, _)| {
+        (domain == rule.sender_domain || domain.ends_with(&format!(".{}", rule.sender_domain)))
+            && subject.to_lowercase().contains(rule.subject)
+    }) else {
+        return Ok(None);
+    };
+
+    let body = message.body_text(0).unwrap_or_default();
+    let capture = |regex: &Regex, what: &str| {
+        let value = regex.captures(&body).and_then(|c| c.get(1)).ok_or_else(|| format!("{subject:?} has no {what}"))?;
+        parse_amount(what, value.as_str())
+    };
+    let created_at = message
+        .date()
+        .and_then(|date| chrono::DateTime::from_timestamp(date.to_timestamp(), 0))
+        .ok_or_else(|| format!("{subject:?} has no valid Date header"))?
+        .naive_utc();
+    Ok(Some(EmailPurchase {
+        casino_url: rule.casino_url,
+        cost: capture(paid, "amount paid")?,
+        benefit: capture(coins, "sweeps coins")?,
+        created_at,
+        subject,
+    }))
+}
+
+/// Propose transactions of `user_id` for the receipts in `files`, checking for
+/// duplicates among the user's `existing` transactions and the earlier receipts.
+#[must_use]
+pub fn propose(
+    files: &[Vec<u8>],
+    user_id: Uuid,
+    casinos: &[Casino],
+    existing: &[Transaction],
+    window: Duration,
+) -> EmailScan {
+    let mut scan = EmailScan::default();
+    let mut known: Vec<Transaction> = existing.to_vec();
+    for raw in files.iter().flat_map(|file| split_messages(file)) {
+        let purchase = match extract(&raw) {
+            Ok(Some(purchase)) => purchase,
+            Ok(None) => {
+                scan.ignored += 1;
+                continue;
+            }
+            Err(e) => {
+                scan.unreadable.push(e);
+                continue;
+            }
+        };
+        let Some(casino) = find_casino(casinos, purchase.casino_url) else {
+            scan.unreadable.push(format!("{:?}: no casino with url {}", purchase.subject, purchase.casino_url));
+            continue;
+        };
+        let duplicate_of = known
+            .iter()
+            .find(|t| {
+                t.casino_id == casino.id
+                    && t.cost == purchase.cost
+                    && (t.created_at - purchase.created_at).abs() <= window
+            })
+            .map(|t| t.id);
+        let transaction = Transaction {
+            id: Uuid::new_v4(),
+            user_id,
+            casino_id: casino.id,
+            cost: purchase.cost,
+            benefit: purchase.benefit,
+            created_at: purchase.created_at,
+            updated_at: chrono::Utc::now().naive_utc(),
+            notes: Some(format!("email: {}", purchase.subject)),
+        };
+        if duplicate_of.is_none() {
+            known.push(transaction.clone());
+        }
+        scan.proposals.push(Proposal {
+            transaction,
+            casino_name: casino.name.clone(),
+            subject: purchase.subject,
+            duplicate_of,
+        });
+    }
+    scan
+}
+
+/// Email receipt imports for [`CasinoContext`]
+impl CasinoContext {
+    /// Propose transactions of `user_id` for the receipts in `files`, see [`propose`].
+    ///
+    /// # Errors
+    /// Will return `Err` if the casinos or transactions can't be read.
+    #[tracing::instrument(skip(self, files), err)]
+    pub async fn propose_email_transactions(
+        &self,
+        user_id: Uuid,
+        files: &[Vec<u8>],
+        window: Duration,
+    ) -> Result<EmailScan, sqlx::Error> {
+        let casinos = self.get_all_casinos().await?;
+        let existing = self.get_transactions(user_id).await?;
+        Ok(propose(files, user_id, &casinos, &existing, window))
+    }
+
+    /// Log the confirmed `proposals` of `user_id` in a single database
+    /// transaction, returning how many were inserted.
+    ///
+    /// # Errors
+    /// Will return `Err` if the insert fails, nothing is logged in that case.
+    #[tracing::instrument(skip(self, proposals), err)]
+    pub async fn accept_proposals(&self, user_id: Uuid, proposals: &[Proposal]) -> Result<u64, sqlx::Error> {
+        let records = ImportRecords {
+            transactions: proposals.iter().map(|proposal| proposal.transaction.clone()).collect(),
+            redemptions: vec![],
+        };
+        let duplicates = self.insert_records(user_id, &records).await?;
+        Ok(duplicates.transactions.iter().filter(|duplicate| duplicate.is_none()).count() as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const CHUMBA: &[u8] = include_bytes!("../test_fixtures/email/chumba_receipt.eml");
+    const PULSZ: &[u8] = include_bytes!("../test_fixtures/email/pulsz_receipt.eml");
+    const NEWSLETTER: &[u8] = include_bytes!("../test_fixtures/email/newsletter.eml");
+    const MBOX: &[u8] = include_bytes!("../test_fixtures/email/receipts.mbox");
+
+    backend_tests!(test_email_proposals);
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    #[test]
+    fn test_extract() {
+        let chumba = extract(CHUMBA).unwrap().unwrap();
+        assert_eq!("chumbacasino.com", chumba.casino_url);
+        assert_eq!((decimal("19.99"), decimal("20")), (chumba.cost, chumba.benefit));
+        assert_eq!("2026-03-01T14:22:10", chumba.created_at.format("%FT%T").to_string());
+
+        // Html only receipts are read as text, the time is converted to UTC.
+        let pulsz = extract(PULSZ).unwrap().unwrap();
+        assert_eq!((decimal("29.99"), decimal("30")), (pulsz.cost, pulsz.benefit));
+        assert_eq!("2026-02-11T19:03:44", pulsz.created_at.format("%FT%T").to_string());
+
+        assert_eq!(Ok(None), extract(NEWSLETTER));
+        let broken = String::from_utf8_lossy(CHUMBA).replace("Total paid", "Total");
+        assert!(extract(broken.as_bytes()).unwrap_err().contains("amount paid"));
+
+        assert_eq!(1, split_messages(CHUMBA).len());
+        assert_eq!(3, split_messages(MBOX).len());
+    }
+
+    #[test]
+    fn test_rules_compile() {
+        assert_eq!(EMAIL_RULES.len(), COMPILED_RULES.len());
+    }
+
+    async fn test_email_proposals(ctx: CasinoContext) -> sqlx::Result<()> {
+        let user_id = ctx.create_user().await?.id;
+        let chumba = ctx.create_casino("Chumba Casino", "https://www.chumbacasino.com", "").await?;
+        // Logged by hand an hour after the Chumba receipt.
+        let receipt = extract(CHUMBA).unwrap().unwrap();
+        let logged = Transaction {
+            id: Uuid::new_v4(),
+            user_id,
+            casino_id: chumba.id,
+            cost: decimal("19.99"),
+            benefit: decimal("20"),
+            created_at: receipt.created_at + Duration::hours(1),
+            updated_at: receipt.created_at + Duration::hours(1),
+            notes: None,
+        };
+        let records = ImportRecords { transactions: vec![logged.clone()], redemptions: vec![] };
+        ctx.insert_records(user_id, &records).await?;
+        let files = vec![CHUMBA.to_vec(), PULSZ.to_vec(), MBOX.to_vec()];
+
+        let scan = ctx.propose_email_transactions(user_id, &files, Duration::hours(DEFAULT_DEDUP_WINDOW_HOURS)).await?;
+        // Without a Pulsz casino its receipt can't be proposed.
+        assert_eq!(1, scan.unreadable.len());
+        assert_eq!(1, scan.ignored);
+        let costs: Vec<String> = scan.proposals.iter().map(|p| p.transaction.cost.to_string()).collect();
+        assert_eq!(vec!["19.99", "9.99", "19.99"], costs);
+        // The receipt and its resent copy in the mailbox are both the hand logged purchase.
+        assert_eq!(Some(logged.id), scan.proposals[0].duplicate_of);
+        assert_eq!(None, scan.proposals[1].duplicate_of);
+        assert_eq!(Some(logged.id), scan.proposals[2].duplicate_of);
+
+        let confirmed: Vec<Proposal> = scan.proposals.iter().filter(|p| p.duplicate_of.is_none()).cloned().collect();
+        assert_eq!(1, ctx.accept_proposals(user_id, &confirmed).await?);
+        assert_eq!(2, ctx.get_transactions(user_id).await?.len());
+
+        // Once logged, the same receipts only propose duplicates.
+        let scan = ctx.propose_email_transactions(user_id, &files, Duration::hours(1)).await?;
+        let duplicates: Vec<Option<Uuid>> = scan.proposals.iter().map(|p| p.duplicate_of).collect();
+        assert_eq!(vec![Some(logged.id), Some(confirmed[0].transaction.id), Some(logged.id)], duplicates);
+        Ok(())
+    }
+}
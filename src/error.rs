@@ -55,6 +55,23 @@ macro_rules! error_types {
             }
 
             impl std::error::Error for $name {}
+
+            // Documented as the string it serializes to.
+            impl utoipa::PartialSchema for $name {
+                fn schema() -> utoipa::openapi::RefOr<utoipa::openapi::schema::Schema> {
+                    let name = serde_json::to_value($name).expect("error types serialize to a string");
+                    utoipa::openapi::ObjectBuilder::new()
+                        .schema_type(utoipa::openapi::Type::String)
+                        .enum_values(Some([name]))
+                        .into()
+                }
+            }
+
+            impl utoipa::ToSchema for $name {
+                fn name() -> std::borrow::Cow<'static, str> {
+                    std::borrow::Cow::Borrowed(stringify!($name))
+                }
+            }
         )*
     };
 }
@@ -69,6 +86,16 @@ error_types! {
     NotFound,
     /// Custom error type for internal server errors.
     InternalServerError,
+    /// Custom error type for forbidden requests.
+    Forbidden,
+    /// Custom error type for rate limited requests.
+    TooManyRequests,
+    /// Custom error type for a known path requested with the wrong method.
+    MethodNotAllowed,
+    /// Custom error type for a body over the route's size limit.
+    PayloadTooLarge,
+    /// Custom error type for a body sent without a `Content-Length`.
+    LengthRequired,
 }
 
 
@@ -89,22 +116,97 @@ impl std::fmt::Display for Sqlx {
 /// Implement the [`std::error::Error`] trait for [`Sqlx`].
 impl std::error::Error for Sqlx {}
 
+/// Custom error type for a bad request with the reason it was rejected.
+#[derive(Debug)]
+pub struct Invalid(pub String);
+
+/// Implement the [`warp::reject::Reject`] trait for [`Invalid`].
+impl warp::reject::Reject for Invalid {}
+
+/// Errors of a request handler that are either a failed query or the client's fault.
+pub(crate) trait RequestError: Display + Sized {
+    /// The failed query, or the error itself when it's the client's fault.
+    fn into_sqlx(self) -> Result<sqlx::Error, Self>;
+}
+
+/// Reject a request with [`Sqlx`] for a failed query, otherwise with [`Invalid`].
+pub(crate) fn reject<E: RequestError>(e: E) -> Rejection {
+    match e.into_sqlx() {
+        Ok(e) => Sqlx(e).into(),
+        Err(e) => {
+            tracing::warn!("Rejected request: {e}");
+            Invalid(e.to_string()).into()
+        }
+    }
+}
+
+/// Json body of an error response.
+#[derive(Debug, Serialize, utoipa::ToSchema)]
+pub struct ErrorBody<E: Serialize> {
+    /// The error type, like `BAD_REQUEST`.
+    pub error: E,
+    /// Why the request was rejected, when the client can fix it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Id of the failed request, to match error reports with the logs.
+    #[schema(value_type = Option<String>)]
+    pub request_id: Option<crate::RequestId>,
+}
+
+impl<E: Serialize> ErrorBody<E> {
+    fn reply(error: E, status: StatusCode) -> reply::Response {
+        Self::reply_with(error, None, status)
+    }
+
+    fn reply_with(error: E, message: Option<String>, status: StatusCode) -> reply::Response {
+        let body = ErrorBody { error, message, request_id: crate::RequestId::current() };
+        reply::with_status(reply::json(&body), status).into_response()
+    }
+}
+
 /// Custom rejection handler that maps rejections into responses.
 pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Rejection> {
     tracing::warn!("handle_rejection");
     tracing::warn!("{:?}", err);
-    if err.is_not_found() {
+    if err.is_not_found() || err.find::<NotFound>().is_some() {
+        crate::metrics::record_rejection("not_found");
         tracing::error_span!("not found");
-        Ok(reply::with_status(warp::reply::json( &NotFound), StatusCode::NOT_FOUND))
+        Ok(ErrorBody::reply(NotFound, StatusCode::NOT_FOUND))
+    } else if let Some(e) = err.find::<warp::cors::CorsForbidden>() {
+        crate::metrics::record_rejection("cors_forbidden");
+        tracing::warn!("cors: {}", e);
+        Ok(ErrorBody::reply(Forbidden, StatusCode::FORBIDDEN))
+    } else if let Some(e) = err.find::<crate::RateLimited>() {
+        crate::metrics::record_rejection("rate_limited");
+        let mut res = ErrorBody::reply(TooManyRequests, StatusCode::TOO_MANY_REQUESTS);
+        res.headers_mut().insert(warp::http::header::RETRY_AFTER, e.retry_after_secs().into());
+        Ok(res)
     } else if let Some(e) = err.find::<Sqlx>() {
+        crate::metrics::record_rejection("sqlx");
         tracing::error!("sqlx error: {:?}", e);
-        Ok(reply::with_status(warp::reply::json(&BadRequest), StatusCode::BAD_REQUEST))
+        Ok(ErrorBody::reply(BadRequest, StatusCode::BAD_REQUEST))
+    } else if let Some(Invalid(message)) = err.find::<Invalid>() {
+        crate::metrics::record_rejection("bad_request");
+        Ok(ErrorBody::reply_with(BadRequest, Some(message.clone()), StatusCode::BAD_REQUEST))
+    } else if err.find::<BadRequest>().is_some()
+        || err.find::<warp::reject::InvalidQuery>().is_some()
+        || err.find::<warp::body::BodyDeserializeError>().is_some()
+    {
+        crate::metrics::record_rejection("bad_request");
+        Ok(ErrorBody::reply(BadRequest, StatusCode::BAD_REQUEST))
+    } else if err.find::<warp::reject::PayloadTooLarge>().is_some() {
+        crate::metrics::record_rejection("payload_too_large");
+        Ok(ErrorBody::reply(PayloadTooLarge, StatusCode::PAYLOAD_TOO_LARGE))
+    } else if err.find::<warp::reject::LengthRequired>().is_some() {
+        crate::metrics::record_rejection("length_required");
+        Ok(ErrorBody::reply(LengthRequired, StatusCode::LENGTH_REQUIRED))
+    } else if err.find::<warp::reject::MethodNotAllowed>().is_some() {
+        crate::metrics::record_rejection("method_not_allowed");
+        Ok(ErrorBody::reply(MethodNotAllowed, StatusCode::METHOD_NOT_ALLOWED))
     } else {
-        eprintln!("unhandled rejection: {err:?}");
-        Ok(reply::with_status(
-        warp::reply::json(&InternalServerError),
-            StatusCode::INTERNAL_SERVER_ERROR,
-        ))
+        crate::metrics::record_rejection("unhandled");
+        tracing::error!("unhandled rejection: {err:?}");
+        Ok(ErrorBody::reply(InternalServerError, StatusCode::INTERNAL_SERVER_ERROR))
     }
 }
 
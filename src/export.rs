@@ -0,0 +1,628 @@
+//! CSV and JSON export of a user's data.
+//!
+//! Exports are read a page at a time, ordered by date and id, and each page is
+//! sent as one chunk of the reply body, so a long history is never held in
+//! memory at once. The first page is read before the reply starts, a database
+//! error after that cuts the body short.
+
+use std::fmt::Display;
+use std::pin::Pin;
+use std::str::FromStr;
+
+use chrono::{NaiveDate, NaiveDateTime};
+use futures_util::{Stream, StreamExt};
+use serde::Serialize;
+use uuid::Uuid;
+use warp::hyper::body::Bytes;
+
+use crate::{CasinoContext, DailyBonus, Db, PlaySession, Redemption, Transaction};
+
+/// Rows read and sent per chunk.
+pub const EXPORT_PAGE_SIZE: i64 = 500;
+
+/// What to export.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportKind {
+    Transactions,
+    Redemptions,
+    Bonuses,
+    Sessions,
+}
+
+impl ExportKind {
+    /// Name of the kind in the export path.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Transactions => "transactions",
+            Self::Redemptions => "redemptions",
+            Self::Bonuses => "bonuses",
+            Self::Sessions => "sessions",
+        }
+    }
+}
+
+impl FromStr for ExportKind {
+    type Err = ExportError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [Self::Transactions, Self::Redemptions, Self::Bonuses, Self::Sessions]
+            .into_iter()
+            .find(|kind| kind.name() == s)
+            .ok_or_else(|| ExportError::UnknownKind(s.to_string()))
+    }
+}
+
+/// Encoding of an export.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    /// A header row, then a row per record.
+    #[default]
+    Csv,
+    /// An array with an object per record.
+    Json,
+}
+
+impl ExportFormat {
+    /// Content type of the reply.
+    #[must_use]
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Json => "application/json",
+        }
+    }
+
+    /// File extension of a download.
+    #[must_use]
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Json => "json",
+        }
+    }
+}
+
+/// Which records to export. Dates are UTC days and both ends are included.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::IntoParams)]
+#[serde(default)]
+#[into_params(parameter_in = Query)]
+pub struct ExportFilter {
+    /// Only records of this casino.
+    pub casino_id:  Option<Uuid>,
+    /// Only records on or after this day.
+    pub from:       Option<NaiveDate>,
+    /// Only records on or before this day.
+    pub to:         Option<NaiveDate>,
+}
+
+impl ExportFilter {
+    /// Start of `from` and of the day after `to`.
+    fn bounds(&self) -> (Option<NaiveDateTime>, Option<NaiveDateTime>) {
+        let start = |day: NaiveDate| day.and_time(chrono::NaiveTime::MIN);
+        (self.from.map(start), self.to.and_then(|day| day.succ_opt()).map(start))
+    }
+}
+
+/// Errors from an export.
+#[derive(Debug)]
+pub enum ExportError {
+    /// The path names no [`ExportKind`].
+    UnknownKind(String),
+    /// `from` is after `to`.
+    InvalidRange,
+    Csv(csv::Error),
+    Json(serde_json::Error),
+    Sqlx(sqlx::Error),
+}
+
+impl Display for ExportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownKind(kind) => write!(f, "nothing to export named {kind:?}"),
+            Self::InvalidRange => f.write_str("from is after to"),
+            Self::Csv(e) => write!(f, "can't write csv: {e}"),
+            Self::Json(e) => write!(f, "can't write json: {e}"),
+            Self::Sqlx(e) => write!(f, "database error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+impl From<csv::Error> for ExportError {
+    fn from(e: csv::Error) -> Self {
+        Self::Csv(e)
+    }
+}
+
+impl From<serde_json::Error> for ExportError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+impl From<sqlx::Error> for ExportError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Sqlx(e)
+    }
+}
+
+impl crate::error::RequestError for ExportError {
+    fn into_sqlx(self) -> Result<sqlx::Error, Self> {
+        match self {
+            Self::Sqlx(e) => Ok(e),
+            e => Err(e),
+        }
+    }
+}
+
+/// Date and id of the last exported record, the next page starts after it.
+pub(crate) type Cursor = (NaiveDateTime, Uuid);
+
+/// A record that can be exported.
+pub trait ExportRow: Serialize {
+    /// CSV header, the field names in serialization order.
+    const COLUMNS: &'static [&'static str];
+
+    /// Date the export is ordered and filtered by, and the id.
+    fn cursor(&self) -> Cursor;
+}
+
+impl ExportRow for Transaction {
+    const COLUMNS: &'static [&'static str] =
+        &["id", "user_id", "casino_id", "cost", "benefit", "created_at", "updated_at", "notes"];
+
+    fn cursor(&self) -> Cursor {
+        (self.created_at, self.id)
+    }
+}
+
+impl ExportRow for Redemption {
+    const COLUMNS: &'static [&'static str] = &["id", "user_id", "casino_id", "amount", "created_at", "received_at"];
+
+    fn cursor(&self) -> Cursor {
+        (self.created_at, self.id)
+    }
+}
+
+impl ExportRow for DailyBonus {
+    const COLUMNS: &'static [&'static str] = &[
+        "id",
+        "user_id",
+        "casino_id",
+        "amount_sc",
+        "amount_gc",
+        "amount_other1",
+        "amount_other2",
+        "amount_other3",
+        "amount_other4",
+        "created_at",
+    ];
+
+    fn cursor(&self) -> Cursor {
+        (self.created_at, self.id)
+    }
+}
+
+impl ExportRow for PlaySession {
+    const COLUMNS: &'static [&'static str] = &[
+        "id",
+        "user_id",
+        "casino_id",
+        "game_id",
+        "beg_amount",
+        "end_amount",
+        "sc_per_spin",
+        "num_spins",
+        "play_date",
+        "created_at",
+        "updated_at",
+    ];
+
+    /// Sessions are exported by the day they were played.
+    fn cursor(&self) -> Cursor {
+        (self.play_date, self.id)
+    }
+}
+
+/// One encoded page of an export.
+struct Chunk {
+    bytes:  Vec<u8>,
+    /// Where the next page starts, `None` after the last page.
+    next:   Option<Cursor>,
+}
+
+/// Encode a page of `rows`. The first page opens the export and a short page closes it.
+fn encode<T: ExportRow>(rows: &[T], format: ExportFormat, first: bool) -> Result<Chunk, ExportError> {
+    let next = match rows.last() {
+        Some(row) if rows.len() as i64 == EXPORT_PAGE_SIZE => Some(row.cursor()),
+        _ => None,
+    };
+    let mut bytes = vec![];
+    match format {
+        ExportFormat::Csv => {
+            let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(&mut bytes);
+            if first {
+                writer.write_record(T::COLUMNS)?;
+            }
+            for row in rows {
+                writer.serialize(row)?;
+            }
+            writer.flush().map_err(csv::Error::from)?;
+        }
+        ExportFormat::Json => {
+            if first {
+                bytes.push(b'[');
+            }
+            for (i, row) in rows.iter().enumerate() {
+                if i > 0 || !first {
+                    bytes.push(b',');
+                }
+                serde_json::to_writer(&mut bytes, row)?;
+            }
+            if next.is_none() {
+                bytes.push(b']');
+            }
+        }
+    }
+    Ok(Chunk { bytes, next })
+}
+
+/// Body of an export reply.
+pub type ExportStream = Pin<Box<dyn Stream<Item = Result<Bytes, ExportError>> + Send>>;
+
+/// Exports for [`CasinoContext`]
+impl CasinoContext {
+    /// Export the `kind` records of `user_id` matching `filter`, one chunk per page.
+    ///
+    /// # Errors
+    /// Will return `Err` if the range is invalid or the first page can't be read.
+    #[tracing::instrument(skip(self), err)]
+    pub async fn export(
+        &self,
+        kind: ExportKind,
+        user_id: Uuid,
+        filter: ExportFilter,
+        format: ExportFormat,
+    ) -> Result<ExportStream, ExportError> {
+        if matches!((filter.from, filter.to), (Some(from), Some(to)) if from > to) {
+            return Err(ExportError::InvalidRange);
+        }
+        let first = self.export_chunk(kind, user_id, &filter, format, None).await?;
+        let ctx = self.clone();
+        let rest = futures_util::stream::try_unfold(first.next, move |after| {
+            let (ctx, filter) = (ctx.clone(), filter.clone());
+            async move {
+                let Some(after) = after else { return Ok(None) };
+                let chunk = ctx.export_chunk(kind, user_id, &filter, format, Some(after)).await?;
+                Ok(Some((Bytes::from(chunk.bytes), chunk.next)))
+            }
+        });
+        // A full last page leaves an empty page to read, `try_unfold` ends after it.
+        Ok(futures_util::stream::once(async move { Ok(Bytes::from(first.bytes)) }).chain(rest).boxed())
+    }
+
+    /// Every transaction and received redemption of `user_id` matching
+    /// `filter`, read a page at a time, for the exports that need all of them
+    /// at once. Redemptions are filtered by the day they were received, the
+    /// day the money reached the bank.
+    pub(crate) async fn cash_flow(
+        &self,
+        user_id: Uuid,
+        filter: &ExportFilter,
+    ) -> Result<(Vec<Transaction>, Vec<Redemption>), sqlx::Error> {
+        let (mut transactions, mut redemptions) = (vec![], vec![]);
+        let mut after = None;
+        loop {
+            let page = self.export_transactions(user_id, filter, after).await?;
+            after = page.last().filter(|_| page.len() as i64 == EXPORT_PAGE_SIZE).map(ExportRow::cursor);
+            transactions.extend(page);
+            if after.is_none() {
+                break;
+            }
+        }
+        loop {
+            let page = self.received_redemptions(user_id, filter, after).await?;
+            after = page
+                .last()
+                .filter(|_| page.len() as i64 == EXPORT_PAGE_SIZE)
+                .map(|r| (r.received_at.unwrap_or(r.created_at), r.id));
+            redemptions.extend(page);
+            if after.is_none() {
+                break;
+            }
+        }
+        Ok((transactions, redemptions))
+    }
+
+    /// Read and encode the page after `after`, the first page when `None`.
+    async fn export_chunk(
+        &self,
+        kind: ExportKind,
+        user_id: Uuid,
+        filter: &ExportFilter,
+        format: ExportFormat,
+        after: Option<Cursor>,
+    ) -> Result<Chunk, ExportError> {
+        let first = after.is_none();
+        match kind {
+            ExportKind::Transactions => encode(&self.export_transactions(user_id, filter, after).await?, format, first),
+            ExportKind::Redemptions => encode(&self.export_redemptions(user_id, filter, after).await?, format, first),
+            ExportKind::Bonuses => encode(&self.export_bonuses(user_id, filter, after).await?, format, first),
+            ExportKind::Sessions => encode(&self.export_sessions(user_id, filter, after).await?, format, first),
+        }
+    }
+
+    /// A page of the user's transactions by creation time.
+    #[tracing::instrument(skip(self), fields(db.system = self.db.backend()), err)]
+    async fn export_transactions(
+        &self,
+        user_id: Uuid,
+        filter: &ExportFilter,
+        after: Option<Cursor>,
+    ) -> Result<Vec<Transaction>, sqlx::Error> {
+        let (from, to) = filter.bounds();
+        match &*self.db {
+            Db::Postgres(pool) => sqlx::query_as!(
+                    Transaction,
+                    r#"SELECT * FROM "transaction"
+                    WHERE user_id = $1
+                    AND ($2::uuid IS NULL OR casino_id = $2)
+                    AND ($3::timestamp IS NULL OR created_at >= $3)
+                    AND ($4::timestamp IS NULL OR created_at < $4)
+                    AND ($5::timestamp IS NULL OR (created_at, id) > ($5, $6::uuid))
+                    ORDER BY created_at, id LIMIT $7"#,
+                    user_id,
+                    filter.casino_id,
+                    from,
+                    to,
+                    after.map(|(date, _)| date),
+                    after.map(|(_, id)| id),
+                    EXPORT_PAGE_SIZE
+                )
+                .fetch_all(pool)
+                .await,
+            #[cfg(feature = "sqlite")]
+            Db::Sqlite(pool) => {
+                crate::sqlite::export_transactions(pool, user_id, filter.casino_id, (from, to), after, EXPORT_PAGE_SIZE)
+                    .await
+            }
+        }
+    }
+
+    /// A page of the user's redemptions by creation time.
+    #[tracing::instrument(skip(self), fields(db.system = self.db.backend()), err)]
+    async fn export_redemptions(
+        &self,
+        user_id: Uuid,
+        filter: &ExportFilter,
+        after: Option<Cursor>,
+    ) -> Result<Vec<Redemption>, sqlx::Error> {
+        let (from, to) = filter.bounds();
+        match &*self.db {
+            Db::Postgres(pool) => sqlx::query_as!(
+                    Redemption,
+                    r#"SELECT * FROM redemption
+                    WHERE user_id = $1
+                    AND ($2::uuid IS NULL OR casino_id = $2)
+                    AND ($3::timestamp IS NULL OR created_at >= $3)
+                    AND ($4::timestamp IS NULL OR created_at < $4)
+                    AND ($5::timestamp IS NULL OR (created_at, id) > ($5, $6::uuid))
+                    ORDER BY created_at, id LIMIT $7"#,
+                    user_id,
+                    filter.casino_id,
+                    from,
+                    to,
+                    after.map(|(date, _)| date),
+                    after.map(|(_, id)| id),
+                    EXPORT_PAGE_SIZE
+                )
+                .fetch_all(pool)
+                .await,
+            #[cfg(feature = "sqlite")]
+            Db::Sqlite(pool) => {
+                crate::sqlite::export_redemptions(pool, user_id, filter.casino_id, (from, to), after, EXPORT_PAGE_SIZE)
+                    .await
+            }
+        }
+    }
+
+    /// A page of the user's received redemptions by receipt time.
+    #[tracing::instrument(skip(self), fields(db.system = self.db.backend()), err)]
+    async fn received_redemptions(
+        &self,
+        user_id: Uuid,
+        filter: &ExportFilter,
+        after: Option<Cursor>,
+    ) -> Result<Vec<Redemption>, sqlx::Error> {
+        let (from, to) = filter.bounds();
+        match &*self.db {
+            Db::Postgres(pool) => sqlx::query_as!(
+                    Redemption,
+                    r#"SELECT * FROM redemption
+                    WHERE user_id = $1 AND received_at IS NOT NULL
+                    AND ($2::uuid IS NULL OR casino_id = $2)
+                    AND ($3::timestamp IS NULL OR received_at >= $3)
+                    AND ($4::timestamp IS NULL OR received_at < $4)
+                    AND ($5::timestamp IS NULL OR (received_at, id) > ($5, $6::uuid))
+                    ORDER BY received_at, id LIMIT $7"#,
+                    user_id,
+                    filter.casino_id,
+                    from,
+                    to,
+                    after.map(|(date, _)| date),
+                    after.map(|(_, id)| id),
+                    EXPORT_PAGE_SIZE
+                )
+                .fetch_all(pool)
+                .await,
+            #[cfg(feature = "sqlite")]
+            Db::Sqlite(pool) => {
+                crate::sqlite::received_redemptions(pool, user_id, filter.casino_id, (from, to), after, EXPORT_PAGE_SIZE)
+                    .await
+            }
+        }
+    }
+
+    /// A page of the user's daily bonus claims by claim time.
+    #[tracing::instrument(skip(self), fields(db.system = self.db.backend()), err)]
+    async fn export_bonuses(
+        &self,
+        user_id: Uuid,
+        filter: &ExportFilter,
+        after: Option<Cursor>,
+    ) -> Result<Vec<DailyBonus>, sqlx::Error> {
+        let (from, to) = filter.bounds();
+        match &*self.db {
+            Db::Postgres(pool) => sqlx::query_as!(
+                    DailyBonus,
+                    r#"SELECT * FROM daily_bonus
+                    WHERE user_id = $1
+                    AND ($2::uuid IS NULL OR casino_id = $2)
+                    AND ($3::timestamp IS NULL OR created_at >= $3)
+                    AND ($4::timestamp IS NULL OR created_at < $4)
+                    AND ($5::timestamp IS NULL OR (created_at, id) > ($5, $6::uuid))
+                    ORDER BY created_at, id LIMIT $7"#,
+                    user_id,
+                    filter.casino_id,
+                    from,
+                    to,
+                    after.map(|(date, _)| date),
+                    after.map(|(_, id)| id),
+                    EXPORT_PAGE_SIZE
+                )
+                .fetch_all(pool)
+                .await,
+            #[cfg(feature = "sqlite")]
+            Db::Sqlite(pool) => {
+                crate::sqlite::export_bonuses(pool, user_id, filter.casino_id, (from, to), after, EXPORT_PAGE_SIZE).await
+            }
+        }
+    }
+
+    /// A page of the user's play sessions by play date.
+    #[tracing::instrument(skip(self), fields(db.system = self.db.backend()), err)]
+    async fn export_sessions(
+        &self,
+        user_id: Uuid,
+        filter: &ExportFilter,
+        after: Option<Cursor>,
+    ) -> Result<Vec<PlaySession>, sqlx::Error> {
+        let (from, to) = filter.bounds();
+        match &*self.db {
+            Db::Postgres(pool) => sqlx::query_as!(
+                    PlaySession,
+                    r#"SELECT * FROM play_session
+                    WHERE user_id = $1
+                    AND ($2::uuid IS NULL OR casino_id = $2)
+                    AND ($3::timestamp IS NULL OR play_date >= $3)
+                    AND ($4::timestamp IS NULL OR play_date < $4)
+                    AND ($5::timestamp IS NULL OR (play_date, id) > ($5, $6::uuid))
+                    ORDER BY play_date, id LIMIT $7"#,
+                    user_id,
+                    filter.casino_id,
+                    from,
+                    to,
+                    after.map(|(date, _)| date),
+                    after.map(|(_, id)| id),
+                    EXPORT_PAGE_SIZE
+                )
+                .fetch_all(pool)
+                .await,
+            #[cfg(feature = "sqlite")]
+            Db::Sqlite(pool) => {
+                crate::sqlite::export_sessions(pool, user_id, filter.casino_id, (from, to), after, EXPORT_PAGE_SIZE).await
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::ImportRecords;
+    use bigdecimal::BigDecimal;
+
+    backend_tests!(test_export);
+
+    /// Collect an export, returning the body and the number of chunks.
+    async fn collect(stream: ExportStream) -> (Vec<u8>, usize) {
+        let chunks: Vec<Bytes> = stream.map(Result::unwrap).collect().await;
+        (chunks.concat(), chunks.len())
+    }
+
+    #[test]
+    fn test_export_kind() {
+        for kind in [ExportKind::Transactions, ExportKind::Redemptions, ExportKind::Bonuses, ExportKind::Sessions] {
+            assert_eq!(kind, ExportKind::from_str(kind.name()).unwrap());
+        }
+        assert!(ExportKind::from_str("games").is_err());
+    }
+
+    async fn test_export(ctx: CasinoContext) -> sqlx::Result<()> {
+        let user_id = ctx.create_user().await?.id;
+        let other = ctx.create_casino("Other", "https://other.example", "").await?;
+        let start = NaiveDate::from_ymd_opt(2026, 1, 1).unwrap().and_hms_opt(0, 0, 0).unwrap();
+        // A full page for the test casino, one hourly purchase each, and one more at another casino.
+        let transactions: Vec<Transaction> = (0..=EXPORT_PAGE_SIZE)
+            .map(|i| Transaction {
+                id: Uuid::new_v4(),
+                user_id,
+                casino_id: if i == EXPORT_PAGE_SIZE { other.id } else { Uuid::nil() },
+                cost: BigDecimal::from(i),
+                benefit: BigDecimal::from(i + 1),
+                created_at: start + chrono::Duration::hours(i),
+                updated_at: start,
+                notes: (i % 2 == 0).then(|| format!("note, {i}")),
+            })
+            .collect();
+        let records = ImportRecords { transactions: transactions.clone(), redemptions: vec![] };
+        ctx.insert_records(user_id, &records).await?;
+        let export = |filter: ExportFilter, format: ExportFormat| {
+            let ctx = ctx.clone();
+            async move { collect(ctx.export(ExportKind::Transactions, user_id, filter, format).await.unwrap()).await }
+        };
+
+        let (body, chunks) = export(ExportFilter::default(), ExportFormat::Csv).await;
+        assert_eq!(2, chunks);
+        let mut reader = csv::Reader::from_reader(body.as_slice());
+        assert_eq!(Transaction::COLUMNS, reader.headers().unwrap().iter().collect::<Vec<_>>().as_slice());
+        let read: Vec<Transaction> = reader.deserialize().map(Result::unwrap).collect();
+        assert_eq!(transactions, read);
+
+        // Exactly a page, the array is closed by an empty last page.
+        let casino = ExportFilter { casino_id: Some(Uuid::nil()), ..ExportFilter::default() };
+        let (body, chunks) = export(casino, ExportFormat::Json).await;
+        assert_eq!(2, chunks);
+        let read: Vec<Transaction> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(transactions[..EXPORT_PAGE_SIZE as usize], read[..]);
+
+        // Both ends of the range are included.
+        let day = NaiveDate::from_ymd_opt(2026, 1, 2);
+        let (body, _) = export(ExportFilter { from: day, to: day, ..ExportFilter::default() }, ExportFormat::Json).await;
+        let read: Vec<Transaction> = serde_json::from_slice(&body).unwrap();
+        assert_eq!(transactions[24..48], read[..]);
+
+        let none = ExportFilter { casino_id: Some(Uuid::new_v4()), ..ExportFilter::default() };
+        assert_eq!(b"[]".to_vec(), export(none.clone(), ExportFormat::Json).await.0);
+        assert_eq!(1, export(none, ExportFormat::Csv).await.0.split(|b| *b == b'\n').filter(|l| !l.is_empty()).count());
+
+        ctx.create_redemption(user_id, other.id, BigDecimal::from(50), None).await?;
+        let (body, _) = collect(
+            ctx.export(ExportKind::Redemptions, user_id, ExportFilter::default(), ExportFormat::Json).await.unwrap(),
+        )
+        .await;
+        assert_eq!(1, serde_json::from_slice::<Vec<Redemption>>(&body).unwrap().len());
+        let (body, _) =
+            collect(ctx.export(ExportKind::Sessions, user_id, ExportFilter::default(), ExportFormat::Json).await.unwrap())
+                .await;
+        assert_eq!(b"[]".to_vec(), body);
+
+        let backwards = ExportFilter { from: day, to: NaiveDate::from_ymd_opt(2026, 1, 1), ..ExportFilter::default() };
+        let err = ctx.export(ExportKind::Bonuses, user_id, backwards, ExportFormat::Csv).await.err().unwrap();
+        assert!(matches!(err, ExportError::InvalidRange));
+        Ok(())
+    }
+}
@@ -4,27 +4,67 @@ use warp::{Filter, Rejection, Reply};
 use uuid::Uuid;
 use bigdecimal::BigDecimal;
 
-use crate::{BadRequest, CasinoContext};
+use crate::export::{ExportFilter, ExportFormat, ExportKind};
+use crate::import::ColumnMapping;
+use crate::journal::{JournalAccounts, JournalFormat};
+use crate::reconcile::{BankColumns, BankLinkCreate, DEFAULT_MATCH_WINDOW_DAYS};
+use crate::statement::{StatementFormat, DEFAULT_STATEMENT_ACCOUNT};
+use crate::tax::{TaxFormat, DEFAULT_REPORTING_THRESHOLD};
+use crate::{BadRequest, CasinoContext, ErrorBody};
+#[allow(unused_imports)] // Referenced from the OpenAPI annotations.
+use crate::{import::ImportReport, importers::HistoryImportReport, reconcile::{BankLink, ReconcileReport}, tax::TaxReport, NotFound};
+#[allow(unused_imports)] // Referenced from the OpenAPI annotations.
+use crate::{CBUserId, CasinoListingReplyBody, DailyBonus, SummaryReplyBody, LivenessReport, ReadinessReport, Redemption, Transaction, TransactionsReplyBody, UserReplyBody};
 
 
-#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
 pub struct UserCreate {
     pub email: String,
     pub username: String,
 }
 
 /// Struct for the json query body for create adding a transaction.
-#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
 pub struct TransactionCreate {
     pub user_id:    String,
     pub casino_id:  String,
+    #[schema(value_type = String)]
     pub cost:       BigDecimal,
+    #[schema(value_type = String)]
     pub benefit:    BigDecimal,
     pub notes:      Option<String>,
 }
 
+/// Struct for the json body for logging a redemption.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
+pub struct RedemptionCreate {
+    #[schema(value_type = String)]
+    pub amount:         BigDecimal,
+    pub received_at:    Option<chrono::NaiveDateTime>,
+}
+
+/// Struct for the json body for logging a daily bonus claim.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
+pub struct DailyBonusCreate {
+    #[schema(value_type = String)]
+    pub amount_sc:      BigDecimal,
+    #[serde(default)]
+    #[schema(value_type = String)]
+    pub amount_gc:      BigDecimal,
+}
+
 
 /// Get a user by their id.
+#[utoipa::path(
+    get,
+    path = "/user/{user_id}",
+    tag = "user",
+    params(("user_id" = Uuid, Path, description = "User id")),
+    responses(
+        (status = 200, description = "The user, empty when it doesn't exist", body = UserReplyBody),
+        (status = 400, description = "The user id isn't a uuid", body = ErrorBody<BadRequest>),
+    ),
+)]
 pub(crate) async fn get_user_filter(
     ctx: CasinoContext,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
@@ -34,7 +74,7 @@ pub(crate) async fn get_user_filter(
         .and(warp::get())
         .and(context)
         .and_then( |user_id: String, inner_ctx: CasinoContext| async move {
-            let user_id = Uuid::parse_str(&user_id).unwrap();
+            let user_id: Uuid = Uuid::from_str(&user_id).map_err(|_| BadRequest)?;
             tracing::info!("Getting user with id: {}", user_id);
             inner_ctx.process_get_user(user_id).await
         })
@@ -47,24 +87,37 @@ fn with_transaction_create_params(
     warp::any().map(move || params.clone())
 }
 
-/// Filter to get the json body.
+/// Filter to get the json body, limited to `limit` bytes.
 pub(crate) fn with_json_body<T: serde::de::DeserializeOwned + Send>(
+    limit: u64,
 ) -> impl Filter<Extract = (T,), Error = Rejection> + Clone {
-    warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+    warp::body::content_length_limit(limit).and(warp::body::json())
 }
 
 /// Post filter for transactions.
 /// `/transaction/{user_id}/{casino_id}`
+#[utoipa::path(
+    post,
+    path = "/transaction/{user_id}/{casino_id}",
+    tag = "transaction",
+    params(("user_id" = Uuid, Path, description = "User id"), ("casino_id" = Uuid, Path, description = "Casino id")),
+    request_body = TransactionCreate,
+    responses(
+        (status = 201, description = "The created transaction", body = Transaction),
+        (status = 400, description = "Invalid ids or the insert failed", body = ErrorBody<BadRequest>),
+    ),
+)]
 #[allow(clippy::unused_async)]
 pub(crate) async fn transaction_post_filter(
     ctx: CasinoContext,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let body_limit = ctx.body_limit;
     let context = warp::any().map(move || ctx.clone());
 
     // POST /transaction/{user_id}/{casino_id}
     warp::path!("transaction" / String / String)
         .and(warp::post())
-        .and(with_json_body())
+        .and(with_json_body(body_limit))
         .and(context)
         .and_then(
             |user_id: String, casino_id: String, params: TransactionCreate, inner_ctx: CasinoContext| async move {
@@ -79,8 +132,488 @@ pub(crate) async fn transaction_post_filter(
         )
 }
 
+/// Post filter for redemptions.
+/// `/redemption/{user_id}/{casino_id}`
+#[utoipa::path(
+    post,
+    path = "/redemption/{user_id}/{casino_id}",
+    tag = "redemption",
+    params(("user_id" = Uuid, Path, description = "User id"), ("casino_id" = Uuid, Path, description = "Casino id")),
+    request_body = RedemptionCreate,
+    responses(
+        (status = 201, description = "The logged redemption", body = Redemption),
+        (status = 400, description = "Invalid ids or the insert failed", body = ErrorBody<BadRequest>),
+    ),
+)]
+#[allow(clippy::unused_async)]
+pub(crate) async fn redemption_post_filter(
+    ctx: CasinoContext,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let body_limit = ctx.body_limit;
+    let context = warp::any().map(move || ctx.clone());
+
+    warp::path!("redemption" / String / String)
+        .and(warp::post())
+        .and(with_json_body(body_limit))
+        .and(context)
+        .and_then(
+            |user_id: String, casino_id: String, params: RedemptionCreate, inner_ctx: CasinoContext| async move {
+                let user_id: Uuid = Uuid::from_str(&user_id).map_err(|_| BadRequest)?;
+                let casino_id: Uuid = Uuid::from_str(&casino_id).map_err(|_| BadRequest)?;
+                inner_ctx
+                    .process_post_redemption(user_id, casino_id, params.amount, params.received_at)
+                    .await
+            },
+        )
+}
+
+/// Post filter for daily bonus claims.
+/// `/bonus/{user_id}/{casino_id}`
+#[utoipa::path(
+    post,
+    path = "/bonus/{user_id}/{casino_id}",
+    tag = "bonus",
+    params(("user_id" = Uuid, Path, description = "User id"), ("casino_id" = Uuid, Path, description = "Casino id")),
+    request_body = DailyBonusCreate,
+    responses(
+        (status = 201, description = "The logged claim", body = DailyBonus),
+        (status = 400, description = "Invalid ids or the insert failed", body = ErrorBody<BadRequest>),
+    ),
+)]
+#[allow(clippy::unused_async)]
+pub(crate) async fn bonus_post_filter(
+    ctx: CasinoContext,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let body_limit = ctx.body_limit;
+    let context = warp::any().map(move || ctx.clone());
+
+    warp::path!("bonus" / String / String)
+        .and(warp::post())
+        .and(with_json_body(body_limit))
+        .and(context)
+        .and_then(
+            |user_id: String, casino_id: String, params: DailyBonusCreate, inner_ctx: CasinoContext| async move {
+                let user_id: Uuid = Uuid::from_str(&user_id).map_err(|_| BadRequest)?;
+                let casino_id: Uuid = Uuid::from_str(&casino_id).map_err(|_| BadRequest)?;
+                inner_ctx
+                    .process_post_bonus(user_id, casino_id, params.amount_sc, params.amount_gc)
+                    .await
+            },
+        )
+}
+
+/// Get a user's spend and benefit per casino.
+/// `/summary/{user_id}`
+#[utoipa::path(
+    get,
+    path = "/summary/{user_id}",
+    tag = "summary",
+    params(("user_id" = Uuid, Path, description = "User id")),
+    responses(
+        (status = 200, description = "One entry per casino with transactions", body = SummaryReplyBody),
+        (status = 400, description = "The user id isn't a uuid", body = ErrorBody<BadRequest>),
+    ),
+)]
+#[allow(clippy::unused_async)]
+pub(crate) async fn summary_get_filter(
+    ctx: CasinoContext,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let context = warp::any().map(move || ctx.clone());
+
+    warp::path!("summary" / String)
+        .and(warp::get())
+        .and(context)
+        .and_then(|user_id: String, inner_ctx: CasinoContext| async move {
+            let user_id: Uuid = Uuid::from_str(&user_id).map_err(|_| BadRequest)?;
+            inner_ctx.process_get_summary(user_id).await
+        })
+}
+
+/// Query of a transaction import: the user and the [`ColumnMapping`] of the file.
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct ImportQuery {
+    pub user_id:    String,
+    #[serde(flatten)]
+    pub columns:    ColumnMapping,
+}
+
+/// Import transactions from a CSV file.
+/// `/import/transactions?user_id={user_id}`
+#[utoipa::path(
+    post,
+    path = "/import/transactions",
+    tag = "import",
+    params(("user_id" = Uuid, Query, description = "User id"), ColumnMapping),
+    request_body(content = String, description = "CSV with a header row", content_type = "text/csv"),
+    responses(
+        (status = 200, description = "What happened to every row", body = ImportReport),
+        (status = 400, description = "Invalid user id, unreadable header or a missing column", body = ErrorBody<BadRequest>),
+    ),
+)]
+#[allow(clippy::unused_async)]
+pub(crate) async fn import_transactions_filter(
+    ctx: CasinoContext,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let import_limit = ctx.import_limit;
+    let context = warp::any().map(move || ctx.clone());
+
+    warp::path!("import" / "transactions")
+        .and(warp::post())
+        .and(warp::query::<ImportQuery>())
+        .and(warp::body::content_length_limit(import_limit))
+        .and(warp::body::bytes())
+        .and(context)
+        .and_then(|query: ImportQuery, body: warp::hyper::body::Bytes, inner_ctx: CasinoContext| async move {
+            let user_id: Uuid = Uuid::from_str(&query.user_id).map_err(|_| BadRequest)?;
+            let csv = std::str::from_utf8(&body).map_err(|_| BadRequest)?;
+            inner_ctx.process_import_transactions(user_id, csv, &query.columns).await
+        })
+}
+
+/// Query of a history export upload.
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct HistoryImportQuery {
+    pub user_id:    String,
+    /// Importer to read the file with, detected when unset.
+    pub format:     Option<String>,
+}
+
+/// Import a casino's history export.
+/// `/import/history?user_id={user_id}`
+#[utoipa::path(
+    post,
+    path = "/import/history",
+    tag = "import",
+    params(
+        ("user_id" = Uuid, Query, description = "User id"),
+        ("format" = Option<String>, Query, description = "Importer name like `chumba`, `pulsz` or `stake`, detected when unset"),
+    ),
+    request_body(content = String, description = "The file exported by the casino", content_type = "text/plain"),
+    responses(
+        (status = 200, description = "Records inserted and skipped as duplicates", body = HistoryImportReport),
+        (status = 400, description = "Invalid user id, unknown format or casino, or an invalid record", body = ErrorBody<BadRequest>),
+    ),
+)]
+#[allow(clippy::unused_async)]
+pub(crate) async fn import_history_filter(
+    ctx: CasinoContext,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let import_limit = ctx.import_limit;
+    let context = warp::any().map(move || ctx.clone());
+
+    warp::path!("import" / "history")
+        .and(warp::post())
+        .and(warp::query::<HistoryImportQuery>())
+        .and(warp::body::content_length_limit(import_limit))
+        .and(warp::body::bytes())
+        .and(context)
+        .and_then(|query: HistoryImportQuery, body: warp::hyper::body::Bytes, inner_ctx: CasinoContext| async move {
+            let user_id: Uuid = Uuid::from_str(&query.user_id).map_err(|_| BadRequest)?;
+            let contents = std::str::from_utf8(&body).map_err(|_| BadRequest)?;
+            inner_ctx.process_import_history(user_id, contents, query.format.as_deref()).await
+        })
+}
+
+/// Query of an export: the user, the format and which records.
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct ExportQuery {
+    pub user_id:    String,
+    pub format:     ExportFormat,
+    #[serde(flatten)]
+    pub filter:     ExportFilter,
+}
+
+/// Export a user's transactions, redemptions, bonuses or play sessions.
+/// `/export/{kind}?user_id={user_id}`
+#[utoipa::path(
+    get,
+    path = "/export/{kind}",
+    tag = "export",
+    params(
+        ("kind" = String, Path, description = "`transactions`, `redemptions`, `bonuses` or `sessions`"),
+        ("user_id" = Uuid, Query, description = "User id"),
+        ("format" = Option<ExportFormat>, Query, description = "`csv` (default) or `json`"),
+        ExportFilter,
+    ),
+    responses(
+        (status = 200, description = "The records ordered by date, streamed", content(
+            (String = "text/csv"),
+            (Vec<serde_json::Value> = "application/json"),
+        )),
+        (status = 400, description = "Invalid user id, kind, format or date range", body = ErrorBody<BadRequest>),
+    ),
+)]
+pub(crate) async fn export_filter(
+    ctx: CasinoContext,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let context = warp::any().map(move || ctx.clone());
+
+    warp::path!("export" / String)
+        .and(warp::get())
+        .and(warp::query::<ExportQuery>())
+        .and(context)
+        .and_then(|kind: String, query: ExportQuery, inner_ctx: CasinoContext| async move {
+            let kind = ExportKind::from_str(&kind).map_err(|_| BadRequest)?;
+            let user_id: Uuid = Uuid::from_str(&query.user_id).map_err(|_| BadRequest)?;
+            inner_ctx.process_export(kind, user_id, query.filter, query.format).await
+        })
+}
+
+/// Query of a journal export: the user, the format, the account names and which records.
+#[derive(Clone, Debug, Default, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct JournalQuery {
+    pub user_id:    String,
+    pub format:     JournalFormat,
+    #[serde(flatten)]
+    pub accounts:   JournalAccounts,
+    #[serde(flatten)]
+    pub filter:     ExportFilter,
+}
+
+/// Export a user's purchases and received redemptions as a ledger or beancount journal.
+/// `/export/journal?user_id={user_id}`
+#[utoipa::path(
+    get,
+    path = "/export/journal",
+    tag = "export",
+    params(
+        ("user_id" = Uuid, Query, description = "User id"),
+        ("format" = Option<JournalFormat>, Query, description = "`ledger` (default) or `beancount`"),
+        JournalAccounts,
+        ExportFilter,
+    ),
+    responses(
+        (status = 200, description = "The journal in date order", body = String, content_type = "text/plain"),
+        (status = 400, description = "Invalid user id, format, account name or date range", body = ErrorBody<BadRequest>),
+    ),
+)]
+pub(crate) async fn export_journal_filter(
+    ctx: CasinoContext,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let context = warp::any().map(move || ctx.clone());
+
+    warp::path!("export" / "journal")
+        .and(warp::get())
+        .and(warp::query::<JournalQuery>())
+        .and(context)
+        .and_then(|query: JournalQuery, inner_ctx: CasinoContext| async move {
+            let user_id: Uuid = Uuid::from_str(&query.user_id).map_err(|_| BadRequest)?;
+            inner_ctx.process_export_journal(user_id, &query).await
+        })
+}
+
+/// Query of a statement export: the user, the format, the account id and which records.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct StatementQuery {
+    pub user_id:    String,
+    pub format:     StatementFormat,
+    /// Account id in the statement.
+    pub account:    String,
+    #[serde(flatten)]
+    pub filter:     ExportFilter,
+}
+
+impl Default for StatementQuery {
+    fn default() -> Self {
+        Self {
+            user_id: String::new(),
+            format: StatementFormat::default(),
+            account: DEFAULT_STATEMENT_ACCOUNT.to_string(),
+            filter: ExportFilter::default(),
+        }
+    }
+}
+
+/// Export a user's purchases and received redemptions as an OFX or QIF statement.
+/// `/export/statement?user_id={user_id}`
+#[utoipa::path(
+    get,
+    path = "/export/statement",
+    tag = "export",
+    params(
+        ("user_id" = Uuid, Query, description = "User id"),
+        ("format" = Option<StatementFormat>, Query, description = "`ofx` (default) or `qif`"),
+        ("account" = Option<String>, Query, description = "Account id in the statement, `CASINOBUDDY` by default"),
+        ExportFilter,
+    ),
+    responses(
+        (status = 200, description = "The statement in date order", body = String, content_type = "application/x-ofx"),
+        (status = 400, description = "Invalid user id, format, account id or date range", body = ErrorBody<BadRequest>),
+    ),
+)]
+pub(crate) async fn export_statement_filter(
+    ctx: CasinoContext,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let context = warp::any().map(move || ctx.clone());
+
+    warp::path!("export" / "statement")
+        .and(warp::get())
+        .and(warp::query::<StatementQuery>())
+        .and(context)
+        .and_then(|query: StatementQuery, inner_ctx: CasinoContext| async move {
+            let user_id: Uuid = Uuid::from_str(&query.user_id).map_err(|_| BadRequest)?;
+            inner_ctx.process_export_statement(user_id, &query).await
+        })
+}
+
+/// Query of a bank statement upload: the user, the match window and the [`BankColumns`] of the file.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct ReconcileQuery {
+    pub user_id:        String,
+    /// Days between a bank row and a record for them to match, at most [`crate::reconcile::MAX_MATCH_WINDOW_DAYS`].
+    pub window_days:    u32,
+    #[serde(flatten)]
+    pub columns:        BankColumns,
+}
+
+impl Default for ReconcileQuery {
+    fn default() -> Self {
+        Self {
+            user_id: String::new(),
+            window_days: DEFAULT_MATCH_WINDOW_DAYS,
+            columns: BankColumns::default(),
+        }
+    }
+}
+
+/// Match a bank statement CSV with a user's transactions and redemptions.
+/// `/reconcile?user_id={user_id}`
+#[utoipa::path(
+    post,
+    path = "/reconcile",
+    tag = "reconcile",
+    params(
+        ("user_id" = Uuid, Query, description = "User id"),
+        ("window_days" = Option<u32>, Query, description = "Days between a bank row and a record for them to match, 3 by default and at most 365"),
+        BankColumns,
+    ),
+    request_body(content = String, description = "Bank statement CSV with a header row", content_type = "text/csv"),
+    responses(
+        (status = 200, description = "Matched and unmatched bank rows and records", body = ReconcileReport),
+        (status = 400, description = "Invalid user id, window over 365 days, unreadable header or a missing column", body = ErrorBody<BadRequest>),
+    ),
+)]
+#[allow(clippy::unused_async)]
+pub(crate) async fn reconcile_filter(
+    ctx: CasinoContext,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let import_limit = ctx.import_limit;
+    let context = warp::any().map(move || ctx.clone());
+
+    warp::path!("reconcile")
+        .and(warp::post())
+        .and(warp::query::<ReconcileQuery>())
+        .and(warp::body::content_length_limit(import_limit))
+        .and(warp::body::bytes())
+        .and(context)
+        .and_then(|query: ReconcileQuery, body: warp::hyper::body::Bytes, inner_ctx: CasinoContext| async move {
+            let user_id: Uuid = Uuid::from_str(&query.user_id).map_err(|_| BadRequest)?;
+            let csv = std::str::from_utf8(&body).map_err(|_| BadRequest)?;
+            inner_ctx.process_reconcile(user_id, csv, &query).await
+        })
+}
+
+/// Link a bank row to a transaction or redemption, so later uploads match them.
+/// `/reconcile/link POST {'user_id': ..., 'kind': 'transaction', 'record_id': ..., 'date': ..., 'amount': ..., 'description': ...}`
+#[utoipa::path(
+    post,
+    path = "/reconcile/link",
+    tag = "reconcile",
+    request_body = BankLinkCreate,
+    responses(
+        (status = 201, description = "The link", body = BankLink),
+        (status = 400, description = "Invalid ids or the insert failed", body = ErrorBody<BadRequest>),
+        (status = 404, description = "The record isn't one of the user's", body = ErrorBody<NotFound>),
+    ),
+)]
+#[allow(clippy::unused_async)]
+pub(crate) async fn reconcile_link_filter(
+    ctx: CasinoContext,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let body_limit = ctx.body_limit;
+    let context = warp::any().map(move || ctx.clone());
+
+    warp::path!("reconcile" / "link")
+        .and(warp::post())
+        .and(with_json_body(body_limit))
+        .and(context)
+        .and_then(|params: BankLinkCreate, inner_ctx: CasinoContext| async move {
+            let user_id: Uuid = Uuid::from_str(&params.user_id).map_err(|_| BadRequest)?;
+            let record_id: Uuid = Uuid::from_str(&params.record_id).map_err(|_| BadRequest)?;
+            inner_ctx.process_link_bank_entry(user_id, record_id, &params).await
+        })
+}
+
+/// Query of a tax report: the user, the format and the reporting threshold.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct TaxQuery {
+    pub user_id:    String,
+    pub format:     TaxFormat,
+    /// Smallest single redemption listed.
+    pub threshold:  BigDecimal,
+}
+
+impl Default for TaxQuery {
+    fn default() -> Self {
+        Self {
+            user_id: String::new(),
+            format: TaxFormat::default(),
+            threshold: BigDecimal::from(DEFAULT_REPORTING_THRESHOLD),
+        }
+    }
+}
+
+/// Get a user's purchases, received redemptions and net gain per casino in a calendar year.
+/// `/report/tax/{year}?user_id={user_id}`
+#[utoipa::path(
+    get,
+    path = "/report/tax/{year}",
+    tag = "report",
+    params(
+        ("year" = i32, Path, description = "Calendar year"),
+        ("user_id" = Uuid, Query, description = "User id"),
+        ("format" = Option<TaxFormat>, Query, description = "`json` (default), a printable `html` page or `csv`"),
+        ("threshold" = Option<String>, Query, description = "Smallest single redemption listed, 600 by default"),
+    ),
+    responses(
+        (status = 200, description = "The report", content(
+            (TaxReport = "application/json"),
+            (String = "text/html"),
+            (String = "text/csv"),
+        )),
+        (status = 400, description = "Invalid user id, year, format or threshold", body = ErrorBody<BadRequest>),
+    ),
+)]
+pub(crate) async fn tax_report_filter(
+    ctx: CasinoContext,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let context = warp::any().map(move || ctx.clone());
+
+    warp::path!("report" / "tax" / i32)
+        .and(warp::get())
+        .and(warp::query::<TaxQuery>())
+        .and(context)
+        .and_then(|year: i32, query: TaxQuery, inner_ctx: CasinoContext| async move {
+            let user_id: Uuid = Uuid::from_str(&query.user_id).map_err(|_| BadRequest)?;
+            inner_ctx.process_tax_report(user_id, year, &query).await
+        })
+}
+
 /// Get casino listing
 /// `/casino`
+#[utoipa::path(
+    get,
+    path = "/casino",
+    tag = "casino",
+    responses((status = 200, description = "Every casino", body = CasinoListingReplyBody)),
+)]
 pub(crate) async fn casino_list_filter(
     ctx: CasinoContext,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
@@ -97,6 +630,16 @@ pub(crate) async fn casino_list_filter(
 
 /// Get all transactions for a user.
 /// `/transaction/{user_id}`
+#[utoipa::path(
+    get,
+    path = "/transaction/{user_id}",
+    tag = "transaction",
+    params(("user_id" = Uuid, Path, description = "User id")),
+    responses(
+        (status = 200, description = "The user's transactions, `{}` when there are none", body = TransactionsReplyBody),
+        (status = 400, description = "The user id isn't a uuid", body = ErrorBody<BadRequest>),
+    ),
+)]
 #[allow(clippy::unused_async)]
 pub(crate) async fn transaction_get_filter(
     ctx: CasinoContext,
@@ -107,11 +650,40 @@ pub(crate) async fn transaction_get_filter(
         .and(warp::get())
         .and(context)
         .and_then(|user_id: String, inner_ctx: CasinoContext| async move {
-            let user_id = user_id.parse::<Uuid>().unwrap();
+            let user_id: Uuid = Uuid::from_str(&user_id).map_err(|_| BadRequest)?;
             tracing::info!("Getting transactions with user_id: {}", user_id);
             inner_ctx.process_get_transaction(user_id).await
         })
 }
+
+/// Get all transactions for a user, an empty list when there are none.
+/// `/v2/transaction/{user_id}`
+#[utoipa::path(
+    get,
+    operation_id = "transaction_get_filter",
+    path = "/transaction/{user_id}",
+    tag = "transaction",
+    params(("user_id" = Uuid, Path, description = "User id")),
+    responses(
+        (status = 200, description = "The user's transactions", body = TransactionsReplyBody),
+        (status = 400, description = "The user id isn't a uuid", body = ErrorBody<BadRequest>),
+    ),
+)]
+#[allow(clippy::unused_async)]
+pub(crate) async fn transaction_get_v2_filter(
+    ctx: CasinoContext,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let context = warp::any().map(move || ctx.clone());
+
+    warp::path!("transaction" / String)
+        .and(warp::get())
+        .and(context)
+        .and_then(|user_id: String, inner_ctx: CasinoContext| async move {
+            let user_id: Uuid = Uuid::from_str(&user_id).map_err(|_| BadRequest)?;
+            inner_ctx.process_list_transactions(user_id).await
+        })
+}
+
 /// Filter to get the items db.
 pub(crate) fn with_user_create_params(
     params: UserCreate,
@@ -121,17 +693,28 @@ pub(crate) fn with_user_create_params(
 
 /// Post a new user.
 /// `/user POST {'username': 'testuser', 'email': 'testemail'}`
+#[utoipa::path(
+    post,
+    path = "/user",
+    tag = "user",
+    request_body = UserCreate,
+    responses(
+        (status = 200, description = "Id of the created user", body = CBUserId),
+        (status = 400, description = "The insert failed", body = ErrorBody<BadRequest>),
+    ),
+)]
 #[allow(clippy::unused_async)]
 pub(crate) async fn post_user_filter(
     ctx: CasinoContext,
 ) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let body_limit = ctx.body_limit;
     let context = warp::any().map(move || ctx.clone());
 
     warp::path("user")
         .and(warp::post())
         .and(warp::path::end())
         .and(context)
-        .and(with_json_body())
+        .and(with_json_body(body_limit))
         .and_then(
             |inner_ctx: CasinoContext, user_create: UserCreate| async move {
                 tracing::info!("Creating user with email: {} and username: {}", user_create.email, user_create.username);
@@ -143,3 +726,91 @@ pub(crate) async fn post_user_filter(
         )
 }
 
+
+/// Plain health check kept for existing probes.
+/// `/health`
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "operations",
+    responses((status = 200, description = "Always `Hello, world!`", body = String, content_type = "text/plain")),
+)]
+pub(crate) fn hello_filter() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("health").map(|| "Hello, world!")
+}
+
+/// Liveness check, doesn't touch the database.
+/// `/health/live`
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "operations",
+    responses((status = 200, description = "The process is serving requests", body = LivenessReport)),
+)]
+pub(crate) fn health_live_filter(
+    ctx: CasinoContext,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("health" / "live")
+        .map(move || ctx.process_liveness())
+}
+
+/// Readiness check against the database and its migrations.
+/// `/health/ready`
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "operations",
+    responses(
+        (status = 200, description = "Ready for traffic", body = ReadinessReport),
+        (status = 503, description = "A check failed", body = ReadinessReport),
+    ),
+)]
+pub(crate) fn health_ready_filter(
+    ctx: CasinoContext,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let context = warp::any().map(move || ctx.clone());
+    warp::path!("health" / "ready")
+        .and(context)
+        .and_then(|inner_ctx: CasinoContext| async move { inner_ctx.process_readiness().await })
+}
+
+/// Liveness and readiness checks.
+/// `/health/live` and `/health/ready`
+#[allow(clippy::unused_async)]
+pub(crate) async fn health_filter(
+    ctx: CasinoContext,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    health_live_filter(ctx.clone()).or(health_ready_filter(ctx))
+}
+
+/// Prometheus metrics in the text exposition format.
+/// `/metrics`
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "operations",
+    responses((status = 200, description = "Prometheus text exposition format", body = String, content_type = "text/plain")),
+)]
+#[allow(clippy::unused_async)]
+pub(crate) async fn metrics_filter(
+    ctx: CasinoContext,
+) -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    warp::path!("metrics")
+        .and(warp::get())
+        .map(move || ctx.process_metrics())
+}
+
+/// The OpenAPI document for every route.
+/// `/openapi.json`
+#[utoipa::path(
+    get,
+    path = "/openapi.json",
+    tag = "operations",
+    responses((status = 200, description = "This document", content_type = "application/json")),
+)]
+pub(crate) fn openapi_filter() -> impl Filter<Extract = impl Reply, Error = Rejection> + Clone {
+    let spec = crate::openapi::spec_json();
+    warp::path!("openapi.json")
+        .and(warp::get())
+        .map(move || warp::reply::with_header(spec.clone(), "content-type", "application/json"))
+}
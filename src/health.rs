@@ -0,0 +1,235 @@
+//! Liveness and readiness checks for orchestrators.
+//!
+//! `/health/live` only says the process is serving requests, `/health/ready`
+//! also checks the database so traffic stops being routed to a broken instance.
+
+use std::time::{Duration, Instant};
+
+use warp::{http::StatusCode, reject::Rejection, Reply};
+
+use crate::CasinoContext;
+
+/// How long the readiness check waits on the database before giving up.
+const READY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Error reported when the database can't be reached. The report is
+/// unauthenticated, so the cause is only logged.
+const DATABASE_UNAVAILABLE: &str = "database unavailable";
+
+/// Error reported when the applied migrations can't be read, the cause is only logged.
+const MIGRATIONS_UNAVAILABLE: &str = "migrations unavailable";
+
+/// Overall health of the instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Ok,
+    Unavailable,
+}
+
+/// Version information compiled into the binary.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct BuildInfo {
+    pub name: String,
+    pub version: String,
+}
+
+impl BuildInfo {
+    #[must_use]
+    pub fn current() -> Self {
+        Self {
+            name: env!("CARGO_PKG_NAME").to_string(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+}
+
+/// Response body for `/health/live`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct LivenessReport {
+    pub status: HealthStatus,
+    pub build: BuildInfo,
+}
+
+/// Database connectivity as seen by the readiness check.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct DatabaseHealth {
+    pub backend: String,
+    pub connected: bool,
+    pub latency_ms: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// Connection pool statistics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct PoolStats {
+    /// Open connections, idle or in use.
+    pub size: u32,
+    pub idle: u32,
+    pub max: u32,
+}
+
+/// Applied migrations as seen by the readiness check.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct MigrationHealth {
+    pub version: Option<i64>,
+    pub pending: Vec<i64>,
+    pub error: Option<String>,
+}
+
+/// Response body for `/health/ready`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct ReadinessReport {
+    pub status: HealthStatus,
+    pub database: DatabaseHealth,
+    pub pool: PoolStats,
+    pub migrations: MigrationHealth,
+    pub build: BuildInfo,
+}
+
+/// Health checks for [`CasinoContext`]
+impl CasinoContext {
+    /// The process is up, doesn't touch the database.
+    #[must_use]
+    pub fn liveness(&self) -> LivenessReport {
+        LivenessReport {
+            status: HealthStatus::Ok,
+            build: BuildInfo::current(),
+        }
+    }
+
+    /// Check the database is reachable and its schema matches this build.
+    /// Pending migrations count as not ready, the queries expect the latest schema.
+    pub async fn readiness(&self) -> ReadinessReport {
+        let start = Instant::now();
+        let database = match tokio::time::timeout(READY_TIMEOUT, self.ping()).await {
+            Ok(Ok(())) => DatabaseHealth {
+                backend: self.db.backend().to_string(),
+                connected: true,
+                latency_ms: Some(u64::try_from(start.elapsed().as_millis()).unwrap_or(u64::MAX)),
+                error: None,
+            },
+            Ok(Err(e)) => {
+                tracing::error!("Readiness check can't reach the database: {e}");
+                DatabaseHealth {
+                    backend: self.db.backend().to_string(),
+                    connected: false,
+                    latency_ms: None,
+                    error: Some(DATABASE_UNAVAILABLE.to_string()),
+                }
+            }
+            Err(_) => DatabaseHealth {
+                backend: self.db.backend().to_string(),
+                connected: false,
+                latency_ms: None,
+                error: Some(format!("timed out after {READY_TIMEOUT:?}")),
+            },
+        };
+
+        let migrations = if database.connected {
+            match tokio::time::timeout(READY_TIMEOUT, self.check_migrations()).await {
+                Ok(Ok(status)) => MigrationHealth {
+                    version: status.version,
+                    pending: status.pending,
+                    error: None,
+                },
+                Ok(Err(e)) => {
+                    tracing::error!("Readiness check can't read the migrations: {e}");
+                    MigrationHealth {
+                        version: None,
+                        pending: vec![],
+                        error: Some(MIGRATIONS_UNAVAILABLE.to_string()),
+                    }
+                }
+                Err(_) => MigrationHealth {
+                    version: None,
+                    pending: vec![],
+                    error: Some(format!("timed out after {READY_TIMEOUT:?}")),
+                },
+            }
+        } else {
+            MigrationHealth {
+                version: None,
+                pending: vec![],
+                error: Some(DATABASE_UNAVAILABLE.to_string()),
+            }
+        };
+
+        let ready = database.connected && migrations.error.is_none() && migrations.pending.is_empty();
+        ReadinessReport {
+            status: if ready { HealthStatus::Ok } else { HealthStatus::Unavailable },
+            database,
+            pool: self.db.pool_stats(),
+            migrations,
+            build: BuildInfo::current(),
+        }
+    }
+
+    /// Process a liveness request.
+    pub(crate) fn process_liveness(&self) -> impl Reply {
+        warp::reply::json(&self.liveness())
+    }
+
+    /// Process a readiness request, `503` when the instance shouldn't get traffic.
+    pub(crate) async fn process_readiness(&self) -> Result<impl Reply, Rejection> {
+        let report = self.readiness().await;
+        if report.status != HealthStatus::Ok {
+            tracing::warn!("Readiness check failed: {:?}", report);
+        }
+        let status = match report.status {
+            HealthStatus::Ok => StatusCode::OK,
+            HealthStatus::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+        };
+        Ok(warp::reply::with_status(warp::reply::json(&report), status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::health_filter;
+
+    backend_tests!(test_readiness, test_req_health_checks);
+
+    async fn test_readiness(ctx: CasinoContext) -> sqlx::Result<()> {
+        let report = ctx.readiness().await;
+        assert_eq!(HealthStatus::Ok, report.status);
+        assert!(report.database.connected);
+        assert!(report.migrations.pending.is_empty());
+        assert_eq!(crate::migrate::MIGRATOR.iter().map(|m| m.version).max(), report.migrations.version);
+        assert!(report.pool.size <= report.pool.max);
+
+        // Once the pool is closed the instance is no longer ready.
+        ctx.close().await;
+        let report = ctx.readiness().await;
+        assert_eq!(HealthStatus::Unavailable, report.status);
+        assert!(!report.database.connected);
+        // The cause is logged, not reported.
+        assert_eq!(Some(DATABASE_UNAVAILABLE), report.database.error.as_deref());
+        assert_eq!(Some(DATABASE_UNAVAILABLE), report.migrations.error.as_deref());
+        Ok(())
+    }
+
+    async fn test_req_health_checks(ctx: CasinoContext) -> sqlx::Result<()> {
+        let filter = health_filter(ctx.clone()).await;
+
+        let res = warp::test::request().path("/health/live").reply(&filter).await;
+        assert_eq!(StatusCode::OK, res.status());
+        let live: LivenessReport = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(BuildInfo::current(), live.build);
+
+        let res = warp::test::request().path("/health/ready").reply(&filter).await;
+        assert_eq!(StatusCode::OK, res.status());
+
+        ctx.close().await;
+        let res = warp::test::request().path("/health/ready").reply(&filter).await;
+        assert_eq!(StatusCode::SERVICE_UNAVAILABLE, res.status());
+        let ready: ReadinessReport = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(HealthStatus::Unavailable, ready.status);
+        assert_eq!(Some(DATABASE_UNAVAILABLE), ready.database.error.as_deref());
+        // Liveness doesn't depend on the database.
+        let res = warp::test::request().path("/health/live").reply(&filter).await;
+        assert_eq!(StatusCode::OK, res.status());
+        Ok(())
+    }
+}
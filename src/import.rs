@@ -0,0 +1,516 @@
+//! CSV import of historical transactions.
+//!
+//! Every row is validated on its own: the casino must match a casino name or
+//! url exactly, the amounts must be non negative decimals and the date an
+//! RFC 3339 time or one of [`DATE_FORMATS`]. The valid rows are inserted in a single database
+//! transaction and rows matching an existing transaction of the user (same
+//! casino, amounts and time) are reported as duplicates instead, so a file can
+//! be fixed and imported again.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::hash::Hash;
+use std::str::FromStr;
+
+use bigdecimal::{BigDecimal, Zero};
+use chrono::NaiveDateTime;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::{Casino, CasinoContext, Db, Redemption, Transaction};
+
+/// Date formats accepted in the date column, tried in order.
+pub const DATE_FORMATS: &[&str] = &[
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y-%m-%d %H:%M",
+    "%Y-%m-%d",
+    "%m/%d/%Y %H:%M",
+    "%m/%d/%Y",
+];
+
+/// Header names of the columns to read, matched case insensitively.
+/// Columns not named here are ignored.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::IntoParams)]
+#[serde(default)]
+#[into_params(parameter_in = Query)]
+pub struct ColumnMapping {
+    /// Casino name or url, defaults to `casino`.
+    pub casino:     String,
+    /// Money spent, defaults to `cost`.
+    pub cost:       String,
+    /// Coins received, defaults to `benefit`.
+    pub benefit:    String,
+    /// When the purchase was made, defaults to `date`.
+    pub date:       String,
+    /// Optional notes, defaults to `notes`.
+    pub notes:      String,
+}
+
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        Self {
+            casino: "casino".to_string(),
+            cost: "cost".to_string(),
+            benefit: "benefit".to_string(),
+            date: "date".to_string(),
+            notes: "notes".to_string(),
+        }
+    }
+}
+
+/// What happened to one row of an import.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportStatus {
+    Accepted,
+    Duplicate,
+    Rejected,
+}
+
+/// Outcome of one data row.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct ImportRow {
+    /// Line of the row in the file, the header is line 1.
+    pub line:           u64,
+    pub status:         ImportStatus,
+    /// The inserted transaction, or the one an imported row duplicates.
+    pub transaction_id: Option<Uuid>,
+    /// Why the row was rejected.
+    pub error:          Option<String>,
+}
+
+/// Per row report of an import.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct ImportReport {
+    pub accepted:   u64,
+    pub duplicates: u64,
+    pub rejected:   u64,
+    pub rows:       Vec<ImportRow>,
+}
+
+impl ImportReport {
+    fn push(&mut self, line: u64, status: ImportStatus, transaction_id: Option<Uuid>, error: Option<String>) {
+        match status {
+            ImportStatus::Accepted => self.accepted += 1,
+            ImportStatus::Duplicate => self.duplicates += 1,
+            ImportStatus::Rejected => self.rejected += 1,
+        }
+        self.rows.push(ImportRow { line, status, transaction_id, error });
+    }
+}
+
+/// Errors that stop a whole import.
+#[derive(Debug)]
+pub enum ImportError {
+    /// The header row can't be read.
+    Csv(csv::Error),
+    /// A mapped column isn't in the header.
+    MissingColumn(String),
+    Sqlx(sqlx::Error),
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Csv(e) => write!(f, "invalid csv: {e}"),
+            Self::MissingColumn(column) => write!(f, "the header has no {column:?} column"),
+            Self::Sqlx(e) => write!(f, "import failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<csv::Error> for ImportError {
+    fn from(e: csv::Error) -> Self {
+        Self::Csv(e)
+    }
+}
+
+impl From<sqlx::Error> for ImportError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Sqlx(e)
+    }
+}
+
+impl crate::error::RequestError for ImportError {
+    fn into_sqlx(self) -> Result<sqlx::Error, Self> {
+        match self {
+            Self::Sqlx(e) => Ok(e),
+            e => Err(e),
+        }
+    }
+}
+
+/// Lowercase host and path of a url, without the scheme, `www.` or a trailing slash.
+pub(crate) fn url_host(url: &str) -> String {
+    let url = url.trim().to_lowercase();
+    let url = url.split_once("://").map_or(url.as_str(), |(_, rest)| rest);
+    url.trim_start_matches("www.").trim_end_matches('/').to_string()
+}
+
+/// The casino named exactly by `name`, by name or url, case insensitive.
+/// A blank name matches no casino, not one without a url.
+pub(crate) fn find_casino<'a>(casinos: &'a [Casino], name: &str) -> Option<&'a Casino> {
+    let lower = name.trim().to_lowercase();
+    let host = url_host(name);
+    if lower.is_empty() || host.is_empty() {
+        return None;
+    }
+    casinos.iter().find(|casino| casino.name.to_lowercase() == lower || url_host(&casino.url) == host)
+}
+
+/// Parse an RFC 3339 time as UTC, or a date in one of [`DATE_FORMATS`].
+/// Dates without a time are at midnight.
+pub(crate) fn parse_date(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim();
+    if let Ok(time) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Some(time.naive_utc());
+    }
+    DATE_FORMATS.iter().find_map(|format| {
+        NaiveDateTime::parse_from_str(value, format)
+            .ok()
+            .or_else(|| chrono::NaiveDate::parse_from_str(value, format).ok().map(|d| d.and_time(chrono::NaiveTime::MIN)))
+    })
+}
+
+/// Parse a non negative amount, allowing a leading `$` and thousands separators.
+pub(crate) fn parse_amount(column: &str, value: &str) -> Result<BigDecimal, String> {
+    let cleaned: String = value.trim().trim_start_matches('$').chars().filter(|c| *c != ',').collect();
+    let amount = BigDecimal::from_str(&cleaned).map_err(|_| format!("{column} {value:?} is not a number"))?;
+    if amount < BigDecimal::zero() {
+        return Err(format!("{column} {value:?} is negative"));
+    }
+    Ok(amount)
+}
+
+/// Column positions of a mapping in a header.
+struct Columns {
+    casino:     usize,
+    cost:       usize,
+    benefit:    usize,
+    date:       usize,
+    notes:      Option<usize>,
+}
+
+impl Columns {
+    fn new(header: &csv::StringRecord, mapping: &ColumnMapping) -> Result<Self, ImportError> {
+        let find = |name: &str| header.iter().position(|column| column.trim().eq_ignore_ascii_case(name.trim()));
+        let required = |name: &str| find(name).ok_or_else(|| ImportError::MissingColumn(name.to_string()));
+        Ok(Self {
+            casino: required(&mapping.casino)?,
+            cost: required(&mapping.cost)?,
+            benefit: required(&mapping.benefit)?,
+            date: required(&mapping.date)?,
+            notes: find(&mapping.notes),
+        })
+    }
+
+    /// Turn a record into a transaction of `user_id`, or say why it can't be.
+    fn transaction(&self, record: &csv::StringRecord, casinos: &[Casino], user_id: Uuid) -> Result<Transaction, String> {
+        let field = |i: usize| record.get(i).unwrap_or_default();
+        let casino = find_casino(casinos, field(self.casino))
+            .ok_or_else(|| format!("no casino matches {:?}", field(self.casino)))?;
+        let created_at =
+            parse_date(field(self.date)).ok_or_else(|| format!("date {:?} is not a known format", field(self.date)))?;
+        let notes = self.notes.map(field).map(str::trim).filter(|notes| !notes.is_empty()).map(str::to_string);
+        Ok(Transaction {
+            id: Uuid::new_v4(),
+            user_id,
+            casino_id: casino.id,
+            cost: parse_amount("cost", field(self.cost))?,
+            benefit: parse_amount("benefit", field(self.benefit))?,
+            created_at,
+            updated_at: chrono::Utc::now().naive_utc(),
+            notes,
+        })
+    }
+}
+
+/// Records parsed from an import, not yet in the database.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportRecords {
+    pub transactions:   Vec<Transaction>,
+    pub redemptions:    Vec<Redemption>,
+}
+
+/// For every record of an [`ImportRecords`], the id of the record it
+/// duplicates or `None` when it was inserted.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Duplicates {
+    pub transactions:   Vec<Option<Uuid>>,
+    pub redemptions:    Vec<Option<Uuid>>,
+    /// Pending redemptions the import has as received, with the time they were received.
+    pub received:       Vec<(Uuid, NaiveDateTime)>,
+}
+
+/// What makes two transactions of a user the same purchase.
+fn transaction_key(transaction: &Transaction) -> (Uuid, BigDecimal, BigDecimal, NaiveDateTime) {
+    (
+        transaction.casino_id,
+        transaction.cost.normalized(),
+        transaction.benefit.normalized(),
+        transaction.created_at,
+    )
+}
+
+/// What makes two redemptions of a user the same withdrawal.
+fn redemption_key(redemption: &Redemption) -> (Uuid, BigDecimal, NaiveDateTime) {
+    (redemption.casino_id, redemption.amount.normalized(), redemption.created_at)
+}
+
+/// For each of `new`, the id of the `existing` or earlier record with the same key.
+fn duplicates_of<T, K: Hash + Eq>(
+    existing: &[T],
+    new: &[T],
+    key: impl Fn(&T) -> K,
+    id: impl Fn(&T) -> Uuid,
+) -> Vec<Option<Uuid>> {
+    let mut seen: HashMap<K, Uuid> = existing.iter().map(|record| (key(record), id(record))).collect();
+    new.iter()
+        .map(|record| match seen.entry(key(record)) {
+            Entry::Occupied(entry) => Some(*entry.get()),
+            Entry::Vacant(entry) => {
+                entry.insert(id(record));
+                None
+            }
+        })
+        .collect()
+}
+
+impl Duplicates {
+    /// Find the duplicates among `records` of the user's `transactions` and `redemptions`.
+    pub(crate) fn find(records: &ImportRecords, transactions: &[Transaction], redemptions: &[Redemption]) -> Self {
+        let duplicate_redemptions = duplicates_of(redemptions, &records.redemptions, redemption_key, |r| r.id);
+        let pending: HashMap<Uuid, &Redemption> =
+            redemptions.iter().filter(|r| r.received_at.is_none()).map(|r| (r.id, r)).collect();
+        let received = records
+            .redemptions
+            .iter()
+            .zip(&duplicate_redemptions)
+            .filter_map(|(record, duplicate)| {
+                let id = duplicate.filter(|id| pending.contains_key(id))?;
+                Some((id, record.received_at?))
+            })
+            .collect();
+        Self {
+            transactions: duplicates_of(transactions, &records.transactions, transaction_key, |t| t.id),
+            redemptions: duplicate_redemptions,
+            received,
+        }
+    }
+}
+
+/// Import operations for [`CasinoContext`]
+impl CasinoContext {
+    /// Insert the records of `user_id` in a single database transaction,
+    /// skipping the ones that duplicate an existing or earlier record. A
+    /// pending redemption duplicated by a received one is marked received.
+    pub(crate) async fn insert_records(&self, user_id: Uuid, records: &ImportRecords) -> Result<Duplicates, sqlx::Error> {
+        match &*self.db {
+            Db::Postgres(pool) => insert_records_postgres(pool, user_id, records).await,
+            #[cfg(feature = "sqlite")]
+            Db::Sqlite(pool) => crate::sqlite::insert_records(pool, user_id, records).await,
+        }
+    }
+
+    /// Import the transactions of `user_id` from a CSV file with a header row.
+    ///
+    /// # Errors
+    /// Will return `Err` if the header can't be read, lacks a mapped column or
+    /// the insert fails, nothing is imported in that case.
+    #[tracing::instrument(skip(self, csv), fields(db.system = self.db.backend()), err)]
+    pub async fn import_transactions(
+        &self,
+        user_id: Uuid,
+        csv: &str,
+        mapping: &ColumnMapping,
+    ) -> Result<ImportReport, ImportError> {
+        let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(csv.as_bytes());
+        let columns = Columns::new(reader.headers()?, mapping)?;
+        let casinos = self.get_all_casinos().await?;
+
+        // Line and either the parsed transaction or why the row was rejected.
+        let mut rows = Vec::new();
+        for record in reader.records() {
+            rows.push(match record {
+                Ok(record) => {
+                    let line = record.position().map_or(0, csv::Position::line);
+                    (line, columns.transaction(&record, &casinos, user_id))
+                }
+                Err(e) => (e.position().map_or(0, csv::Position::line), Err(e.to_string())),
+            });
+        }
+
+        let records = ImportRecords {
+            transactions: rows.iter().filter_map(|(_, row)| row.as_ref().ok()).cloned().collect(),
+            redemptions: vec![],
+        };
+        let mut duplicates = self.insert_records(user_id, &records).await?.transactions.into_iter();
+
+        let mut report = ImportReport::default();
+        for (line, row) in rows {
+            match row {
+                Ok(transaction) => match duplicates.next().flatten() {
+                    Some(id) => report.push(line, ImportStatus::Duplicate, Some(id), None),
+                    None => report.push(line, ImportStatus::Accepted, Some(transaction.id), None),
+                },
+                Err(e) => report.push(line, ImportStatus::Rejected, None, Some(e)),
+            }
+        }
+        tracing::info!(
+            "Imported transactions: {} accepted, {} duplicates, {} rejected",
+            report.accepted,
+            report.duplicates,
+            report.rejected
+        );
+        Ok(report)
+    }
+}
+
+/// Insert the records that don't duplicate one of the user's in a single database transaction.
+async fn insert_records_postgres(
+    pool: &PgPool,
+    user_id: Uuid,
+    records: &ImportRecords,
+) -> Result<Duplicates, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let transactions = sqlx::query_as!(Transaction, r#"SELECT * FROM "transaction" WHERE user_id = $1"#, user_id)
+        .fetch_all(&mut *tx)
+        .await?;
+    let redemptions = sqlx::query_as!(Redemption, "SELECT * FROM redemption WHERE user_id = $1", user_id)
+        .fetch_all(&mut *tx)
+        .await?;
+    let duplicates = Duplicates::find(records, &transactions, &redemptions);
+
+    for (transaction, _) in records.transactions.iter().zip(&duplicates.transactions).filter(|(_, d)| d.is_none()) {
+        sqlx::query!(
+            r#"INSERT INTO "transaction" (id, user_id, casino_id, cost, benefit, created_at, updated_at, notes)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)"#,
+            transaction.id,
+            transaction.user_id,
+            transaction.casino_id,
+            transaction.cost,
+            transaction.benefit,
+            transaction.created_at,
+            transaction.updated_at,
+            transaction.notes
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    for (redemption, _) in records.redemptions.iter().zip(&duplicates.redemptions).filter(|(_, d)| d.is_none()) {
+        sqlx::query!(
+            r#"INSERT INTO redemption (id, user_id, casino_id, amount, created_at, received_at)
+            VALUES ($1, $2, $3, $4, $5, $6)"#,
+            redemption.id,
+            redemption.user_id,
+            redemption.casino_id,
+            redemption.amount,
+            redemption.created_at,
+            redemption.received_at
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    for (id, received_at) in &duplicates.received {
+        sqlx::query!(
+            "UPDATE redemption SET received_at = $1 WHERE id = $2 AND received_at IS NULL",
+            received_at,
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(duplicates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    backend_tests!(test_import_transactions);
+
+    #[test]
+    fn test_parse_fields() {
+        assert_eq!("chumbacasino.com", url_host("https://www.ChumbaCasino.com/"));
+        let midnight = chrono::NaiveDate::from_ymd_opt(2026, 3, 1).unwrap().and_time(chrono::NaiveTime::MIN);
+        assert_eq!(Some(midnight), parse_date("2026-03-01"));
+        assert_eq!(Some(midnight), parse_date("03/01/2026"));
+        assert_eq!(Some(midnight), parse_date("2026-03-01T00:00:00"));
+        assert_eq!(Some(midnight + chrono::Duration::minutes(90)), parse_date("2026-03-01 01:30"));
+        assert_eq!(Some(midnight), parse_date("2026-03-01T02:00:00+02:00"));
+        assert_eq!(None, parse_date("yesterday"));
+        assert_eq!(Ok(BigDecimal::from_str("1234.5").unwrap()), parse_amount("cost", "$1,234.50"));
+        assert!(parse_amount("cost", "-1").unwrap_err().contains("negative"));
+        assert!(parse_amount("cost", "ten").is_err());
+
+        let casino = |name: &str, url: &str| Casino {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            url: url.to_string(),
+            description: String::new(),
+            created_at: NaiveDateTime::default(),
+            updated_at: NaiveDateTime::default(),
+        };
+        let casinos = [casino("Stake", ""), casino("Chumba Casino", "https://www.chumbacasino.com")];
+        assert_eq!(Some(&casinos[1]), find_casino(&casinos, "chumbacasino.com"));
+        assert_eq!(Some(&casinos[0]), find_casino(&casinos, " stake "));
+        assert_eq!(None, find_casino(&casinos, ""));
+        assert_eq!(None, find_casino(&casinos, " / "));
+    }
+
+    async fn test_import_transactions(ctx: CasinoContext) -> sqlx::Result<()> {
+        let user_id = ctx.create_user().await?.id;
+        let casino = ctx.create_casino("Import Casino", "https://import.example.com", "").await?;
+        let mapping = ColumnMapping {
+            casino: "Site".to_string(),
+            cost: "Price".to_string(),
+            benefit: "Coins".to_string(),
+            date: "When".to_string(),
+            ..ColumnMapping::default()
+        };
+        let csv = "When,Site,Price,Coins,Notes\n\
+            2026-03-01,Import Casino,19.99,25,first\n\
+            2026-03-02 10:00,import.example.com,\"$1,000.00\",1200,\n\
+            2026-03-01,import casino,19.990,25,same as line 2\n\
+            2026-03-03,Nowhere,1,1,\n\
+            2026-03-03,Import Casino,-1,1,\n\
+            2026-03-03,Import Casino\n";
+
+        let report = ctx.import_transactions(user_id, csv, &mapping).await.unwrap();
+        let statuses: Vec<(u64, ImportStatus)> = report.rows.iter().map(|row| (row.line, row.status)).collect();
+        assert_eq!(
+            vec![
+                (2, ImportStatus::Accepted),
+                (3, ImportStatus::Accepted),
+                (4, ImportStatus::Duplicate),
+                (5, ImportStatus::Rejected),
+                (6, ImportStatus::Rejected),
+                (7, ImportStatus::Rejected),
+            ],
+            statuses
+        );
+        assert_eq!((2, 1, 3), (report.accepted, report.duplicates, report.rejected));
+        assert_eq!(report.rows[0].transaction_id, report.rows[2].transaction_id);
+        assert!(report.rows[3].error.as_deref().unwrap().contains("Nowhere"));
+
+        let transactions = ctx.get_transactions(user_id).await?;
+        assert_eq!(2, transactions.len());
+        let first = transactions.iter().find(|t| Some(t.id) == report.rows[0].transaction_id).unwrap();
+        assert_eq!((casino.id, Some("first")), (first.casino_id, first.notes.as_deref()));
+        assert_eq!(BigDecimal::from(1000), transactions.iter().find(|t| t.notes.is_none()).unwrap().cost);
+
+        // Importing the file again only finds duplicates.
+        let again = ctx.import_transactions(user_id, csv, &mapping).await.unwrap();
+        assert_eq!((0, 3, 3), (again.accepted, again.duplicates, again.rejected));
+        assert_eq!(2, ctx.get_transactions(user_id).await?.len());
+
+        let missing = ctx.import_transactions(user_id, csv, &ColumnMapping::default()).await;
+        assert!(matches!(missing, Err(ImportError::MissingColumn(column)) if column == "casino"));
+        Ok(())
+    }
+}
@@ -0,0 +1,523 @@
+//! Importers for the purchase history exports of individual casinos.
+//!
+//! Each casino exports its history in its own layout. An [`Importer`] knows
+//! one layout: it recognises a file and turns it into the [`Transaction`]s and
+//! [`Redemption`]s of a user. [`IMPORTERS`] lists the ones the upload endpoint
+//! tries, in order, when the request doesn't name a format. Only completed
+//! purchases are imported, and the records are inserted like a CSV import, so
+//! uploading the same export twice only finds duplicates. A redemption that
+//! was pending in an earlier upload and is received in a newer one is updated.
+
+use std::fmt::Display;
+
+use bigdecimal::BigDecimal;
+use chrono::NaiveDateTime;
+use serde::de::DeserializeOwned;
+use uuid::Uuid;
+
+use crate::import::{find_casino, parse_amount, parse_date, ImportRecords};
+use crate::{CasinoContext, Redemption, Transaction};
+
+/// Reads one casino's history export.
+pub trait Importer: Send + Sync {
+    /// Name of the format, used to pick an importer explicitly.
+    fn name(&self) -> &'static str;
+
+    /// Url of the casino the exports come from, matched against the casino table.
+    fn casino_url(&self) -> &'static str;
+
+    /// Whether `contents` looks like an export in this format.
+    fn detect(&self, contents: &str) -> bool;
+
+    /// Turn an export into the records of `user_id` at `casino_id`.
+    ///
+    /// # Errors
+    /// Will return `Err` if the file isn't in this format or a record is invalid.
+    fn parse(&self, contents: &str, user_id: Uuid, casino_id: Uuid) -> Result<ImportRecords, ImporterError>;
+}
+
+/// Every importer, in the order formats are detected.
+pub static IMPORTERS: &[&dyn Importer] = &[&ChumbaCsv, &PulszCsv, &StakeJson];
+
+/// The importer for the format called `name`.
+#[must_use]
+pub fn importer(name: &str) -> Option<&'static dyn Importer> {
+    IMPORTERS.iter().copied().find(|importer| importer.name().eq_ignore_ascii_case(name))
+}
+
+/// The first importer that recognises `contents`.
+#[must_use]
+pub fn detect(contents: &str) -> Option<&'static dyn Importer> {
+    IMPORTERS.iter().copied().find(|importer| importer.detect(contents))
+}
+
+/// Errors from reading or importing a history export.
+#[derive(Debug)]
+pub enum ImporterError {
+    /// No importer is called this.
+    UnknownFormat(String),
+    /// No importer recognises the file.
+    Undetected,
+    /// The casino the export is from isn't in the casino table.
+    UnknownCasino(&'static str),
+    Csv(csv::Error),
+    Json(serde_json::Error),
+    /// A record can't be imported, the message says which one.
+    Invalid(String),
+    Sqlx(sqlx::Error),
+}
+
+impl Display for ImporterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownFormat(name) => write!(f, "unknown import format {name:?}"),
+            Self::Undetected => f.write_str("the file isn't in a known export format"),
+            Self::UnknownCasino(url) => write!(f, "no casino with url {url}"),
+            Self::Csv(e) => write!(f, "invalid csv: {e}"),
+            Self::Json(e) => write!(f, "invalid json: {e}"),
+            Self::Invalid(msg) => f.write_str(msg),
+            Self::Sqlx(e) => write!(f, "import failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ImporterError {}
+
+impl From<csv::Error> for ImporterError {
+    fn from(e: csv::Error) -> Self {
+        Self::Csv(e)
+    }
+}
+
+impl From<serde_json::Error> for ImporterError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+impl From<sqlx::Error> for ImporterError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Sqlx(e)
+    }
+}
+
+impl crate::error::RequestError for ImporterError {
+    fn into_sqlx(self) -> Result<sqlx::Error, Self> {
+        match self {
+            Self::Sqlx(e) => Ok(e),
+            e => Err(e),
+        }
+    }
+}
+
+/// Records inserted, updated and skipped as duplicates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct ImportCounts {
+    pub accepted:   u64,
+    /// Pending redemptions the export has as received since.
+    pub updated:    u64,
+    pub duplicates: u64,
+}
+
+impl ImportCounts {
+    fn count(duplicates: &[Option<Uuid>], updated: usize) -> Self {
+        let found = duplicates.iter().filter(|duplicate| duplicate.is_some()).count() as u64;
+        let updated = updated as u64;
+        Self { accepted: duplicates.len() as u64 - found, updated, duplicates: found - updated }
+    }
+}
+
+/// Outcome of a history export upload.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct HistoryImportReport {
+    /// Name of the importer that read the file.
+    pub format:         String,
+    pub casino_id:      Uuid,
+    pub transactions:   ImportCounts,
+    pub redemptions:    ImportCounts,
+}
+
+fn new_transaction(
+    user_id: Uuid,
+    casino_id: Uuid,
+    cost: BigDecimal,
+    benefit: BigDecimal,
+    created_at: NaiveDateTime,
+    notes: String,
+) -> Transaction {
+    Transaction {
+        id: Uuid::new_v4(),
+        user_id,
+        casino_id,
+        cost,
+        benefit,
+        created_at,
+        updated_at: chrono::Utc::now().naive_utc(),
+        notes: Some(notes),
+    }
+}
+
+fn new_redemption(
+    user_id: Uuid,
+    casino_id: Uuid,
+    amount: BigDecimal,
+    created_at: NaiveDateTime,
+    received_at: Option<NaiveDateTime>,
+) -> Redemption {
+    Redemption { id: Uuid::new_v4(), user_id, casino_id, amount, created_at, received_at }
+}
+
+/// Whether the first line of `contents` is exactly the `columns` header.
+fn has_header(contents: &str, columns: &[&str]) -> bool {
+    let first = contents.trim_start_matches('\u{feff}').lines().next().unwrap_or_default();
+    first.trim_end().split(',').map(str::trim).eq(columns.iter().copied())
+}
+
+/// Deserialize every row of a CSV export, `row` gets its line for messages.
+fn csv_rows<R: DeserializeOwned>(
+    contents: &str,
+    mut row: impl FnMut(u64, R) -> Result<(), String>,
+) -> Result<(), ImporterError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(contents.trim_start_matches('\u{feff}').as_bytes());
+    let headers = reader.headers()?.clone();
+    for record in reader.records() {
+        let record = record?;
+        let line = record.position().map_or(0, csv::Position::line);
+        let parsed = record.deserialize(Some(&headers))?;
+        row(line, parsed).map_err(|e| ImporterError::Invalid(format!("line {line}: {e}")))?;
+    }
+    Ok(())
+}
+
+fn date(value: &str) -> Result<NaiveDateTime, String> {
+    parse_date(value).ok_or_else(|| format!("date {value:?} is not a known format"))
+}
+
+/// Chumba Casino's transaction history CSV. Redemptions only have a request
+/// date, completed ones are marked received on it.
+pub struct ChumbaCsv;
+
+#[derive(serde::Deserialize)]
+struct ChumbaRow {
+    #[serde(rename = "Date")]
+    date:   String,
+    #[serde(rename = "Type")]
+    kind:   String,
+    #[serde(rename = "Amount")]
+    amount: String,
+    #[serde(rename = "Sweeps Coins")]
+    sweeps: String,
+    #[serde(rename = "Status")]
+    status: String,
+}
+
+impl Importer for ChumbaCsv {
+    fn name(&self) -> &'static str {
+        "chumba"
+    }
+
+    fn casino_url(&self) -> &'static str {
+        "chumbacasino.com"
+    }
+
+    fn detect(&self, contents: &str) -> bool {
+        has_header(contents, &["Date", "Type", "Description", "Amount", "Gold Coins", "Sweeps Coins", "Status"])
+    }
+
+    fn parse(&self, contents: &str, user_id: Uuid, casino_id: Uuid) -> Result<ImportRecords, ImporterError> {
+        let mut records = ImportRecords::default();
+        csv_rows(contents, |line, row: ChumbaRow| {
+            let completed = row.status == "Completed";
+            match row.kind.as_str() {
+                "Purchase" if completed => records.transactions.push(new_transaction(
+                    user_id,
+                    casino_id,
+                    parse_amount("Amount", &row.amount)?,
+                    parse_amount("Sweeps Coins", &row.sweeps)?,
+                    date(&row.date)?,
+                    format!("chumba export line {line}"),
+                )),
+                "Redemption" if completed || row.status == "Pending" => {
+                    let created_at = date(&row.date)?;
+                    records.redemptions.push(new_redemption(
+                        user_id,
+                        casino_id,
+                        parse_amount("Amount", &row.amount)?,
+                        created_at,
+                        completed.then_some(created_at),
+                    ));
+                }
+                _ => {}
+            }
+            Ok(())
+        })?;
+        Ok(records)
+    }
+}
+
+/// Pulsz's account history CSV.
+pub struct PulszCsv;
+
+#[derive(serde::Deserialize)]
+struct PulszRow {
+    transaction_id: String,
+    created_at:     String,
+    kind:           String,
+    usd_amount:     String,
+    sc_amount:      String,
+    state:          String,
+}
+
+impl Importer for PulszCsv {
+    fn name(&self) -> &'static str {
+        "pulsz"
+    }
+
+    fn casino_url(&self) -> &'static str {
+        "pulsz.com"
+    }
+
+    fn detect(&self, contents: &str) -> bool {
+        has_header(contents, &["transaction_id", "created_at", "kind", "usd_amount", "sc_amount", "gc_amount", "state"])
+    }
+
+    fn parse(&self, contents: &str, user_id: Uuid, casino_id: Uuid) -> Result<ImportRecords, ImporterError> {
+        let mut records = ImportRecords::default();
+        csv_rows(contents, |_, row: PulszRow| {
+            match (row.kind.as_str(), row.state.as_str()) {
+                ("purchase", "success") => records.transactions.push(new_transaction(
+                    user_id,
+                    casino_id,
+                    parse_amount("usd_amount", &row.usd_amount)?,
+                    parse_amount("sc_amount", &row.sc_amount)?,
+                    date(&row.created_at)?,
+                    format!("pulsz {}", row.transaction_id),
+                )),
+                ("redeem", "processing" | "paid") => {
+                    let created_at = date(&row.created_at)?;
+                    records.redemptions.push(new_redemption(
+                        user_id,
+                        casino_id,
+                        parse_amount("usd_amount", &row.usd_amount)?,
+                        created_at,
+                        (row.state == "paid").then_some(created_at),
+                    ));
+                }
+                _ => {}
+            }
+            Ok(())
+        })?;
+        Ok(records)
+    }
+}
+
+/// Stake.us's JSON account export, deposits are the coin purchases.
+pub struct StakeJson;
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StakeExport {
+    site:           String,
+    deposits:       Vec<StakeDeposit>,
+    withdrawals:    Vec<StakeWithdrawal>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StakeDeposit {
+    id:             String,
+    created_at:     String,
+    cost_usd:       String,
+    sweeps_coins:   String,
+    status:         String,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StakeWithdrawal {
+    id:             String,
+    created_at:     String,
+    amount:         String,
+    completed_at:   Option<String>,
+}
+
+impl Importer for StakeJson {
+    fn name(&self) -> &'static str {
+        "stake"
+    }
+
+    fn casino_url(&self) -> &'static str {
+        "stake.us"
+    }
+
+    fn detect(&self, contents: &str) -> bool {
+        serde_json::from_str::<serde_json::Value>(contents)
+            .is_ok_and(|export| export.get("site").and_then(serde_json::Value::as_str) == Some("stake.us"))
+    }
+
+    fn parse(&self, contents: &str, user_id: Uuid, casino_id: Uuid) -> Result<ImportRecords, ImporterError> {
+        let export: StakeExport = serde_json::from_str(contents)?;
+        if export.site != "stake.us" {
+            return Err(ImporterError::Invalid(format!("export is from {:?}, not stake.us", export.site)));
+        }
+        let invalid = |id: &str, e: String| ImporterError::Invalid(format!("{id}: {e}"));
+        let mut records = ImportRecords::default();
+        for deposit in export.deposits.iter().filter(|deposit| deposit.status == "confirmed") {
+            let parse = || {
+                Ok::<_, String>(new_transaction(
+                    user_id,
+                    casino_id,
+                    parse_amount("costUsd", &deposit.cost_usd)?,
+                    parse_amount("sweepsCoins", &deposit.sweeps_coins)?,
+                    date(&deposit.created_at)?,
+                    format!("stake {}", deposit.id),
+                ))
+            };
+            records.transactions.push(parse().map_err(|e| invalid(&deposit.id, e))?);
+        }
+        for withdrawal in &export.withdrawals {
+            let parse = || {
+                Ok::<_, String>(new_redemption(
+                    user_id,
+                    casino_id,
+                    parse_amount("amount", &withdrawal.amount)?,
+                    date(&withdrawal.created_at)?,
+                    withdrawal.completed_at.as_deref().map(date).transpose()?,
+                ))
+            };
+            records.redemptions.push(parse().map_err(|e| invalid(&withdrawal.id, e))?);
+        }
+        Ok(records)
+    }
+}
+
+/// History export imports for [`CasinoContext`]
+impl CasinoContext {
+    /// Import a casino's history export for `user_id`, in the named `format`
+    /// or the detected one. Everything is inserted in a single database transaction.
+    ///
+    /// # Errors
+    /// Will return `Err` if the format is unknown, the casino isn't in the
+    /// database, any record is invalid or the insert fails. Nothing is imported then.
+    #[tracing::instrument(skip(self, contents), err)]
+    pub async fn import_history(
+        &self,
+        user_id: Uuid,
+        contents: &str,
+        format: Option<&str>,
+    ) -> Result<HistoryImportReport, ImporterError> {
+        let importer = match format {
+            Some(name) => importer(name).ok_or_else(|| ImporterError::UnknownFormat(name.to_string()))?,
+            None => detect(contents).ok_or(ImporterError::Undetected)?,
+        };
+        let casinos = self.get_all_casinos().await?;
+        let casino = find_casino(&casinos, importer.casino_url())
+            .ok_or(ImporterError::UnknownCasino(importer.casino_url()))?;
+        let records = importer.parse(contents, user_id, casino.id)?;
+        let duplicates = self.insert_records(user_id, &records).await?;
+        let report = HistoryImportReport {
+            format: importer.name().to_string(),
+            casino_id: casino.id,
+            transactions: ImportCounts::count(&duplicates.transactions, 0),
+            redemptions: ImportCounts::count(&duplicates.redemptions, duplicates.received.len()),
+        };
+        tracing::info!("Imported {} history: {:?}", report.format, report);
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const CHUMBA: &str = include_str!("../test_fixtures/importers/chumba.csv");
+    const PULSZ: &str = include_str!("../test_fixtures/importers/pulsz.csv");
+    const STAKE: &str = include_str!("../test_fixtures/importers/stake.json");
+
+    backend_tests!(test_import_history);
+
+    fn amounts(records: &ImportRecords) -> (Vec<String>, Vec<String>) {
+        (
+            records.transactions.iter().map(|t| format!("{}/{}", t.cost, t.benefit)).collect(),
+            records.redemptions.iter().map(|r| format!("{}/{}", r.amount, r.received_at.is_some())).collect(),
+        )
+    }
+
+    #[test]
+    fn test_detect() {
+        for (contents, name) in [(CHUMBA, "chumba"), (PULSZ, "pulsz"), (STAKE, "stake")] {
+            assert_eq!(Some(name), detect(contents).map(Importer::name));
+            assert_eq!(Some(name), importer(&name.to_uppercase()).map(Importer::name));
+        }
+        assert!(detect("date,casino,cost,benefit\n").is_none());
+        assert!(detect(r#"{"site": "example.com"}"#).is_none());
+        assert!(importer("nope").is_none());
+    }
+
+    #[test]
+    fn test_parse_fixtures() {
+        let (user, casino) = (Uuid::new_v4(), Uuid::new_v4());
+
+        let chumba = ChumbaCsv.parse(CHUMBA, user, casino).unwrap();
+        assert_eq!(
+            (vec!["19.99/20".to_string(), "9.99/10".to_string()], vec!["100.00/true".to_string(), "50.00/false".to_string()]),
+            amounts(&chumba)
+        );
+        let first = &chumba.transactions[0];
+        assert_eq!((user, casino), (first.user_id, first.casino_id));
+        assert_eq!(parse_date("2026-03-01 14:22"), Some(first.created_at));
+
+        let pulsz = PulszCsv.parse(PULSZ, user, casino).unwrap();
+        assert_eq!(
+            (vec!["29.99/30".to_string()], vec!["150.00/false".to_string(), "75.00/true".to_string()]),
+            amounts(&pulsz)
+        );
+        assert_eq!(Some("pulsz p-1001"), pulsz.transactions[0].notes.as_deref());
+
+        let stake = StakeJson.parse(STAKE, user, casino).unwrap();
+        assert_eq!(
+            (vec!["49.99/50".to_string(), "99.99/105".to_string()], vec!["200/true".to_string(), "80.50/false".to_string()]),
+            amounts(&stake)
+        );
+        // Offsets are converted to UTC.
+        assert_eq!(parse_date("2026-01-05 21:30"), Some(stake.transactions[1].created_at));
+
+        let bad = PULSZ.replace("29.99", "lots");
+        assert!(matches!(PulszCsv.parse(&bad, user, casino), Err(ImporterError::Invalid(e)) if e.starts_with("line 2:")));
+        assert!(matches!(StakeJson.parse("[]", user, casino), Err(ImporterError::Json(_))));
+    }
+
+    async fn test_import_history(ctx: CasinoContext) -> sqlx::Result<()> {
+        let user_id = ctx.create_user().await?.id;
+        let report = ctx.import_history(user_id, PULSZ, None).await;
+        assert!(matches!(report, Err(ImporterError::UnknownCasino("pulsz.com"))));
+
+        let casino = ctx.create_casino("Pulsz", "https://www.pulsz.com/", "").await?;
+        let report = ctx.import_history(user_id, PULSZ, None).await.unwrap();
+        assert_eq!(("pulsz", casino.id), (report.format.as_str(), report.casino_id));
+        assert_eq!(ImportCounts { accepted: 1, updated: 0, duplicates: 0 }, report.transactions);
+        assert_eq!(ImportCounts { accepted: 2, updated: 0, duplicates: 0 }, report.redemptions);
+        assert_eq!(BigDecimal::from_str("29.99").unwrap(), ctx.get_transactions(user_id).await?[0].cost);
+        assert_eq!(1, ctx.get_pending_redemptions(user_id).await?.len());
+
+        // Uploading the export again inserts nothing.
+        let again = ctx.import_history(user_id, PULSZ, Some("pulsz")).await.unwrap();
+        assert_eq!(ImportCounts { accepted: 0, updated: 0, duplicates: 1 }, again.transactions);
+        assert_eq!(ImportCounts { accepted: 0, updated: 0, duplicates: 2 }, again.redemptions);
+
+        // A newer export has the pending redemption paid.
+        let paid = PULSZ.replace("150,0,processing", "150,0,paid");
+        let newer = ctx.import_history(user_id, &paid, None).await.unwrap();
+        assert_eq!(ImportCounts { accepted: 0, updated: 1, duplicates: 1 }, newer.redemptions);
+        assert!(ctx.get_pending_redemptions(user_id).await?.is_empty());
+        let again = ctx.import_history(user_id, &paid, None).await.unwrap();
+        assert_eq!(ImportCounts { accepted: 0, updated: 0, duplicates: 2 }, again.redemptions);
+
+        assert!(matches!(ctx.import_history(user_id, "a,b\n", None).await, Err(ImporterError::Undetected)));
+        assert!(matches!(ctx.import_history(user_id, PULSZ, Some("csv")).await, Err(ImporterError::UnknownFormat(_))));
+        Ok(())
+    }
+}
@@ -0,0 +1,581 @@
+//! Double entry journals for plain text accounting in ledger-cli and beancount.
+//!
+//! A purchase moves its cost out of the bank account and its sweeps coins into
+//! an account per casino, a received redemption moves the coins back out and
+//! the cash into the bank. Coins are priced with the total cost (`@@`) so every
+//! entry balances in the currency. Pending redemptions are left out until they
+//! are received.
+
+use std::fmt::{Display, Write};
+use std::sync::LazyLock;
+
+use bigdecimal::{BigDecimal, Zero};
+use chrono::NaiveDateTime;
+use regex::Regex;
+use uuid::Uuid;
+
+use crate::export::ExportFilter;
+use crate::{Casino, CasinoContext, Redemption, Transaction};
+
+/// Plain text accounting format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum JournalFormat {
+    /// ledger-cli, also read by hledger.
+    #[default]
+    Ledger,
+    Beancount,
+}
+
+impl JournalFormat {
+    /// File extension of a download.
+    #[must_use]
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Ledger => "ledger",
+            Self::Beancount => "beancount",
+        }
+    }
+}
+
+/// Account and commodity names of a journal.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::IntoParams)]
+#[serde(default)]
+#[into_params(parameter_in = Query)]
+pub struct JournalAccounts {
+    /// Account purchases are paid from and redemptions paid into.
+    pub bank:       String,
+    /// Parent of the coin account of each casino.
+    pub casinos:    String,
+    /// Currency of purchases and redemptions.
+    pub currency:   String,
+    /// Commodity of the sweeps coins.
+    pub coins:      String,
+}
+
+impl Default for JournalAccounts {
+    fn default() -> Self {
+        Self {
+            bank: "Assets:Bank:Checking".to_string(),
+            casinos: "Assets:Casinos".to_string(),
+            currency: "USD".to_string(),
+            coins: "SC".to_string(),
+        }
+    }
+}
+
+/// Beancount account names: a root type, then capitalized components.
+static BEANCOUNT_ACCOUNT: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^(Assets|Liabilities|Equity|Income|Expenses)(:[A-Z0-9][A-Za-z0-9-]*)+$").unwrap()
+});
+
+/// Commodities both formats read without quoting.
+static COMMODITY: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[A-Z]{2,24}$").unwrap());
+
+impl JournalAccounts {
+    /// Check the names are valid in `format`.
+    ///
+    /// # Errors
+    /// Will return `Err` naming the first invalid account or commodity.
+    pub fn validate(&self, format: JournalFormat) -> Result<(), JournalError> {
+        for account in [&self.bank, &self.casinos] {
+            let valid = match format {
+                JournalFormat::Beancount => BEANCOUNT_ACCOUNT.is_match(account),
+                // Ledger ends an account name at two spaces or a tab.
+                JournalFormat::Ledger => {
+                    !account.is_empty()
+                        && account.trim() == account
+                        && !account.contains("  ")
+                        && !account.contains(['\t', '\n', ';'])
+                        && !account.starts_with(':')
+                        && !account.ends_with(':')
+                }
+            };
+            if !valid {
+                return Err(JournalError::InvalidAccount(account.clone()));
+            }
+        }
+        for commodity in [&self.currency, &self.coins] {
+            if !COMMODITY.is_match(commodity) {
+                return Err(JournalError::InvalidCommodity(commodity.clone()));
+            }
+        }
+        if self.currency == self.coins {
+            return Err(JournalError::InvalidCommodity(self.coins.clone()));
+        }
+        Ok(())
+    }
+
+    /// Coin account of a casino, its name in CamelCase under [`Self::casinos`].
+    #[must_use]
+    pub fn casino_account(&self, casino_name: &str) -> String {
+        let mut component = String::new();
+        for word in casino_name.split(|c: char| !c.is_ascii_alphanumeric()) {
+            let mut chars = word.chars();
+            if let Some(first) = chars.next() {
+                component.push(first.to_ascii_uppercase());
+                component.extend(chars);
+            }
+        }
+        if component.is_empty() {
+            component.push_str("Casino");
+        }
+        format!("{}:{component}", self.casinos)
+    }
+}
+
+/// Errors from a journal export.
+#[derive(Debug)]
+pub enum JournalError {
+    InvalidAccount(String),
+    InvalidCommodity(String),
+    /// `from` is after `to`.
+    InvalidRange,
+    Sqlx(sqlx::Error),
+}
+
+impl Display for JournalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidAccount(account) => write!(f, "invalid account name {account:?}"),
+            Self::InvalidCommodity(commodity) => write!(f, "invalid commodity {commodity:?}"),
+            Self::InvalidRange => f.write_str("from is after to"),
+            Self::Sqlx(e) => write!(f, "database error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for JournalError {}
+
+impl From<sqlx::Error> for JournalError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Sqlx(e)
+    }
+}
+
+impl crate::error::RequestError for JournalError {
+    fn into_sqlx(self) -> Result<sqlx::Error, Self> {
+        match self {
+            Self::Sqlx(e) => Ok(e),
+            e => Err(e),
+        }
+    }
+}
+
+/// A journal entry before formatting.
+struct Entry<'a> {
+    time:       NaiveDateTime,
+    id:         Uuid,
+    payee:      &'a str,
+    narration:  &'static str,
+    notes:      Option<&'a str>,
+    /// Account and amount of each posting.
+    postings:   Vec<(String, String)>,
+}
+
+/// An amount without exponent notation.
+fn number(value: &BigDecimal) -> String {
+    let (_, scale) = value.as_bigint_and_exponent();
+    value.with_scale(scale.max(0)).to_string()
+}
+
+/// `value` in `commodity`.
+fn amount(value: &BigDecimal, commodity: &str) -> String {
+    format!("{} {commodity}", number(value))
+}
+
+/// A quoted beancount string.
+fn quoted(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace(['\n', '\r'], " "))
+}
+
+/// Render the purchases and received redemptions as a journal in `format`.
+///
+/// # Errors
+/// Will return `Err` if an account or commodity name is invalid in `format`.
+pub fn render(
+    format: JournalFormat,
+    accounts: &JournalAccounts,
+    casinos: &[Casino],
+    transactions: &[Transaction],
+    redemptions: &[Redemption],
+) -> Result<String, JournalError> {
+    accounts.validate(format)?;
+    let casino_name =
+        |id: Uuid| casinos.iter().find(|casino| casino.id == id).map_or("Unknown casino", |casino| casino.name.as_str());
+    let mut entries = vec![];
+    for t in transactions.iter().filter(|t| !(t.cost.is_zero() && t.benefit.is_zero())) {
+        let payee = casino_name(t.casino_id);
+        // Without coins the cost itself sits at the casino.
+        let coins = if t.benefit.is_zero() {
+            amount(&t.cost, &accounts.currency)
+        } else {
+            format!("{} @@ {}", amount(&t.benefit, &accounts.coins), amount(&t.cost, &accounts.currency))
+        };
+        entries.push(Entry {
+            time: t.created_at,
+            id: t.id,
+            payee,
+            narration: "Purchase",
+            notes: t.notes.as_deref().filter(|notes| !notes.trim().is_empty()),
+            postings: vec![
+                (accounts.casino_account(payee), coins),
+                (accounts.bank.clone(), amount(&-&t.cost, &accounts.currency)),
+            ],
+        });
+    }
+    for r in redemptions.iter().filter(|r| !r.amount.is_zero()) {
+        let Some(received_at) = r.received_at else { continue };
+        let payee = casino_name(r.casino_id);
+        entries.push(Entry {
+            time: received_at,
+            id: r.id,
+            payee,
+            narration: "Redemption",
+            notes: None,
+            postings: vec![
+                (accounts.bank.clone(), amount(&r.amount, &accounts.currency)),
+                (
+                    accounts.casino_account(payee),
+                    format!("{} @@ {}", amount(&-&r.amount, &accounts.coins), amount(&r.amount, &accounts.currency)),
+                ),
+            ],
+        });
+    }
+    entries.sort_by_key(|entry| (entry.time, entry.id));
+
+    let mut out = String::new();
+    if format == JournalFormat::Beancount {
+        writeln!(out, "option \"operating_currency\" {}", quoted(&accounts.currency)).unwrap();
+        if let Some(first) = entries.first() {
+            let mut opened: Vec<&str> = vec![];
+            out.push('\n');
+            for (account, _) in entries.iter().flat_map(|entry| &entry.postings) {
+                if !opened.contains(&account.as_str()) {
+                    opened.push(account);
+                    writeln!(out, "{} open {account}", first.time.date()).unwrap();
+                }
+            }
+        }
+    }
+    for entry in &entries {
+        out.push('\n');
+        let date = entry.time.date();
+        // Writing to a String doesn't fail.
+        match format {
+            JournalFormat::Ledger => {
+                let payee = entry.payee.replace(['\n', '\r', ';'], " ");
+                writeln!(out, "{date} * {payee}").unwrap();
+                writeln!(out, "    ; id: {}", entry.id).unwrap();
+                writeln!(out, "    ; type: {}", entry.narration.to_lowercase()).unwrap();
+                if let Some(notes) = entry.notes {
+                    writeln!(out, "    ; {}", notes.replace(['\n', '\r'], " ")).unwrap();
+                }
+                for (account, amount) in &entry.postings {
+                    writeln!(out, "    {account}  {amount}").unwrap();
+                }
+            }
+            JournalFormat::Beancount => {
+                writeln!(out, "{date} * {} {}", quoted(entry.payee), quoted(entry.narration)).unwrap();
+                writeln!(out, "  id: {}", quoted(&entry.id.to_string())).unwrap();
+                if let Some(notes) = entry.notes {
+                    writeln!(out, "  notes: {}", quoted(notes)).unwrap();
+                }
+                for (account, amount) in &entry.postings {
+                    writeln!(out, "  {account}  {amount}").unwrap();
+                }
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Journal exports for [`CasinoContext`]
+impl CasinoContext {
+    /// Render the purchases and received redemptions of `user_id` matching `filter` as a journal.
+    ///
+    /// # Errors
+    /// Will return `Err` if a name in `accounts` is invalid, the range is invalid or a query fails.
+    #[tracing::instrument(skip(self), err)]
+    pub async fn export_journal(
+        &self,
+        user_id: Uuid,
+        filter: &ExportFilter,
+        format: JournalFormat,
+        accounts: &JournalAccounts,
+    ) -> Result<String, JournalError> {
+        accounts.validate(format)?;
+        if matches!((filter.from, filter.to), (Some(from), Some(to)) if from > to) {
+            return Err(JournalError::InvalidRange);
+        }
+        let casinos = self.get_all_casinos().await?;
+        let (transactions, redemptions) = self.cash_flow(user_id, filter).await?;
+        render(format, accounts, &casinos, &transactions, &redemptions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    backend_tests!(test_export_journal);
+
+    /// A posting read back from a journal.
+    #[derive(Debug)]
+    struct Posting {
+        account:    String,
+        units:      BigDecimal,
+        commodity:  String,
+        /// Total price of `@@`.
+        total:      Option<(BigDecimal, String)>,
+    }
+
+    /// An entry read back from a journal.
+    #[derive(Debug)]
+    struct Parsed {
+        date:       NaiveDate,
+        id:         Uuid,
+        payee:      String,
+        postings:   Vec<Posting>,
+    }
+
+    /// An amount read by ledger-parser, whose decimals are `rust_decimal`.
+    fn ledger_amount(amount: &ledger_parser::Amount) -> (BigDecimal, String) {
+        (BigDecimal::from_str(&amount.quantity.to_string()).unwrap(), amount.commodity.name.clone())
+    }
+
+    /// Read a ledger journal with ledger-parser.
+    fn parse_ledger(journal: &str) -> Result<Vec<Parsed>, String> {
+        let ledger = ledger_parser::parse(journal).map_err(|e| e.to_string())?;
+        let mut entries = vec![];
+        for item in ledger.items {
+            let transaction = match item {
+                ledger_parser::LedgerItem::Transaction(transaction) => transaction,
+                ledger_parser::LedgerItem::EmptyLine => continue,
+                item => return Err(format!("unexpected item {item:?}")),
+            };
+            let id = transaction.posting_metadata.tags.iter().find_map(|tag| match &tag.value {
+                Some(ledger_parser::TagValue::String(id)) if tag.name == "id" => Some(id.as_str()),
+                _ => None,
+            });
+            let id = id.ok_or(format!("no id tag in {:?}", transaction.posting_metadata))?;
+            let mut postings = vec![];
+            for posting in transaction.postings {
+                let amount = posting.amount.ok_or(format!("{} has no amount", posting.account))?;
+                let (units, commodity) = ledger_amount(&amount.amount);
+                let total = match amount.price {
+                    Some(ledger_parser::Price::Total(total)) => Some(ledger_amount(&total)),
+                    Some(price) => return Err(format!("unexpected price {price:?}")),
+                    None => None,
+                };
+                postings.push(Posting { account: posting.account, units, commodity, total });
+            }
+            entries.push(Parsed {
+                date: transaction.date,
+                id: Uuid::parse_str(id).map_err(|e| e.to_string())?,
+                payee: transaction.description.unwrap_or_default(),
+                postings,
+            });
+        }
+        Ok(entries)
+    }
+
+    /// Read a beancount journal with beancount-parser, checking every account
+    /// is opened before it's used.
+    fn parse_beancount(journal: &str) -> Result<Vec<Parsed>, String> {
+        let file: beancount_parser::BeancountFile<BigDecimal> = journal.parse().map_err(|e| format!("{e:?}"))?;
+        let mut opened: HashMap<String, NaiveDate> = HashMap::new();
+        let mut entries = vec![];
+        for directive in file.directives {
+            let date = NaiveDate::from_ymd_opt(directive.date.year.into(), directive.date.month.into(), directive.date.day.into())
+                .ok_or(format!("invalid date {:?}", directive.date))?;
+            let transaction = match directive.content {
+                beancount_parser::DirectiveContent::Open(open) => {
+                    opened.insert(open.account.as_str().to_string(), date);
+                    continue;
+                }
+                beancount_parser::DirectiveContent::Transaction(transaction) => transaction,
+                content => return Err(format!("unexpected directive {content:?}")),
+            };
+            let id = match directive.metadata.get("id") {
+                Some(beancount_parser::metadata::Value::String(id)) => Uuid::parse_str(id).map_err(|e| e.to_string())?,
+                id => return Err(format!("invalid id {id:?}")),
+            };
+            let mut postings = vec![];
+            for posting in transaction.postings {
+                let account = posting.account.as_str().to_string();
+                if opened.get(&account).is_none_or(|open| *open > date) {
+                    return Err(format!("{account} isn't open on {date}"));
+                }
+                let amount = posting.amount.ok_or(format!("{account} has no amount"))?;
+                let total = match posting.price {
+                    Some(beancount_parser::PostingPrice::Total(total)) => Some((total.value, total.currency.as_str().to_string())),
+                    Some(price) => return Err(format!("unexpected price {price:?}")),
+                    None => None,
+                };
+                postings.push(Posting { account, units: amount.value, commodity: amount.currency.as_str().to_string(), total });
+            }
+            entries.push(Parsed { date, id, payee: transaction.payee.unwrap_or_default(), postings });
+        }
+        Ok(entries)
+    }
+
+    /// Read a journal with a real parser of `format`, checking that every entry balances.
+    fn parse(format: JournalFormat, journal: &str) -> Result<Vec<Parsed>, String> {
+        let entries = match format {
+            JournalFormat::Ledger => parse_ledger(journal)?,
+            JournalFormat::Beancount => parse_beancount(journal)?,
+        };
+        for entry in &entries {
+            let mut weights: HashMap<&str, BigDecimal> = HashMap::new();
+            for posting in &entry.postings {
+                let (weight, commodity) = match &posting.total {
+                    Some((total, commodity)) if posting.units < BigDecimal::zero() => (-total, commodity),
+                    Some((total, commodity)) => (total.clone(), commodity),
+                    None => (posting.units.clone(), &posting.commodity),
+                };
+                *weights.entry(commodity).or_default() += weight;
+            }
+            if weights.values().any(|weight| !weight.is_zero()) {
+                return Err(format!("{} doesn't balance: {weights:?}", entry.id));
+            }
+        }
+        Ok(entries)
+    }
+
+    fn decimal(value: &str) -> BigDecimal {
+        BigDecimal::from_str(value).unwrap()
+    }
+
+    fn time(day: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 3, day).unwrap().and_hms_opt(12, 0, 0).unwrap()
+    }
+
+    fn casino(name: &str) -> Casino {
+        Casino {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            url: String::new(),
+            description: String::new(),
+            created_at: time(1),
+            updated_at: time(1),
+        }
+    }
+
+    fn transaction(casino: &Casino, cost: &str, benefit: &str, day: u32, notes: Option<&str>) -> Transaction {
+        Transaction {
+            id: Uuid::new_v4(),
+            user_id: Uuid::nil(),
+            casino_id: casino.id,
+            cost: decimal(cost),
+            benefit: decimal(benefit),
+            created_at: time(day),
+            updated_at: time(day),
+            notes: notes.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_render_round_trip() {
+        let chumba = casino("Chumba Casino");
+        let stake = casino("Stake.us");
+        let casinos = [chumba.clone(), stake.clone()];
+        let transactions = [
+            transaction(&chumba, "19.99", "20", 2, Some("the \"best\" deal; \\o/\nagain")),
+            transaction(&stake, "5.00", "0", 1, None),
+            transaction(&stake, "0", "0", 1, None),
+        ];
+        let redeem = |amount: &str, received_at| Redemption {
+            id: Uuid::new_v4(),
+            user_id: Uuid::nil(),
+            casino_id: chumba.id,
+            amount: decimal(amount),
+            created_at: time(3),
+            received_at,
+        };
+        let redemptions = [redeem("100", Some(time(5))), redeem("50", None)];
+        let accounts = JournalAccounts::default();
+
+        for format in [JournalFormat::Ledger, JournalFormat::Beancount] {
+            let journal = render(format, &accounts, &casinos, &transactions, &redemptions).unwrap();
+            let entries = parse(format, &journal).unwrap_or_else(|e| panic!("{e}\n{journal}"));
+            // Empty purchases and pending redemptions are left out, entries are in date order.
+            let ids: Vec<Uuid> = entries.iter().map(|entry| entry.id).collect();
+            assert_eq!(vec![transactions[1].id, transactions[0].id, redemptions[0].id], ids);
+            assert_eq!("Chumba Casino", entries[1].payee);
+
+            let purchase = &entries[1].postings;
+            assert_eq!("Assets:Casinos:ChumbaCasino", purchase[0].account);
+            assert_eq!((decimal("20"), "SC"), (purchase[0].units.clone(), purchase[0].commodity.as_str()));
+            assert_eq!(Some((decimal("19.99"), "USD".to_string())), purchase[0].total);
+            assert_eq!(decimal("-19.99"), purchase[1].units);
+
+            let no_coins = &entries[0].postings;
+            assert_eq!("Assets:Casinos:StakeUs", no_coins[0].account);
+            assert_eq!((decimal("5.00"), "USD"), (no_coins[0].units.clone(), no_coins[0].commodity.as_str()));
+
+            let redemption = &entries[2].postings;
+            assert_eq!(("Assets:Bank:Checking", decimal("100")), (redemption[0].account.as_str(), redemption[0].units.clone()));
+            assert_eq!(decimal("-100"), redemption[1].units);
+            assert_eq!(entries[2].date, time(5).date());
+        }
+    }
+
+    #[test]
+    fn test_accounts() {
+        let accounts = JournalAccounts::default();
+        assert_eq!("Assets:Casinos:StakeUs", accounts.casino_account("Stake.us"));
+        assert_eq!("Assets:Casinos:Casino", accounts.casino_account("!!"));
+        let with = |bank: &str, coins: &str| JournalAccounts {
+            bank: bank.to_string(),
+            coins: coins.to_string(),
+            ..JournalAccounts::default()
+        };
+        assert!(with("Assets:Bank Account", "SC").validate(JournalFormat::Ledger).is_ok());
+        for (accounts, format) in [
+            (with("Assets:Bank Account", "SC"), JournalFormat::Beancount),
+            (with("Assets:bank", "SC"), JournalFormat::Beancount),
+            (with("Bank:Checking", "SC"), JournalFormat::Beancount),
+            (with("Assets:Bank  Checking", "SC"), JournalFormat::Ledger),
+            (with("Assets:Bank", "sc"), JournalFormat::Ledger),
+            (with("Assets:Bank", "USD"), JournalFormat::Ledger),
+        ] {
+            assert!(accounts.validate(format).is_err(), "{accounts:?}");
+        }
+    }
+
+    async fn test_export_journal(ctx: CasinoContext) -> sqlx::Result<()> {
+        let user_id = Uuid::parse_str("d61b6bba-61ba-4cab-b8b7-74a880968ec6").unwrap();
+        let received = chrono::Utc::now().naive_utc();
+        ctx.create_redemption(user_id, Uuid::nil(), BigDecimal::from(25), Some(received)).await?;
+        let accounts = JournalAccounts { bank: "Assets:Cash".to_string(), ..JournalAccounts::default() };
+
+        let journal =
+            ctx.export_journal(user_id, &ExportFilter::default(), JournalFormat::Beancount, &accounts).await.unwrap();
+        let entries = parse(JournalFormat::Beancount, &journal).unwrap();
+        assert_eq!(2, entries.len());
+        assert!(entries.iter().all(|entry| entry.payee == "Test"));
+        assert!(journal.contains("open Assets:Casinos:Test"));
+
+        // A redemption received days after it was requested is in the range of the later day only.
+        let later = received + chrono::Duration::days(3);
+        ctx.create_redemption(user_id, Uuid::nil(), BigDecimal::from(40), Some(later)).await?;
+        for (day, count) in [(received.date(), 2), (later.date(), 1)] {
+            let filter = ExportFilter { from: Some(day), to: Some(day), ..ExportFilter::default() };
+            let journal = ctx.export_journal(user_id, &filter, JournalFormat::Ledger, &accounts).await.unwrap();
+            assert_eq!(count, parse(JournalFormat::Ledger, &journal).unwrap().len(), "{day}");
+        }
+
+        let other = ExportFilter { casino_id: Some(Uuid::new_v4()), ..ExportFilter::default() };
+        let journal = ctx.export_journal(user_id, &other, JournalFormat::Ledger, &accounts).await.unwrap();
+        assert!(parse(JournalFormat::Ledger, &journal).unwrap().is_empty());
+
+        let bad = JournalAccounts { casinos: "casinos".to_string(), ..JournalAccounts::default() };
+        let err = ctx.export_journal(user_id, &other, JournalFormat::Beancount, &bad).await.unwrap_err();
+        assert!(matches!(err, JournalError::InvalidAccount(_)));
+        Ok(())
+    }
+}
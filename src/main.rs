@@ -1,11 +1,126 @@
-use casino_buddy::run;
+use casino_buddy::{run, CasinoContext, Config, ConfigArgs, StateFile};
+use clap::{Parser, Subcommand};
+use std::path::PathBuf;
+
+/// Casino Buddy API server and administration tool.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(flatten)]
+    config: ConfigArgs,
+    /// Print the resolved config (with the database password hidden) and exit.
+    #[arg(long, global = true)]
+    print_config: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Start the API server, this is the default.
+    Serve,
+    /// Apply pending database migrations.
+    Migrate,
+    /// Load the demo casinos, user and transactions.
+    Seed,
+    /// Create a new user and print it as json.
+    CreateUser,
+    /// Add a casino and print it as json.
+    AddCasino {
+        /// Display name of the casino.
+        #[arg(long)]
+        name: String,
+        /// Website of the casino.
+        #[arg(long)]
+        url: String,
+        #[arg(long, default_value = "")]
+        description: String,
+    },
+    /// Export every table to a state file, `-` writes to stdout.
+    Export {
+        #[arg(default_value = "-")]
+        path: PathBuf,
+    },
+    /// Import a state file, rows that already exist are skipped.
+    Import { path: PathBuf },
+    /// Check that the database is reachable and its schema matches this build.
+    CheckDb,
+}
+
+/// Run an administrative command against the configured database.
+async fn admin(command: Command, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let ctx = CasinoContext::from_config(config)?;
+    match command {
+        Command::Serve => unreachable!("serve is handled by run"),
+        Command::Migrate => {
+            let status = ctx.migrate().await?;
+            println!("Migrations applied, schema is at version {:?}", status.version);
+        }
+        Command::Seed => {
+            let summary = ctx.seed().await?;
+            println!("{}", serde_json::to_string_pretty(&summary)?);
+        }
+        Command::CreateUser => {
+            let user = ctx.create_user().await?;
+            println!("{}", serde_json::to_string_pretty(&user)?);
+        }
+        Command::AddCasino { name, url, description } => {
+            let casino = ctx.create_casino(&name, &url, &description).await?;
+            println!("{}", serde_json::to_string_pretty(&casino)?);
+        }
+        Command::Export { path } => {
+            let state = ctx.export_state().await?;
+            if path.as_os_str() == "-" {
+                println!("{}", state.to_json()?);
+            } else {
+                state.write(&path)?;
+                eprintln!("Exported state to {}", path.display());
+            }
+        }
+        Command::Import { path } => {
+            let summary = ctx.import_state(&StateFile::read(&path)?).await?;
+            println!("{}", serde_json::to_string_pretty(&summary)?);
+        }
+        Command::CheckDb => {
+            ctx.ping().await?;
+            let status = ctx.check_migrations().await?;
+            println!("Database is reachable, schema is at version {:?}", status.version);
+            if !status.pending.is_empty() {
+                println!("Pending migrations: {:?}", status.pending);
+            }
+        }
+    }
+    Ok(())
+}
 
 #[tokio::main]
 async fn main() {
-    // TODO: Add logging
-    // TODO: Print some system / config info
-    println!("Starting Casino Buddy server...");
-    if let Err(e) = run().await {
-        eprintln!("Error: {e:?}");
+    let cli = Cli::parse();
+    let config = match Config::load(&cli.config) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Error: {e}");
+            std::process::exit(2);
+        }
+    };
+    if cli.print_config {
+        print!("{}", config.to_redacted_toml());
+        return;
+    }
+
+    let result = match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => {
+            println!(
+                "Starting Casino Buddy server v{} on {}...",
+                env!("CARGO_PKG_VERSION"),
+                config.bind
+            );
+            run(config).await
+        }
+        command => admin(command, &config).await,
+    };
+    if let Err(e) = result {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
     }
 }
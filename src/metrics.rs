@@ -0,0 +1,202 @@
+//! Prometheus metrics, served in the text exposition format at `/metrics`.
+
+use std::sync::LazyLock;
+
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use warp::Reply;
+
+use crate::versioning::API_VERSIONS;
+use crate::CasinoContext;
+
+/// First path segments of the routes we serve, anything else is labelled `unmatched`.
+const ROUTES: &[&str] = &["health", "metrics", "user", "transaction", "redemption", "bonus", "summary", "casino", "import", "export", "reconcile", "report", "openapi.json"];
+
+/// Path segments that are part of a route rather than an id.
+const STATIC_SEGMENTS: &[&str] = &["live", "ready", "transactions", "history", "redemptions", "bonuses", "sessions", "journal", "statement", "link", "tax"];
+
+/// Every metric the server exports.
+pub struct Metrics {
+    registry: Registry,
+    pub http_requests: IntCounterVec,
+    pub http_request_duration: HistogramVec,
+    pub rejections: IntCounterVec,
+    pub db_pool_connections: IntGaugeVec,
+    pub db_pool_max_connections: IntGauge,
+    pub transactions_created: IntCounter,
+    pub redemptions_logged: IntCounter,
+}
+
+/// The process wide metrics.
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+impl Metrics {
+    fn new() -> Self {
+        // Registering only fails on duplicate or malformed names, which are fixed here.
+        let registry = Registry::new_custom(Some("casino_buddy".to_string()), None).unwrap();
+        let metrics = Self {
+            http_requests: IntCounterVec::new(
+                Opts::new("http_requests_total", "HTTP requests by route and status."),
+                &["method", "route", "status"],
+            )
+            .unwrap(),
+            http_request_duration: HistogramVec::new(
+                HistogramOpts::new("http_request_duration_seconds", "HTTP request latency by route."),
+                &["method", "route"],
+            )
+            .unwrap(),
+            rejections: IntCounterVec::new(
+                Opts::new("http_rejections_total", "Rejections handled by the rejection handler, by cause."),
+                &["error"],
+            )
+            .unwrap(),
+            db_pool_connections: IntGaugeVec::new(
+                Opts::new("db_pool_connections", "Open database connections by state."),
+                &["state"],
+            )
+            .unwrap(),
+            db_pool_max_connections: IntGauge::new("db_pool_max_connections", "Maximum database connections.")
+                .unwrap(),
+            transactions_created: IntCounter::new("transactions_created_total", "Transactions created.").unwrap(),
+            redemptions_logged: IntCounter::new("redemptions_logged_total", "Redemptions logged.").unwrap(),
+            registry,
+        };
+        metrics.registry.register(Box::new(metrics.http_requests.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.http_request_duration.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.rejections.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.db_pool_connections.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.db_pool_max_connections.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.transactions_created.clone())).unwrap();
+        metrics.registry.register(Box::new(metrics.redemptions_logged.clone())).unwrap();
+        metrics
+    }
+
+    /// Render every metric in the Prometheus text format.
+    #[must_use]
+    pub fn render(&self) -> String {
+        let mut buffer = vec![];
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            tracing::error!("Failed to encode metrics: {}", e);
+        }
+        String::from_utf8(buffer).unwrap_or_default()
+    }
+}
+
+/// Route label for a request path. Every dynamic segment becomes `:id`, valid or not,
+/// so the label stays bounded whatever clients send. The API version prefix is kept.
+pub(crate) fn route_label(path: &str) -> String {
+    let mut segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+    let version = match segments.first() {
+        Some(first) if API_VERSIONS.contains(first) => format!("/{}", segments.remove(0)),
+        _ => String::new(),
+    };
+    match segments.first() {
+        None if version.is_empty() => return "/".to_string(),
+        None => return "unmatched".to_string(),
+        Some(first) if !ROUTES.contains(first) || segments.len() > 3 => return "unmatched".to_string(),
+        Some(_) => {}
+    }
+    let route: String = segments
+        .iter()
+        .enumerate()
+        .map(|(i, segment)| {
+            if i == 0 || STATIC_SEGMENTS.contains(segment) {
+                format!("/{segment}")
+            } else {
+                "/:id".to_string()
+            }
+        })
+        .collect();
+    version + &route
+}
+
+/// Record a finished request, used with [`warp::log::custom`].
+#[allow(clippy::needless_pass_by_value)]
+pub(crate) fn record_request(info: warp::log::Info<'_>) {
+    let route = route_label(info.path());
+    let method = info.method().as_str();
+    METRICS
+        .http_requests
+        .with_label_values(&[method, &route, info.status().as_str()])
+        .inc();
+    METRICS
+        .http_request_duration
+        .with_label_values(&[method, &route])
+        .observe(info.elapsed().as_secs_f64());
+}
+
+/// Count a rejection reaching the rejection handler.
+pub(crate) fn record_rejection(error: &str) {
+    METRICS.rejections.with_label_values(&[error]).inc();
+}
+
+/// Metrics for [`CasinoContext`]
+impl CasinoContext {
+    /// Process a scrape, the pool gauges are refreshed first.
+    pub(crate) fn process_metrics(&self) -> impl Reply {
+        let pool = self.db.pool_stats();
+        METRICS
+            .db_pool_connections
+            .with_label_values(&["idle"])
+            .set(i64::from(pool.idle));
+        METRICS
+            .db_pool_connections
+            .with_label_values(&["in_use"])
+            .set(i64::from(pool.size.saturating_sub(pool.idle)));
+        METRICS.db_pool_max_connections.set(i64::from(pool.max));
+        warp::reply::with_header(METRICS.render(), "content-type", prometheus::TEXT_FORMAT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{metrics_filter, redemption_post_filter};
+    use warp::http::StatusCode;
+
+    backend_tests!(test_req_metrics);
+
+    #[test]
+    fn test_route_label() {
+        assert_eq!("/", route_label("/"));
+        assert_eq!("/casino", route_label("/casino"));
+        assert_eq!("/health/ready", route_label("/health/ready"));
+        assert_eq!(
+            "/transaction/:id/:id",
+            route_label("/transaction/d61b6bba-61ba-4cab-b8b7-74a880968ec6/00000000-0000-0000-0000-000000000000")
+        );
+        assert_eq!("/user/:id", route_label("/user/not-a-uuid"));
+        assert_eq!("/v1/user/:id", route_label("/v1/user/not-a-uuid"));
+        assert_eq!("/v2/casino", route_label("/v2/casino"));
+        assert_eq!("/v1/import/transactions", route_label("/v1/import/transactions"));
+        assert_eq!("unmatched", route_label("/v1"));
+        assert_eq!("unmatched", route_label("/wp-admin/login.php"));
+        assert_eq!("unmatched", route_label("/user/a/b/c"));
+    }
+
+    async fn test_req_metrics(ctx: CasinoContext) -> sqlx::Result<()> {
+        let logged = METRICS.redemptions_logged.get();
+        let res = warp::test::request()
+            .method("POST")
+            .path("/redemption/d61b6bba-61ba-4cab-b8b7-74a880968ec6/00000000-0000-0000-0000-000000000000")
+            .json(&serde_json::json!({ "amount": "50.00" }))
+            .reply(&redemption_post_filter(ctx.clone()).await)
+            .await;
+        assert_eq!(StatusCode::CREATED, res.status());
+        let redemption: crate::Redemption = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(bigdecimal::BigDecimal::from(50), redemption.amount);
+        assert_eq!(None, redemption.received_at);
+        // Other tests share the counters, so only check that ours was counted.
+        assert!(METRICS.redemptions_logged.get() > logged);
+
+        let res = warp::test::request().path("/metrics").reply(&metrics_filter(ctx).await).await;
+        assert_eq!(StatusCode::OK, res.status());
+        let body = String::from_utf8(res.body().to_vec()).unwrap();
+        assert!(body.contains("casino_buddy_redemptions_logged_total"));
+        assert!(body.contains("casino_buddy_db_pool_max_connections"));
+        assert!(body.contains(r#"casino_buddy_db_pool_connections{state="idle"}"#));
+        Ok(())
+    }
+}
@@ -0,0 +1,250 @@
+//! Database migrations embedded in the binary.
+//!
+//! The server checks the applied migrations against the embedded ones before it
+//! starts, and refuses to run against a database that is ahead of this build or
+//! whose migrations were modified after being applied.
+
+use std::fmt::Display;
+
+use sqlx::migrate::{MigrateError, Migrator};
+
+use crate::{CasinoContext, Db};
+
+/// Removed migrations that databases may still have applied.
+///
+/// `20240930015306_basic_test_data` inserted the nil test user, the "Test"
+/// casino and a transaction into every database, that data now lives in
+/// `test_fixtures/`. Editing it into a no-op would change its checksum, so it
+/// was removed instead: databases that applied it keep its rows, which can be
+/// deleted by hand, and its entry in `_sqlx_migrations`.
+const RETIRED: &[i64] = &[20_240_930_015_306];
+
+/// Postgres migrations from `migrations/`. Missing migrations are ignored when
+/// running them so [`RETIRED`] ones don't fail, [`check`] rejects any others first.
+pub static MIGRATOR: Migrator = Migrator { ignore_missing: true, ..sqlx::migrate!("./migrations") };
+
+/// SQLite migrations from `migrations_sqlite/`, ignoring missing ones like [`MIGRATOR`].
+#[cfg(feature = "sqlite")]
+pub static SQLITE_MIGRATOR: Migrator = Migrator { ignore_missing: true, ..sqlx::migrate!("./migrations_sqlite") };
+
+/// How the applied migrations compare to the embedded ones.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize)]
+pub struct MigrationStatus {
+    /// Latest applied migration version, `None` for an empty database.
+    pub version: Option<i64>,
+    /// Embedded migrations that haven't been applied yet.
+    pub pending: Vec<i64>,
+}
+
+/// Reasons the database schema can't be used by this build.
+#[derive(Debug)]
+pub enum MigrationError {
+    /// The database has migrations this build doesn't know about.
+    Ahead(Vec<i64>),
+    /// Applied migrations whose contents changed since they were applied.
+    Modified(Vec<i64>),
+    /// A migration failed part way through.
+    Dirty(i64),
+    Migrate(MigrateError),
+}
+
+impl Display for MigrationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Ahead(versions) => write!(
+                f,
+                "database has migrations {versions:?} that this build doesn't know about, refusing to start"
+            ),
+            Self::Modified(versions) => write!(f, "applied migrations {versions:?} were modified, refusing to start"),
+            Self::Dirty(version) => write!(f, "migration {version} was only partially applied"),
+            Self::Migrate(e) => write!(f, "migration failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+impl From<MigrateError> for MigrationError {
+    fn from(e: MigrateError) -> Self {
+        Self::Migrate(e)
+    }
+}
+
+impl From<sqlx::Error> for MigrationError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Migrate(e.into())
+    }
+}
+
+/// Applied migrations, oldest first, as `(version, checksum, success)`.
+const APPLIED_SQL: &str = "SELECT version, checksum, success FROM _sqlx_migrations ORDER BY version";
+
+/// Compare the `applied` migrations with the ones in `migrator`.
+fn check(migrator: &Migrator, applied: &[(i64, Vec<u8>, bool)]) -> Result<MigrationStatus, MigrationError> {
+    if let Some((version, ..)) = applied.iter().find(|(_, _, success)| !success) {
+        return Err(MigrationError::Dirty(*version));
+    }
+
+    let ahead: Vec<i64> = applied
+        .iter()
+        .filter(|(version, ..)| !migrator.version_exists(*version) && !RETIRED.contains(version))
+        .map(|(version, ..)| *version)
+        .collect();
+    if !ahead.is_empty() {
+        return Err(MigrationError::Ahead(ahead));
+    }
+
+    let modified: Vec<i64> = migrator
+        .iter()
+        .filter(|migration| {
+            applied
+                .iter()
+                .any(|(version, checksum, _)| *version == migration.version && **checksum != *migration.checksum)
+        })
+        .map(|migration| migration.version)
+        .collect();
+    if !modified.is_empty() {
+        return Err(MigrationError::Modified(modified));
+    }
+
+    Ok(MigrationStatus {
+        version: applied.iter().map(|(version, ..)| *version).max(),
+        pending: migrator
+            .iter()
+            .filter(|migration| !migration.migration_type.is_down_migration())
+            .filter(|migration| !applied.iter().any(|(version, ..)| *version == migration.version))
+            .map(|migration| migration.version)
+            .collect(),
+    })
+}
+
+/// Migration operations for [`CasinoContext`]
+impl CasinoContext {
+    /// Apply any pending migrations for the configured backend.
+    ///
+    /// # Errors
+    /// Will return `Err` if a migration fails or the database has drifted from the embedded migrations.
+    pub async fn migrate(&self) -> Result<MigrationStatus, MigrationError> {
+        self.check_migrations().await?;
+        match &*self.db {
+            Db::Postgres(pool) => MIGRATOR.run(pool).await?,
+            #[cfg(feature = "sqlite")]
+            Db::Sqlite(pool) => SQLITE_MIGRATOR.run(pool).await?,
+        }
+        self.check_migrations().await
+    }
+
+    /// Compare the applied migrations with the embedded ones without applying
+    /// anything. Only reads, so a database without the migrations table has
+    /// every migration pending.
+    ///
+    /// # Errors
+    /// Will return `Err` if the database is ahead of this build or an applied migration was modified.
+    pub async fn check_migrations(&self) -> Result<MigrationStatus, MigrationError> {
+        match &*self.db {
+            Db::Postgres(pool) => {
+                let exists: bool = sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations') IS NOT NULL")
+                    .fetch_one(pool)
+                    .await?;
+                let applied = if exists { sqlx::query_as(APPLIED_SQL).fetch_all(pool).await? } else { vec![] };
+                check(&MIGRATOR, &applied)
+            }
+            #[cfg(feature = "sqlite")]
+            Db::Sqlite(pool) => {
+                let exists: bool = sqlx::query_scalar(
+                    "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations')",
+                )
+                .fetch_one(pool)
+                .await?;
+                let applied = if exists { sqlx::query_as(APPLIED_SQL).fetch_all(pool).await? } else { vec![] };
+                check(&SQLITE_MIGRATOR, &applied)
+            }
+        }
+    }
+
+    /// Get the schema ready to serve requests, optionally applying pending migrations first.
+    ///
+    /// # Errors
+    /// Will return `Err` if the database has drifted from the embedded migrations.
+    pub async fn prepare_schema(&self, apply: bool) -> Result<MigrationStatus, MigrationError> {
+        let status = if apply {
+            self.migrate().await?
+        } else {
+            self.check_migrations().await?
+        };
+        if status.pending.is_empty() {
+            tracing::info!("Database schema is at version {:?}", status.version);
+        } else {
+            tracing::warn!(
+                "Database schema is at version {:?}, migrations {:?} are pending",
+                status.version,
+                status.pending
+            );
+        }
+        Ok(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    backend_tests!(test_check_migrations);
+
+    /// Run a statement that doesn't need any bind parameters on either backend.
+    async fn execute(ctx: &CasinoContext, sql: &str) -> sqlx::Result<()> {
+        match &*ctx.db {
+            Db::Postgres(pool) => sqlx::raw_sql(sql).execute(pool).await.map(|_| ()),
+            #[cfg(feature = "sqlite")]
+            Db::Sqlite(pool) => sqlx::raw_sql(sql).execute(pool).await.map(|_| ()),
+        }
+    }
+
+    async fn test_check_migrations(ctx: CasinoContext) -> sqlx::Result<()> {
+        // The test harness applies every embedded migration.
+        let status = ctx.check_migrations().await.unwrap();
+        assert!(status.pending.is_empty());
+        assert_eq!(MIGRATOR.iter().map(|m| m.version).max(), status.version);
+        assert_eq!(status, ctx.migrate().await.unwrap());
+
+        // Databases that applied a retired migration still check and migrate.
+        execute(
+            &ctx,
+            &format!(
+                "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+                SELECT {}, 'basic test data', success, checksum, execution_time
+                FROM _sqlx_migrations LIMIT 1",
+                RETIRED[0]
+            ),
+        )
+        .await?;
+        assert_eq!(status, ctx.check_migrations().await.unwrap());
+        assert_eq!(status, ctx.migrate().await.unwrap());
+
+        let latest = status.version.unwrap();
+        let tamper = format!("UPDATE _sqlx_migrations SET checksum = substr(checksum, 1, 4) WHERE version = {latest}");
+        execute(&ctx, &tamper).await?;
+        assert!(matches!(ctx.check_migrations().await, Err(MigrationError::Modified(v)) if v == vec![latest]));
+        assert!(ctx.prepare_schema(true).await.is_err());
+
+        execute(&ctx, &format!("DELETE FROM _sqlx_migrations WHERE version = {latest}")).await?;
+        assert_eq!(vec![latest], ctx.check_migrations().await.unwrap().pending);
+
+        execute(
+            &ctx,
+            "INSERT INTO _sqlx_migrations (version, description, success, checksum, execution_time)
+            SELECT 99990101000000, 'from the future', success, checksum, execution_time
+            FROM _sqlx_migrations LIMIT 1",
+        )
+        .await?;
+        assert!(matches!(ctx.check_migrations().await, Err(MigrationError::Ahead(v)) if v == vec![99_990_101_000_000]));
+        assert!(ctx.prepare_schema(false).await.is_err());
+
+        // Without the migrations table everything is pending, and checking doesn't create it.
+        execute(&ctx, "DROP TABLE _sqlx_migrations").await?;
+        let status = ctx.check_migrations().await.unwrap();
+        assert_eq!((None, MIGRATOR.iter().count()), (status.version, status.pending.len()));
+        assert!(execute(&ctx, "SELECT * FROM _sqlx_migrations").await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,247 @@
+//! OpenAPI document for the HTTP API, served at `/openapi.json`.
+//!
+//! The document is generated from the `#[utoipa::path]` annotations on the
+//! filters in [`crate::filter`]. A copy is committed as `openapi.json` so
+//! client generators and reviewers can see API changes in diffs, a test keeps
+//! the two in sync.
+
+use utoipa::{Modify, OpenApi};
+
+use crate::error::{BadRequest, ErrorBody, NotFound};
+use crate::filter;
+use crate::versioning::API_VERSIONS;
+
+/// The API description, see the module docs.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Casino Buddy API",
+        description = "Track sweepstakes casino play, transactions and redemptions.\n\n\
+            The original unversioned paths (`/user`, `/casino`, ...) are deprecated aliases of their `/v1` routes.",
+        license(name = "BSD-2-Clause", identifier = "BSD-2-Clause"),
+    ),
+    paths(
+        filter::hello_filter,
+        filter::health_live_filter,
+        filter::health_ready_filter,
+        filter::metrics_filter,
+        filter::openapi_filter,
+    ),
+    nest(
+        (path = "/v1", api = V1Api),
+        (path = "/v2", api = V2Api),
+    ),
+    components(schemas(ErrorBody<BadRequest>, ErrorBody<NotFound>)),
+    modifiers(&VersionedOperationIds),
+    tags(
+        (name = "user", description = "Users"),
+        (name = "casino", description = "Supported casinos"),
+        (name = "transaction", description = "Purchases and their bonuses"),
+        (name = "redemption", description = "Prize redemptions"),
+        (name = "bonus", description = "Daily bonus claims"),
+        (name = "summary", description = "Spend and benefit per casino"),
+        (name = "import", description = "Bulk imports of historical records"),
+        (name = "export", description = "Downloads of a user's records"),
+        (name = "reconcile", description = "Bank statements matched with purchases and redemptions"),
+        (name = "report", description = "Yearly reports"),
+        (name = "operations", description = "Health checks, metrics and this document"),
+    ),
+)]
+pub struct ApiDoc;
+
+/// Routes served under `/v1`, the original ones also at the deprecated unversioned paths.
+#[derive(OpenApi)]
+#[openapi(paths(
+    filter::post_user_filter,
+    filter::get_user_filter,
+    filter::casino_list_filter,
+    filter::transaction_get_filter,
+    filter::transaction_post_filter,
+    filter::redemption_post_filter,
+    filter::bonus_post_filter,
+    filter::summary_get_filter,
+    filter::import_transactions_filter,
+    filter::import_history_filter,
+    filter::export_journal_filter,
+    filter::export_statement_filter,
+    filter::export_filter,
+    filter::reconcile_filter,
+    filter::reconcile_link_filter,
+    filter::tax_report_filter,
+))]
+struct V1Api;
+
+/// Routes served under `/v2`, the `/v1` ones with the changed replies swapped in.
+#[derive(OpenApi)]
+#[openapi(paths(
+    filter::post_user_filter,
+    filter::get_user_filter,
+    filter::casino_list_filter,
+    filter::transaction_get_v2_filter,
+    filter::transaction_post_filter,
+    filter::redemption_post_filter,
+    filter::bonus_post_filter,
+    filter::summary_get_filter,
+    filter::import_transactions_filter,
+    filter::import_history_filter,
+    filter::export_journal_filter,
+    filter::export_statement_filter,
+    filter::export_filter,
+    filter::reconcile_filter,
+    filter::reconcile_link_filter,
+    filter::tax_report_filter,
+))]
+struct V2Api;
+
+/// Prefixes the operation ids of versioned routes with their version, so the
+/// routes each version shares stay unique.
+struct VersionedOperationIds;
+
+impl Modify for VersionedOperationIds {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        for (path, item) in &mut openapi.paths.paths {
+            let Some(version) = path
+                .split('/')
+                .nth(1)
+                .filter(|segment| API_VERSIONS.contains(segment))
+            else {
+                continue;
+            };
+            for operation in [&mut item.get, &mut item.post].into_iter().flatten() {
+                if let Some(id) = &mut operation.operation_id {
+                    *id = format!("{version}_{id}");
+                }
+            }
+        }
+    }
+}
+
+/// The document rendered as pretty printed json.
+#[must_use]
+pub fn spec_json() -> String {
+    // Only fails for maps with non string keys, which the document doesn't have.
+    ApiDoc::openapi().to_pretty_json().expect("openapi document serializes")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CasinoContext;
+    use std::collections::BTreeSet;
+    use warp::http::StatusCode;
+
+    backend_tests!(test_req_openapi_routes);
+
+    /// Routes `get_app` serves outside the versioned API, update with `get_app`.
+    const UNVERSIONED_ROUTES: &[(&str, &str)] = &[
+        ("GET", "/health"),
+        ("GET", "/health/live"),
+        ("GET", "/health/ready"),
+        ("GET", "/metrics"),
+        ("GET", "/openapi.json"),
+    ];
+
+    /// Routes `get_app` serves under every version in [`API_VERSIONS`], update with `get_app`.
+    const VERSIONED_ROUTES: &[(&str, &str)] = &[
+        ("POST", "/bonus/{user_id}/{casino_id}"),
+        ("GET", "/casino"),
+        ("GET", "/export/journal"),
+        ("GET", "/export/statement"),
+        ("GET", "/export/{kind}"),
+        ("POST", "/import/history"),
+        ("POST", "/import/transactions"),
+        ("POST", "/reconcile"),
+        ("POST", "/reconcile/link"),
+        ("POST", "/redemption/{user_id}/{casino_id}"),
+        ("GET", "/report/tax/{year}"),
+        ("GET", "/summary/{user_id}"),
+        ("GET", "/transaction/{user_id}"),
+        ("POST", "/transaction/{user_id}/{casino_id}"),
+        ("POST", "/user"),
+        ("GET", "/user/{user_id}"),
+    ];
+
+    /// Versioned routes also served at their deprecated unversioned paths,
+    /// which the document only mentions in its description.
+    const LEGACY_ROUTES: &[(&str, &str)] = &[
+        ("GET", "/casino"),
+        ("GET", "/transaction/{user_id}"),
+        ("POST", "/transaction/{user_id}/{casino_id}"),
+        ("POST", "/redemption/{user_id}/{casino_id}"),
+        ("POST", "/user"),
+        ("GET", "/user/{user_id}"),
+    ];
+
+    /// Set `UPDATE_OPENAPI=1` to rewrite the committed copy after changing the API.
+    #[test]
+    fn test_openapi_snapshot() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+        let spec = spec_json() + "\n";
+        if std::env::var_os("UPDATE_OPENAPI").is_some() {
+            std::fs::write(path, &spec).unwrap();
+        }
+        let committed = std::fs::read_to_string(path).unwrap_or_default();
+        assert!(
+            committed == spec,
+            "openapi.json is out of date, rerun the tests with UPDATE_OPENAPI=1"
+        );
+    }
+
+    async fn test_req_openapi_routes(ctx: CasinoContext) -> sqlx::Result<()> {
+        let app = crate::get_app(&ctx).await;
+
+        let res = warp::test::request().path("/openapi.json").reply(&app).await;
+        assert_eq!(StatusCode::OK, res.status());
+        let served: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!(serde_json::to_value(ApiDoc::openapi()).unwrap(), served);
+
+        // The served routes are exactly the documented ones.
+        let mut documented = BTreeSet::new();
+        for (path, item) in &ApiDoc::openapi().paths.paths {
+            let methods = [("GET", &item.get), ("POST", &item.post)];
+            for (method, _) in methods.iter().filter(|(_, operation)| operation.is_some()) {
+                documented.insert((method.to_string(), path.clone()));
+            }
+        }
+        let mut served: BTreeSet<(String, String)> =
+            UNVERSIONED_ROUTES.iter().map(|(method, path)| (method.to_string(), path.to_string())).collect();
+        for version in API_VERSIONS {
+            served.extend(VERSIONED_ROUTES.iter().map(|(method, path)| (method.to_string(), format!("/{version}{path}"))));
+        }
+        assert_eq!(
+            Vec::<&(String, String)>::new(),
+            served.difference(&documented).collect::<Vec<_>>(),
+            "served but not documented"
+        );
+        assert_eq!(
+            Vec::<&(String, String)>::new(),
+            documented.difference(&served).collect::<Vec<_>>(),
+            "documented but not served"
+        );
+        assert!(LEGACY_ROUTES.iter().all(|route| VERSIONED_ROUTES.contains(route)));
+
+        // Every listed route is routed, whatever it makes of the empty body.
+        let legacy = LEGACY_ROUTES.iter().map(|(method, path)| (method.to_string(), path.to_string()));
+        let id = "d61b6bba-61ba-4cab-b8b7-74a880968ec6";
+        for (method, path) in served.into_iter().chain(legacy) {
+            let path = path
+                .replace("{user_id}", id)
+                .replace("{casino_id}", id)
+                .replace("{year}", "2026")
+                .replace("{kind}", "transactions");
+            let res = warp::test::request().method(&method).path(&path).reply(&app).await;
+            assert_ne!(StatusCode::NOT_FOUND, res.status(), "{method} {path}");
+            assert_ne!(StatusCode::METHOD_NOT_ALLOWED, res.status(), "{method} {path}");
+        }
+
+        // Unlisted routes aren't.
+        for (method, path) in [("GET", "/v3/casino"), ("GET", "/summary/00000000-0000-0000-0000-000000000000")] {
+            let res = warp::test::request().method(method).path(path).reply(&app).await;
+            assert_eq!(StatusCode::NOT_FOUND, res.status(), "{method} {path}");
+        }
+
+        let res = warp::test::request().method("DELETE").path("/casino").reply(&app).await;
+        assert_eq!(StatusCode::METHOD_NOT_ALLOWED, res.status());
+        Ok(())
+    }
+}
@@ -0,0 +1,337 @@
+//! Token bucket rate limiting, applied once per request in front of every route.
+//!
+//! Routes are split into a read group (`GET`) and a write group (everything
+//! else), each with its own [`RateLimit`]. A request spends a token from the
+//! bucket of its client IP. Requests aren't authenticated, so the user a route
+//! acts on isn't a key: anyone could empty another user's bucket. Health
+//! checks, metrics and the API document are never limited.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use warp::http::Method;
+use warp::path::FullPath;
+use warp::reject::{Reject, Rejection};
+use warp::Filter;
+
+use crate::server::RemoteAddr;
+
+/// How often full buckets are dropped.
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Most client IPs a group tracks, others are limited until the next prune.
+const MAX_BUCKETS: usize = 100_000;
+
+/// Paths that are never rate limited.
+const EXEMPT_ROUTES: &[&str] = &["health", "metrics", "openapi.json"];
+
+/// Requests allowed per period for a route group, written like `30/min` or `off`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum RateLimit {
+    Off,
+    /// Up to `requests` at once, refilled evenly over `period`.
+    Limit { requests: u32, period: Duration },
+}
+
+impl RateLimit {
+    #[must_use]
+    pub fn per_minute(requests: u32) -> Self {
+        Self::Limit { requests, period: Duration::from_secs(60) }
+    }
+}
+
+impl FromStr for RateLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "off" {
+            return Ok(Self::Off);
+        }
+        let err = || format!("invalid rate limit {s:?}, expected `off` or `<requests>/<s|min|h>`");
+        let (requests, unit) = s.split_once('/').ok_or_else(err)?;
+        let requests: u32 = requests.trim().parse().map_err(|_| err())?;
+        let period = match unit.trim() {
+            "s" | "sec" => Duration::from_secs(1),
+            "m" | "min" => Duration::from_secs(60),
+            "h" | "hour" => Duration::from_secs(60 * 60),
+            _ => return Err(err()),
+        };
+        if requests == 0 {
+            return Err(err());
+        }
+        Ok(Self::Limit { requests, period })
+    }
+}
+
+impl TryFrom<String> for RateLimit {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl Display for RateLimit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Off => f.write_str("off"),
+            Self::Limit { requests, period } => match period.as_secs() {
+                1 => write!(f, "{requests}/s"),
+                60 => write!(f, "{requests}/min"),
+                3600 => write!(f, "{requests}/h"),
+                secs => write!(f, "{requests}/{secs}s"),
+            },
+        }
+    }
+}
+
+impl From<RateLimit> for String {
+    fn from(limit: RateLimit) -> Self {
+        limit.to_string()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+#[derive(Debug)]
+struct Buckets {
+    by_ip: HashMap<IpAddr, Bucket>,
+    pruned: Instant,
+}
+
+/// Token buckets for one route group.
+#[derive(Debug)]
+pub struct RateLimiter {
+    capacity: f64,
+    /// Tokens added per second.
+    rate: f64,
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    /// A limiter for `limit`, `None` when the limit is off.
+    #[must_use]
+    pub fn new(limit: RateLimit) -> Option<Self> {
+        match limit {
+            RateLimit::Off => None,
+            RateLimit::Limit { requests, period } => Some(Self {
+                capacity: f64::from(requests),
+                rate: f64::from(requests) / period.as_secs_f64(),
+                buckets: Mutex::new(Buckets { by_ip: HashMap::new(), pruned: Instant::now() }),
+            }),
+        }
+    }
+
+    /// Take a token for `ip` at `now`, or say how long until one is available.
+    ///
+    /// # Errors
+    /// Will return `Err` with the time to wait when the bucket is empty, or
+    /// when `ip` is new and the limiter tracks [`MAX_BUCKETS`] already.
+    #[allow(clippy::missing_panics_doc)]
+    pub fn check(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().expect("rate limiter lock poisoned");
+        let next_prune = buckets.pruned + PRUNE_INTERVAL;
+        if now >= next_prune {
+            // Full buckets are the same as new ones, drop them.
+            buckets.by_ip.retain(|_, bucket| self.refill(bucket, now) < self.capacity);
+            buckets.pruned = now;
+        } else if buckets.by_ip.len() >= MAX_BUCKETS && !buckets.by_ip.contains_key(&ip) {
+            return Err(next_prune - now);
+        }
+        let bucket = buckets.by_ip.entry(ip).or_insert(Bucket { tokens: self.capacity, updated: now });
+        bucket.tokens = self.refill(bucket, now);
+        bucket.updated = now;
+        if bucket.tokens < 1.0 {
+            return Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.rate));
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+
+    fn refill(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(bucket.updated).as_secs_f64();
+        (bucket.tokens + elapsed * self.rate).min(self.capacity)
+    }
+}
+
+/// The limiters for every route group, cheap to clone.
+#[derive(Debug, Clone, Default)]
+pub struct RateLimits {
+    read: Option<Arc<RateLimiter>>,
+    write: Option<Arc<RateLimiter>>,
+}
+
+impl RateLimits {
+    #[must_use]
+    pub fn new(read: RateLimit, write: RateLimit) -> Self {
+        Self {
+            read: RateLimiter::new(read).map(Arc::new),
+            write: RateLimiter::new(write).map(Arc::new),
+        }
+    }
+
+    /// Check a request against its group's limiter, requests without a client
+    /// address aren't limited.
+    fn check(&self, method: &Method, path: &str, ip: Option<IpAddr>) -> Result<(), Rejection> {
+        let mut segments = path.split('/').filter(|segment| !segment.is_empty());
+        if segments.next().is_some_and(|first| EXEMPT_ROUTES.contains(&first)) {
+            return Ok(());
+        }
+        let limiter = match *method {
+            Method::GET | Method::HEAD | Method::OPTIONS => &self.read,
+            _ => &self.write,
+        };
+        let (Some(limiter), Some(ip)) = (limiter, ip) else {
+            return Ok(());
+        };
+
+        limiter.check(ip, Instant::now()).map_err(|retry_after| {
+            tracing::warn!("Rate limited {}, retry after {:?}", ip, retry_after);
+            warp::reject::custom(RateLimited { retry_after })
+        })
+    }
+}
+
+/// Rejection for a request over its rate limit.
+#[derive(Debug)]
+pub struct RateLimited {
+    pub retry_after: Duration,
+}
+
+impl Reject for RateLimited {}
+
+impl RateLimited {
+    /// `Retry-After` in whole seconds, rounded up so clients don't retry too early.
+    #[must_use]
+    pub fn retry_after_secs(&self) -> u64 {
+        self.retry_after.as_secs() + u64::from(self.retry_after.subsec_nanos() > 0)
+    }
+}
+
+/// Filter that rejects requests over their group's rate limit.
+pub(crate) fn rate_limit_filter(limits: RateLimits) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::method()
+        .and(warp::path::full())
+        .and(warp::ext::optional::<RemoteAddr>())
+        .and_then(move |method: Method, path: FullPath, remote: Option<RemoteAddr>| {
+            let limits = limits.clone();
+            async move { limits.check(&method, path.as_str(), remote.map(|remote| remote.0.ip())) }
+        })
+        .untuple_one()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handle_rejection;
+    use crate::server::RemoteAddr;
+    use std::net::SocketAddr;
+    use warp::http::StatusCode;
+
+    #[test]
+    fn test_rate_limit_parse() {
+        assert_eq!(Ok(RateLimit::Off), "off".parse());
+        assert_eq!(Ok(RateLimit::per_minute(30)), "30/min".parse());
+        assert_eq!(
+            Ok(RateLimit::Limit { requests: 5, period: Duration::from_secs(1) }),
+            "5/s".parse()
+        );
+        assert!("0/min".parse::<RateLimit>().is_err());
+        assert!("30".parse::<RateLimit>().is_err());
+        assert!("30/fortnight".parse::<RateLimit>().is_err());
+        assert_eq!("30/min", RateLimit::per_minute(30).to_string());
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let limiter = RateLimiter::new("2/s".parse().unwrap()).unwrap();
+        let key = IpAddr::from([127, 0, 0, 1]);
+        let other = IpAddr::from([127, 0, 0, 2]);
+        let start = Instant::now();
+
+        assert!(limiter.check(key, start).is_ok());
+        assert!(limiter.check(key, start).is_ok());
+        let retry_after = limiter.check(key, start).unwrap_err();
+        assert_eq!(Duration::from_millis(500), retry_after);
+        // Other keys have their own bucket.
+        assert!(limiter.check(other, start).is_ok());
+        // Half a second refills one token.
+        assert!(limiter.check(key, start + Duration::from_millis(500)).is_ok());
+        assert!(limiter.check(key, start + Duration::from_millis(500)).is_err());
+        assert!(RateLimiter::new(RateLimit::Off).is_none());
+    }
+
+    #[test]
+    fn test_prune_buckets() {
+        let limiter = RateLimiter::new("2/s".parse().unwrap()).unwrap();
+        let start = Instant::now();
+        let ip = |n: usize| IpAddr::from(std::net::Ipv6Addr::from(n as u128));
+        {
+            let mut buckets = limiter.buckets.lock().unwrap();
+            buckets.pruned = start;
+            let full = Bucket { tokens: 2.0, updated: start };
+            buckets.by_ip.extend((0..MAX_BUCKETS).map(|n| (ip(n), full)));
+        }
+
+        // Known addresses go on, new ones wait for the next prune.
+        assert!(limiter.check(ip(0), start).is_ok());
+        let later = start + Duration::from_secs(10);
+        assert_eq!(Err(PRUNE_INTERVAL - Duration::from_secs(10)), limiter.check(ip(MAX_BUCKETS), later));
+        assert_eq!(MAX_BUCKETS, limiter.buckets.lock().unwrap().by_ip.len());
+
+        // The prune keeps only buckets that aren't full.
+        let later = start + PRUNE_INTERVAL;
+        assert!(limiter.check(ip(1), later).is_ok());
+        assert!(limiter.check(ip(1), later).is_ok());
+        assert!(limiter.check(ip(MAX_BUCKETS), later).is_ok());
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!((buckets.by_ip.len(), buckets.pruned), (2, later));
+    }
+
+    #[tokio::test]
+    async fn test_req_rate_limited() {
+        let limits = RateLimits::new(RateLimit::Off, RateLimit::per_minute(1));
+        let app = rate_limit_filter(limits)
+            .and(warp::any())
+            .map(warp::reply)
+            .recover(handle_rejection);
+        let remote = RemoteAddr(SocketAddr::from(([10, 0, 0, 1], 5000)));
+        let post = |path: &'static str| {
+            warp::test::request().method("POST").path(path).extension(remote)
+        };
+
+        assert_eq!(StatusCode::OK, post("/user").reply(&app).await.status());
+        let res = post("/user").reply(&app).await;
+        assert_eq!(StatusCode::TOO_MANY_REQUESTS, res.status());
+        assert_eq!("60", res.headers()["retry-after"]);
+        let body: serde_json::Value = serde_json::from_slice(res.body()).unwrap();
+        assert_eq!("TOO_MANY_REQUESTS", body["error"]);
+
+        // Reads and health checks aren't limited here.
+        let res = warp::test::request().path("/casino").extension(remote).reply(&app).await;
+        assert_eq!(StatusCode::OK, res.status());
+        let res = post("/health/ready").reply(&app).await;
+        assert_eq!(StatusCode::OK, res.status());
+
+        // Neither are requests without a client address.
+        let user = "/transaction/d61b6bba-61ba-4cab-b8b7-74a880968ec6/00000000-0000-0000-0000-000000000000";
+        assert_eq!(StatusCode::OK, warp::test::request().method("POST").path(user).reply(&app).await.status());
+        assert_eq!(StatusCode::OK, warp::test::request().method("POST").path(user).reply(&app).await.status());
+
+        // Other addresses have their own bucket, whatever user they act on.
+        let other = RemoteAddr(SocketAddr::from(([10, 0, 0, 2], 5000)));
+        let res = warp::test::request().method("POST").path(user).extension(other).reply(&app).await;
+        assert_eq!(StatusCode::OK, res.status());
+        let res = warp::test::request().method("POST").path(user).extension(other).reply(&app).await;
+        assert_eq!(StatusCode::TOO_MANY_REQUESTS, res.status());
+    }
+}
@@ -0,0 +1,831 @@
+//! Reconciliation of bank statements with the logged purchases and redemptions.
+//!
+//! A bank statement CSV has a date, a description and a signed amount per row,
+//! charges are negative and deposits positive. The casino of a row is found by
+//! its description, with the [`PAYEE_PATTERNS`] of the supported casinos or
+//! the casino's name. Charges are matched with transactions of the same casino
+//! and cost, deposits with redemptions of the same casino and amount, each
+//! taking the closest record within the match window. Pending redemptions
+//! match deposits up to [`PENDING_REDEMPTION_DAYS`] after they were requested.
+//!
+//! Rows the matcher gets wrong are fixed by linking them to a record, the
+//! [`BankLink`] is kept and wins over the matcher on the next upload.
+
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::sync::LazyLock;
+
+use bigdecimal::{BigDecimal, Zero};
+use chrono::NaiveDate;
+use regex::Regex;
+use uuid::Uuid;
+
+#[cfg(feature = "sqlite")]
+use crate::sqlite;
+use crate::export::ExportFilter;
+use crate::import::{find_casino, parse_amount, parse_date};
+use crate::{Casino, CasinoContext, Db, Redemption, Transaction};
+
+/// Days between a bank row and a record for them to match.
+pub const DEFAULT_MATCH_WINDOW_DAYS: u32 = 3;
+
+/// Longest match window a statement upload can ask for.
+pub const MAX_MATCH_WINDOW_DAYS: u32 = 365;
+
+/// Days after a pending redemption was requested in which a deposit can pay it.
+pub const PENDING_REDEMPTION_DAYS: i64 = 14;
+
+/// How to recognise a casino in bank descriptions.
+#[derive(Debug, Clone, Copy)]
+pub struct PayeePattern {
+    /// Url of the casino, matched against the casino table.
+    pub casino_url: &'static str,
+    /// Regex matching the descriptions of the casino's charges and deposits.
+    pub pattern:    &'static str,
+}
+
+/// Payee patterns of the supported casinos, which rarely bill under their own name.
+pub static PAYEE_PATTERNS: &[PayeePattern] = &[
+    PayeePattern { casino_url: "chumbacasino.com", pattern: r"(?i)chumba|\bvgw\b|virtual gaming worlds" },
+    PayeePattern { casino_url: "pulsz.com", pattern: r"(?i)pulsz|yellow social" },
+    PayeePattern { casino_url: "stake.us", pattern: r"(?i)\bstake\b" },
+    PayeePattern { casino_url: "wowvegas.com", pattern: r"(?i)wow\s*vegas" },
+];
+
+/// [`PAYEE_PATTERNS`] compiled, the patterns are fixed so they compile.
+static COMPILED_PATTERNS: LazyLock<Vec<(&PayeePattern, Regex)>> = LazyLock::new(|| {
+    PAYEE_PATTERNS.iter().map(|payee| (payee, Regex::new(payee.pattern).unwrap())).collect()
+});
+
+/// Header names of the bank statement columns, matched case insensitively.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::IntoParams)]
+#[serde(default)]
+#[into_params(parameter_in = Query)]
+pub struct BankColumns {
+    /// Posting date, defaults to `date`.
+    pub date:           String,
+    /// Payee or description, defaults to `description`.
+    pub description:    String,
+    /// Signed amount, negative for charges, defaults to `amount`.
+    pub amount:         String,
+}
+
+impl Default for BankColumns {
+    fn default() -> Self {
+        Self {
+            date: "date".to_string(),
+            description: "description".to_string(),
+            amount: "amount".to_string(),
+        }
+    }
+}
+
+/// Kind of record a bank row is matched with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RecordKind {
+    Transaction,
+    Redemption,
+}
+
+impl RecordKind {
+    /// Name stored in the database.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Transaction => "transaction",
+            Self::Redemption => "redemption",
+        }
+    }
+}
+
+/// A row of a bank statement.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct BankEntry {
+    /// Line of the row in the file, the header is line 1.
+    pub line:           u64,
+    pub date:           NaiveDate,
+    pub description:    String,
+    /// Negative for charges.
+    #[schema(value_type = String)]
+    pub amount:         BigDecimal,
+    /// The casino the description names, `None` for rows of other payees.
+    pub casino_id:      Option<Uuid>,
+}
+
+/// A transaction or redemption as it shows on a bank statement.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct AppEntry {
+    pub kind:       RecordKind,
+    pub id:         Uuid,
+    pub casino_id:  Uuid,
+    /// Negative cost of a transaction, amount of a redemption.
+    #[schema(value_type = String)]
+    pub amount:     BigDecimal,
+    /// Day of the purchase, or the day a redemption was received or requested when pending.
+    pub date:       NaiveDate,
+    /// A redemption that isn't received yet.
+    pub pending:    bool,
+}
+
+/// A bank row and the record it pays for.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct Match {
+    pub bank:       BankEntry,
+    pub record:     AppEntry,
+    /// Matched by a saved link rather than by the matcher.
+    pub linked:     bool,
+    /// Days from the record to the bank row.
+    pub days_apart: i64,
+}
+
+/// A casino row of the statement without a record.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct UnmatchedBank {
+    pub bank:       BankEntry,
+    /// Unmatched records of the same casino and amount, closest first, to link by hand.
+    pub candidates: Vec<AppEntry>,
+}
+
+/// Outcome of reconciling a bank statement.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct ReconcileReport {
+    pub matched:        Vec<Match>,
+    pub unmatched_bank: Vec<UnmatchedBank>,
+    /// Records in the period of the statement that no row pays for.
+    pub unmatched_app:  Vec<AppEntry>,
+    /// Rows that aren't from a known casino.
+    pub ignored:        usize,
+    /// Rows that couldn't be read, with the reason.
+    pub unreadable:     Vec<String>,
+}
+
+/// DB struct for a bank row linked to a record by hand.
+#[derive(Debug, Clone, sqlx::FromRow, serde::Serialize, serde::Deserialize, PartialEq, utoipa::ToSchema)]
+pub struct BankLink {
+    pub id:                 Uuid,
+    pub user_id:            Uuid,
+    /// `transaction` or `redemption`.
+    pub kind:               String,
+    pub record_id:          Uuid,
+    pub bank_date:          NaiveDate,
+    #[schema(value_type = String)]
+    pub bank_amount:        BigDecimal,
+    pub bank_description:   String,
+    pub created_at:         chrono::NaiveDateTime,
+}
+
+/// Struct for the json body linking a bank row to a record.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize, utoipa::ToSchema)]
+pub struct BankLinkCreate {
+    pub user_id:        String,
+    pub kind:           RecordKind,
+    pub record_id:      String,
+    /// Date of the bank row.
+    pub date:           NaiveDate,
+    /// Signed amount of the bank row.
+    #[schema(value_type = String)]
+    pub amount:         BigDecimal,
+    /// Description of the bank row.
+    pub description:    String,
+}
+
+/// Errors from reconciling a bank statement.
+#[derive(Debug)]
+pub enum ReconcileError {
+    /// The header couldn't be read.
+    Csv(csv::Error),
+    /// A column of the [`BankColumns`] isn't in the header.
+    MissingColumn(String),
+    /// The linked record isn't one of the user's.
+    UnknownRecord(Uuid),
+    /// The match window is longer than [`MAX_MATCH_WINDOW_DAYS`].
+    Window(u32),
+    Sqlx(sqlx::Error),
+}
+
+impl Display for ReconcileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Csv(e) => write!(f, "invalid csv: {e}"),
+            Self::MissingColumn(column) => write!(f, "the header has no {column:?} column"),
+            Self::UnknownRecord(id) => write!(f, "no record {id} of the user"),
+            Self::Window(days) => write!(f, "match window of {days} days is over {MAX_MATCH_WINDOW_DAYS}"),
+            Self::Sqlx(e) => write!(f, "database error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ReconcileError {}
+
+impl From<csv::Error> for ReconcileError {
+    fn from(e: csv::Error) -> Self {
+        Self::Csv(e)
+    }
+}
+
+impl From<sqlx::Error> for ReconcileError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Sqlx(e)
+    }
+}
+
+impl crate::error::RequestError for ReconcileError {
+    fn into_sqlx(self) -> Result<sqlx::Error, Self> {
+        match self {
+            Self::Sqlx(e) => Ok(e),
+            e => Err(e),
+        }
+    }
+}
+
+/// The casino a bank description names, by its payee pattern or its name.
+#[must_use]
+pub fn payee_casino(casinos: &[Casino], description: &str) -> Option<Uuid> {
+    let by_pattern = COMPILED_PATTERNS
+        .iter()
+        .filter(|(_, regex)| regex.is_match(description))
+        .find_map(|(payee, _)| find_casino(casinos, payee.casino_url));
+    if let Some(casino) = by_pattern {
+        return Some(casino.id);
+    }
+    // Short names like "Test" would match too many descriptions by accident.
+    let words = normalize(description);
+    casinos
+        .iter()
+        .map(|casino| (casino, normalize(&casino.name)))
+        .find(|(_, name)| name.len() >= 4 && format!(" {words} ").contains(&format!(" {name} ")))
+        .map(|(casino, _)| casino.id)
+}
+
+/// Lowercase words of `text`, separated by single spaces.
+fn normalize(text: &str) -> String {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Parse an amount with an optional sign, a leading `-` or parentheses, as negative.
+fn parse_signed(column: &str, value: &str) -> Result<BigDecimal, String> {
+    let value = value.trim();
+    let (negative, unsigned) = if let Some(inner) = value.strip_prefix('(').and_then(|v| v.strip_suffix(')')) {
+        (true, inner)
+    } else if let Some(rest) = value.strip_prefix('-') {
+        (true, rest)
+    } else {
+        (false, value.strip_prefix('+').unwrap_or(value))
+    };
+    let amount = parse_amount(column, unsigned).map_err(|_| format!("{column} {value:?} is not a number"))?;
+    Ok(if negative { -amount } else { amount })
+}
+
+/// Read the rows of a bank statement CSV with a header.
+///
+/// # Errors
+/// Will return `Err` if the header can't be read or lacks a column, rows that
+/// can't be read are listed in the second value instead.
+pub fn read_statement(
+    csv: &str,
+    columns: &BankColumns,
+    casinos: &[Casino],
+) -> Result<(Vec<BankEntry>, Vec<String>), ReconcileError> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(csv.as_bytes());
+    let header = reader.headers()?.clone();
+    let find = |name: &str| {
+        header
+            .iter()
+            .position(|column| column.trim().eq_ignore_ascii_case(name.trim()))
+            .ok_or_else(|| ReconcileError::MissingColumn(name.to_string()))
+    };
+    let (date, description, amount) = (find(&columns.date)?, find(&columns.description)?, find(&columns.amount)?);
+
+    let (mut entries, mut unreadable) = (vec![], vec![]);
+    for record in reader.records() {
+        let line = match &record {
+            Ok(record) => record.position().map_or(0, csv::Position::line),
+            Err(e) => e.position().map_or(0, csv::Position::line),
+        };
+        let entry = record.map_err(|e| e.to_string()).and_then(|record| {
+            let field = |i: usize| record.get(i).unwrap_or_default().trim().to_string();
+            let day = parse_date(&field(date))
+                .ok_or_else(|| format!("date {:?} is not a known format", field(date)))?
+                .date();
+            let description = field(description);
+            Ok(BankEntry {
+                line,
+                date: day,
+                casino_id: payee_casino(casinos, &description),
+                description,
+                amount: parse_signed("amount", &field(amount))?,
+            })
+        });
+        match entry {
+            Ok(entry) => entries.push(entry),
+            Err(reason) => unreadable.push(format!("line {line}: {reason}")),
+        }
+    }
+    Ok((entries, unreadable))
+}
+
+impl AppEntry {
+    fn transaction(transaction: &Transaction) -> Self {
+        Self {
+            kind: RecordKind::Transaction,
+            id: transaction.id,
+            casino_id: transaction.casino_id,
+            amount: -transaction.cost.clone(),
+            date: transaction.created_at.date(),
+            pending: false,
+        }
+    }
+
+    fn redemption(redemption: &Redemption) -> Self {
+        Self {
+            kind: RecordKind::Redemption,
+            id: redemption.id,
+            casino_id: redemption.casino_id,
+            amount: redemption.amount.clone(),
+            date: redemption.received_at.unwrap_or(redemption.created_at).date(),
+            pending: redemption.received_at.is_none(),
+        }
+    }
+
+    /// Days from the record to `bank` when it could pay for it within `window` days.
+    fn days_to(&self, bank: &BankEntry, window: i64) -> Option<i64> {
+        if Some(self.casino_id) != bank.casino_id || self.amount != bank.amount {
+            return None;
+        }
+        let days = (bank.date - self.date).num_days();
+        let fits = if self.pending { (0..=PENDING_REDEMPTION_DAYS).contains(&days) } else { days.abs() <= window };
+        fits.then_some(days)
+    }
+}
+
+/// Whether `link` was made for `bank`.
+fn links(link: &BankLink, bank: &BankEntry) -> bool {
+    link.bank_date == bank.date && link.bank_amount == bank.amount && link.bank_description.trim() == bank.description
+}
+
+/// Match bank rows with the records they pay for, saved links first and then
+/// the closest pairs within `window_days`.
+#[must_use]
+pub fn reconcile(
+    entries: Vec<BankEntry>,
+    transactions: &[Transaction],
+    redemptions: &[Redemption],
+    links_made: &[BankLink],
+    window_days: u32,
+) -> ReconcileReport {
+    let window = i64::from(window_days);
+    let records: Vec<AppEntry> = transactions
+        .iter()
+        .map(AppEntry::transaction)
+        .chain(redemptions.iter().map(AppEntry::redemption))
+        .collect();
+    let mut used_records: HashSet<usize> = HashSet::new();
+    let mut paired: Vec<Option<(usize, bool)>> = vec![None; entries.len()];
+
+    let mut used_links: HashSet<usize> = HashSet::new();
+    for (bank, pair) in entries.iter().zip(paired.iter_mut()) {
+        let found = links_made.iter().enumerate().find_map(|(l, link)| {
+            if used_links.contains(&l) || !links(link, bank) {
+                return None;
+            }
+            let r = records.iter().position(|record| record.kind.as_str() == link.kind && record.id == link.record_id)?;
+            (!used_records.contains(&r)).then_some((l, r))
+        });
+        if let Some((l, r)) = found {
+            used_links.insert(l);
+            used_records.insert(r);
+            *pair = Some((r, true));
+        }
+    }
+
+    // Closest pairs first, so a record goes to the row nearest to it.
+    let mut candidates: Vec<(i64, usize, usize)> = vec![];
+    for (b, bank) in entries.iter().enumerate().filter(|(b, _)| paired[*b].is_none()) {
+        for (r, record) in records.iter().enumerate().filter(|(r, _)| !used_records.contains(r)) {
+            if let Some(days) = record.days_to(bank, window) {
+                candidates.push((days.abs(), b, r));
+            }
+        }
+    }
+    candidates.sort_by_key(|&(distance, b, r)| (distance, b, records[r].date));
+    for (_, b, r) in candidates {
+        if paired[b].is_none() && used_records.insert(r) {
+            paired[b] = Some((r, false));
+        }
+    }
+
+    let mut report = ReconcileReport::default();
+    let span = entries.iter().map(|bank| bank.date).min().zip(entries.iter().map(|bank| bank.date).max());
+    let mut unmatched_bank = vec![];
+    for (bank, pair) in entries.into_iter().zip(paired) {
+        match pair {
+            Some((r, linked)) => report.matched.push(Match {
+                days_apart: (bank.date - records[r].date).num_days(),
+                record: records[r].clone(),
+                bank,
+                linked,
+            }),
+            None if bank.casino_id.is_none() || bank.amount.is_zero() => report.ignored += 1,
+            None => unmatched_bank.push(bank),
+        }
+    }
+    report.unmatched_bank = unmatched_bank
+        .into_iter()
+        .map(|bank| {
+            let mut candidates: Vec<&AppEntry> = records
+                .iter()
+                .enumerate()
+                .filter(|(r, record)| {
+                    !used_records.contains(r) && Some(record.casino_id) == bank.casino_id && record.amount == bank.amount
+                })
+                .map(|(_, record)| record)
+                .collect();
+            candidates.sort_by_key(|record| (bank.date - record.date).num_days().abs());
+            UnmatchedBank { candidates: candidates.into_iter().cloned().collect(), bank }
+        })
+        .collect();
+    if let Some((first, last)) = span {
+        let window = chrono::Duration::days(window);
+        let first = first.checked_sub_signed(window).unwrap_or(NaiveDate::MIN);
+        let last = last.checked_add_signed(window).unwrap_or(NaiveDate::MAX);
+        report.unmatched_app = records
+            .iter()
+            .enumerate()
+            .filter(|(r, record)| !used_records.contains(r) && !record.pending && (first..=last).contains(&record.date))
+            .map(|(_, record)| record.clone())
+            .collect();
+        report.unmatched_app.sort_by_key(|record| (record.date, record.id));
+    }
+    report
+}
+
+impl CasinoContext {
+    /// Match the rows of a bank statement CSV with the transactions and
+    /// redemptions of `user_id`.
+    ///
+    /// # Errors
+    /// Will return `Err` if the window is over [`MAX_MATCH_WINDOW_DAYS`], the
+    /// header can't be read or lacks a column, or a query fails.
+    #[tracing::instrument(skip(self, csv), err)]
+    pub async fn reconcile_statement(
+        &self,
+        user_id: Uuid,
+        csv: &str,
+        columns: &BankColumns,
+        window_days: u32,
+    ) -> Result<ReconcileReport, ReconcileError> {
+        if window_days > MAX_MATCH_WINDOW_DAYS {
+            return Err(ReconcileError::Window(window_days));
+        }
+        let casinos = self.get_all_casinos().await?;
+        let (entries, unreadable) = read_statement(csv, columns, &casinos)?;
+        let Some((first, last)) = entries.iter().map(|bank| bank.date).min().zip(entries.iter().map(|bank| bank.date).max())
+        else {
+            return Ok(ReconcileReport { unreadable, ..ReconcileReport::default() });
+        };
+        // Only records that can match a row: within the window of the statement,
+        // or pending and requested before a deposit could pay them.
+        let window = chrono::Days::new(window_days.into());
+        let span = ExportFilter { from: first.checked_sub_days(window), to: last.checked_add_days(window), casino_id: None };
+        let (mut transactions, mut redemptions) = self.cash_flow(user_id, &span).await?;
+        let requested = first - chrono::Duration::days(PENDING_REDEMPTION_DAYS)..=last;
+        redemptions.extend(
+            self.get_pending_redemptions(user_id)
+                .await?
+                .into_iter()
+                .filter(|redemption| requested.contains(&redemption.created_at.date())),
+        );
+        let links_made: Vec<BankLink> = self
+            .get_bank_links(user_id)
+            .await?
+            .into_iter()
+            .filter(|link| entries.iter().any(|bank| links(link, bank)))
+            .collect();
+        // Records linked to a row of the statement count however far from it they are.
+        let linked: HashSet<(&str, Uuid)> = links_made.iter().map(|link| (link.kind.as_str(), link.record_id)).collect();
+        let (linked_transactions, linked_redemptions) = self.get_linked_records(user_id).await?;
+        let known: HashSet<Uuid> = transactions.iter().map(|t| t.id).collect();
+        transactions.extend(linked_transactions.into_iter().filter(|t| {
+            linked.contains(&(RecordKind::Transaction.as_str(), t.id)) && !known.contains(&t.id)
+        }));
+        let known: HashSet<Uuid> = redemptions.iter().map(|r| r.id).collect();
+        redemptions.extend(linked_redemptions.into_iter().filter(|r| {
+            linked.contains(&(RecordKind::Redemption.as_str(), r.id)) && !known.contains(&r.id)
+        }));
+        let report = reconcile(entries, &transactions, &redemptions, &links_made, window_days);
+        Ok(ReconcileReport { unreadable, ..report })
+    }
+
+    /// Link a bank row to a transaction or redemption of `user_id`, replacing
+    /// an earlier link of the record.
+    ///
+    /// # Errors
+    /// Will return `Err` if the record isn't one of the user's or a query fails.
+    #[tracing::instrument(skip(self), err)]
+    pub async fn link_bank_entry(
+        &self,
+        user_id: Uuid,
+        kind: RecordKind,
+        record_id: Uuid,
+        date: NaiveDate,
+        amount: &BigDecimal,
+        description: &str,
+    ) -> Result<BankLink, ReconcileError> {
+        if !self.owns_record(user_id, kind, record_id).await? {
+            return Err(ReconcileError::UnknownRecord(record_id));
+        }
+        let description = description.trim();
+        let link = match &*self.db {
+            Db::Postgres(pool) => sqlx::query_as!(
+                    BankLink,
+                    r#"INSERT INTO bank_link (user_id, kind, record_id, bank_date, bank_amount, bank_description)
+                    VALUES ($1, $2, $3, $4, $5, $6)
+                    ON CONFLICT (user_id, kind, record_id) DO UPDATE SET
+                        bank_date = EXCLUDED.bank_date,
+                        bank_amount = EXCLUDED.bank_amount,
+                        bank_description = EXCLUDED.bank_description,
+                        created_at = CURRENT_TIMESTAMP
+                    RETURNING *"#,
+                    user_id,
+                    kind.as_str(),
+                    record_id,
+                    date,
+                    amount,
+                    description
+                )
+                .fetch_one(pool)
+                .await?,
+            #[cfg(feature = "sqlite")]
+            Db::Sqlite(pool) => sqlite::insert_bank_link(pool, user_id, kind, record_id, date, amount, description).await?,
+        };
+        Ok(link)
+    }
+
+    /// Get the bank rows `user_id` linked by hand.
+    ///
+    /// # Errors
+    /// Will return `Err` if the query fails.
+    #[tracing::instrument(skip(self), fields(db.system = self.db.backend()), err)]
+    pub async fn get_bank_links(&self, user_id: Uuid) -> Result<Vec<BankLink>, sqlx::Error> {
+        match &*self.db {
+            Db::Postgres(pool) => {
+                sqlx::query_as!(BankLink, "SELECT * FROM bank_link WHERE user_id = $1 ORDER BY created_at, id", user_id)
+                    .fetch_all(pool)
+                    .await
+            }
+            #[cfg(feature = "sqlite")]
+            Db::Sqlite(pool) => sqlite::get_bank_links(pool, user_id).await,
+        }
+    }
+
+    /// Get the transactions and redemptions of `user_id` that have a bank link.
+    #[tracing::instrument(skip(self), fields(db.system = self.db.backend()), err)]
+    async fn get_linked_records(&self, user_id: Uuid) -> Result<(Vec<Transaction>, Vec<Redemption>), sqlx::Error> {
+        match &*self.db {
+            Db::Postgres(pool) => {
+                let transactions = sqlx::query_as!(
+                    Transaction,
+                    r#"SELECT * FROM "transaction" WHERE user_id = $1
+                    AND id IN (SELECT record_id FROM bank_link WHERE user_id = $1 AND kind = 'transaction')"#,
+                    user_id
+                )
+                .fetch_all(pool)
+                .await?;
+                let redemptions = sqlx::query_as!(
+                    Redemption,
+                    r#"SELECT * FROM redemption WHERE user_id = $1
+                    AND id IN (SELECT record_id FROM bank_link WHERE user_id = $1 AND kind = 'redemption')"#,
+                    user_id
+                )
+                .fetch_all(pool)
+                .await?;
+                Ok((transactions, redemptions))
+            }
+            #[cfg(feature = "sqlite")]
+            Db::Sqlite(pool) => sqlite::get_linked_records(pool, user_id).await,
+        }
+    }
+
+    /// Whether the `kind` record `record_id` is one of `user_id`'s.
+    #[tracing::instrument(skip(self), fields(db.system = self.db.backend()), err)]
+    async fn owns_record(&self, user_id: Uuid, kind: RecordKind, record_id: Uuid) -> Result<bool, sqlx::Error> {
+        let row = match &*self.db {
+            Db::Postgres(pool) => match kind {
+                RecordKind::Transaction => sqlx::query_scalar!(
+                        r#"SELECT 1 AS "one!" FROM "transaction" WHERE id = $1 AND user_id = $2"#,
+                        record_id,
+                        user_id
+                    )
+                    .fetch_optional(pool)
+                    .await?,
+                RecordKind::Redemption => sqlx::query_scalar!(
+                        r#"SELECT 1 AS "one!" FROM redemption WHERE id = $1 AND user_id = $2"#,
+                        record_id,
+                        user_id
+                    )
+                    .fetch_optional(pool)
+                    .await?,
+            },
+            #[cfg(feature = "sqlite")]
+            Db::Sqlite(pool) => return sqlite::owns_record(pool, user_id, kind, record_id).await,
+        };
+        Ok(row.is_some())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+
+    backend_tests!(test_reconcile_statement);
+
+    const BANK: &str = include_str!("../test_fixtures/reconcile/bank.csv");
+
+    fn casino(name: &str, url: &str) -> Casino {
+        Casino {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            url: url.to_string(),
+            description: String::new(),
+            created_at: NaiveDateTime::default(),
+            updated_at: NaiveDateTime::default(),
+        }
+    }
+
+    fn at(date: &str) -> NaiveDateTime {
+        parse_date(date).unwrap()
+    }
+
+    fn purchase(casino: &Casino, cost: i32, date: &str) -> Transaction {
+        Transaction {
+            id: Uuid::new_v4(),
+            user_id: Uuid::nil(),
+            casino_id: casino.id,
+            cost: BigDecimal::from(cost),
+            benefit: BigDecimal::from(cost),
+            created_at: at(date),
+            updated_at: at(date),
+            notes: None,
+        }
+    }
+
+    fn redemption(casino: &Casino, amount: i32, created: &str, received: Option<&str>) -> Redemption {
+        Redemption {
+            id: Uuid::new_v4(),
+            user_id: Uuid::nil(),
+            casino_id: casino.id,
+            amount: BigDecimal::from(amount),
+            created_at: at(created),
+            received_at: received.map(at),
+        }
+    }
+
+    #[test]
+    fn test_payee_casino() {
+        let casinos = [casino("Chumba", "https://www.chumbacasino.com/"), casino("Wow Vegas", "wowvegas.com"), casino("Test", "test.com")];
+        assert_eq!(payee_casino(&casinos, "VGW*CHUMBA CASINO 844-123"), Some(casinos[0].id));
+        assert_eq!(payee_casino(&casinos, "ACH CREDIT VIRTUAL GAMING WORLDS"), Some(casinos[0].id));
+        assert_eq!(payee_casino(&casinos, "WOWVEGAS.COM PURCHASE"), Some(casinos[1].id));
+        assert_eq!(payee_casino(&casinos, "POS DEBIT TEST CASINO"), Some(casinos[2].id));
+        assert_eq!(payee_casino(&casinos, "CONTEST ENTRY"), None);
+        // Pulsz isn't in the casino table.
+        assert_eq!(payee_casino(&casinos, "PULSZ.COM"), None);
+    }
+
+    #[test]
+    fn test_read_statement() {
+        let casinos = [casino("Chumba", "chumbacasino.com"), casino("Stake", "stake.us")];
+        let (entries, unreadable) = read_statement(BANK, &BankColumns::default(), &casinos).unwrap();
+        assert_eq!(unreadable, vec!["line 7: amount \"twenty\" is not a number"]);
+        let amounts: Vec<_> = entries.iter().map(|entry| entry.amount.to_string()).collect();
+        assert_eq!(amounts, vec!["-19.99", "-50.00", "150.00", "-12.50", "-100"]);
+        assert_eq!(entries[0].casino_id, Some(casinos[0].id));
+        assert_eq!(entries[1].casino_id, Some(casinos[1].id));
+        assert_eq!(entries[3].casino_id, None);
+        assert_eq!(entries[2].date, NaiveDate::from_ymd_opt(2026, 3, 6).unwrap());
+        assert_eq!(entries[2].line, 4);
+
+        // Lines count the breaks inside quoted fields.
+        let quoted = "Date,Description,Amount\n2026-03-01,\"CHUMBA\nCASINO\",-10\n2026-03-02,Stake,twenty\n";
+        let (entries, unreadable) = read_statement(quoted, &BankColumns::default(), &casinos).unwrap();
+        assert_eq!(entries[0].line, 2);
+        assert_eq!(unreadable, vec!["line 4: amount \"twenty\" is not a number"]);
+
+        let columns = BankColumns { amount: "value".to_string(), ..BankColumns::default() };
+        let err = read_statement(BANK, &columns, &casinos).unwrap_err();
+        assert!(matches!(err, ReconcileError::MissingColumn(column) if column == "value"));
+    }
+
+    #[test]
+    fn test_reconcile() {
+        let casinos = [casino("Chumba", "chumbacasino.com"), casino("Stake", "stake.us")];
+        let (chumba, stake) = (&casinos[0], &casinos[1]);
+        let (entries, _) = read_statement(BANK, &BankColumns::default(), &casinos).unwrap();
+        let transactions = [
+            purchase(chumba, 20, "2026-03-01"),
+            // Same casino and cost, but outside the window of the charge unlike the next one.
+            purchase(stake, 50, "2026-02-27"),
+            purchase(stake, 50, "2026-03-02"),
+            // Outside the window of the statement.
+            purchase(chumba, 10, "2026-01-10"),
+            purchase(chumba, 30, "2026-03-04"),
+        ];
+        let redemptions = [redemption(stake, 150, "2026-02-25", None), redemption(chumba, 75, "2026-03-01", Some("2026-03-05"))];
+
+        let report = reconcile(entries.clone(), &transactions, &redemptions, &[], DEFAULT_MATCH_WINDOW_DAYS);
+        let matched: Vec<_> = report.matched.iter().map(|m| (m.bank.line, m.record.id, m.days_apart)).collect();
+        assert_eq!(matched, vec![(3, transactions[2].id, 1), (4, redemptions[0].id, 9)]);
+        // 19.99 isn't the cost of the Chumba purchase.
+        assert_eq!(report.unmatched_bank.len(), 1);
+        assert_eq!(report.unmatched_bank[0].bank.line, 2);
+        assert!(report.unmatched_bank[0].candidates.is_empty());
+        let unmatched: Vec<_> = report.unmatched_app.iter().map(|record| record.id).collect();
+        assert_eq!(unmatched, vec![transactions[1].id, transactions[0].id, transactions[4].id, redemptions[1].id]);
+        assert_eq!(report.ignored, 2);
+
+        // A link moves the charge to the earlier purchase, the other becomes a candidate.
+        let link = BankLink {
+            id: Uuid::new_v4(),
+            user_id: Uuid::nil(),
+            kind: "transaction".to_string(),
+            record_id: transactions[1].id,
+            bank_date: entries[1].date,
+            bank_amount: entries[1].amount.clone(),
+            bank_description: entries[1].description.clone(),
+            created_at: NaiveDateTime::default(),
+        };
+        let report = reconcile(entries.clone(), &transactions, &redemptions, &[link], DEFAULT_MATCH_WINDOW_DAYS);
+        let charge = report.matched.iter().find(|m| m.bank.line == 3).unwrap();
+        assert!(charge.linked);
+        assert_eq!((charge.record.id, charge.days_apart), (transactions[1].id, 4));
+        assert!(report.unmatched_app.iter().any(|record| record.id == transactions[2].id));
+
+        // A window past the calendar keeps every record in the statement's period.
+        let report = reconcile(entries, &transactions, &redemptions, &[], u32::MAX);
+        assert!(report.unmatched_app.iter().any(|record| record.id == transactions[4].id));
+    }
+
+    async fn test_reconcile_statement(ctx: CasinoContext) -> sqlx::Result<()> {
+        let user_id = Uuid::parse_str("d61b6bba-61ba-4cab-b8b7-74a880968ec6").unwrap();
+        let transaction = ctx.get_transactions(user_id).await?.remove(0);
+        let day = transaction.created_at.date();
+        let csv = format!(
+            "Posted,Payee,Amount\n{},POS DEBIT TEST CASINO,-100.00\n{},POS DEBIT TEST CASINO,-100.00\n",
+            day,
+            day.succ_opt().unwrap(),
+        );
+        let columns = BankColumns { date: "posted".to_string(), description: "payee".to_string(), ..BankColumns::default() };
+
+        let report = ctx.reconcile_statement(user_id, &csv, &columns, 1).await.unwrap();
+        assert_eq!(report.matched.len(), 1);
+        assert_eq!(report.matched[0].bank.line, 2);
+        assert!(!report.matched[0].linked);
+        assert_eq!(report.unmatched_bank.len(), 1);
+        assert!(report.unmatched_app.is_empty());
+
+        let bank = report.unmatched_bank[0].bank.clone();
+        let link = ctx.link_bank_entry(user_id, RecordKind::Transaction, transaction.id, bank.date, &bank.amount, &bank.description).await.unwrap();
+        assert_eq!((link.record_id, link.bank_date), (transaction.id, bank.date));
+        // Linking the record again replaces the link.
+        let relinked = ctx.link_bank_entry(user_id, RecordKind::Transaction, transaction.id, bank.date, &bank.amount, &bank.description).await.unwrap();
+        assert_eq!(ctx.get_bank_links(user_id).await?, vec![relinked]);
+
+        let report = ctx.reconcile_statement(user_id, &csv, &columns, 1).await.unwrap();
+        assert_eq!(report.matched.len(), 1);
+        assert_eq!((report.matched[0].bank.line, report.matched[0].linked), (3, true));
+
+        let err = ctx.link_bank_entry(user_id, RecordKind::Redemption, transaction.id, bank.date, &bank.amount, &bank.description).await.unwrap_err();
+        assert!(matches!(err, ReconcileError::UnknownRecord(id) if id == transaction.id));
+        let other = ctx.create_user().await?.id;
+        let err = ctx.link_bank_entry(other, RecordKind::Transaction, transaction.id, bank.date, &bank.amount, &bank.description).await.unwrap_err();
+        assert!(matches!(err, ReconcileError::UnknownRecord(_)));
+        let pending = ctx.create_redemption(user_id, Uuid::nil(), BigDecimal::from(5), None).await?;
+        let link = ctx.link_bank_entry(user_id, RecordKind::Redemption, pending.id, bank.date, &BigDecimal::from(5), "TEST").await.unwrap();
+        assert_eq!(link.record_id, pending.id);
+
+        let err = ctx.reconcile_statement(user_id, &csv, &columns, MAX_MATCH_WINDOW_DAYS + 1).await.unwrap_err();
+        assert!(matches!(err, ReconcileError::Window(days) if days == MAX_MATCH_WINDOW_DAYS + 1));
+
+        // Records outside the window of the statement aren't even candidates.
+        let later = format!("Posted,Payee,Amount\n{},POS DEBIT TEST CASINO,-100.00\n", day + chrono::Duration::days(30));
+        let report = ctx.reconcile_statement(user_id, &later, &columns, 1).await.unwrap();
+        assert!(report.matched.is_empty());
+        assert!(report.unmatched_bank[0].candidates.is_empty());
+
+        // But a link to one holds on the next upload.
+        let bank = report.unmatched_bank[0].bank.clone();
+        ctx.link_bank_entry(user_id, RecordKind::Transaction, transaction.id, bank.date, &bank.amount, &bank.description).await.unwrap();
+        let report = ctx.reconcile_statement(user_id, &later, &columns, 1).await.unwrap();
+        assert_eq!(report.matched.len(), 1);
+        assert_eq!((report.matched[0].record.id, report.matched[0].linked), (transaction.id, true));
+        assert_eq!(report.matched[0].days_apart, 30);
+        assert!(report.unmatched_bank.is_empty());
+        Ok(())
+    }
+}
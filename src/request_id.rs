@@ -0,0 +1,177 @@
+//! Per request ids for correlating logs, responses and error reports.
+//!
+//! Every request gets the id from its `X-Request-Id` header, or a fresh one when
+//! the header is missing or unusable. The id is attached to a span wrapping the
+//! whole request, stored in the request extensions for filters, and echoed back
+//! in the response header and in error bodies.
+
+use std::convert::Infallible;
+use std::fmt::Display;
+use std::net::SocketAddr;
+
+use tracing::Instrument;
+use warp::http::{HeaderMap, HeaderValue, Request, Response};
+use warp::hyper::{service::Service, Body};
+
+/// Header carrying the request id in both directions.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest client supplied id we accept.
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: RequestId;
+}
+
+/// Id of a single request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    /// A fresh random id.
+    #[must_use]
+    pub fn new() -> Self {
+        Self(uuid::Uuid::new_v4().to_string())
+    }
+
+    /// Take the id from the request headers, short printable ASCII only so it's safe to log and echo.
+    #[must_use]
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        headers
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| !id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN)
+            .filter(|id| id.bytes().all(|b| b.is_ascii_graphic()))
+            .map_or_else(Self::new, |id| Self(id.to_string()))
+    }
+
+    /// The id of the request being handled by the current task, if any.
+    #[must_use]
+    pub fn current() -> Option<Self> {
+        REQUEST_ID.try_with(Clone::clone).ok()
+    }
+
+    #[must_use]
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    fn header_value(&self) -> HeaderValue {
+        // Ids are either uuids or checked to be printable ASCII.
+        HeaderValue::from_str(&self.0).expect("request id is a valid header value")
+    }
+}
+
+impl Default for RequestId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl serde::Serialize for RequestId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0)
+    }
+}
+
+/// Handle one request with `service`, assigning it an id first.
+pub(crate) async fn handle<S>(mut service: S, mut req: Request<Body>, remote: SocketAddr) -> Result<Response<Body>, Infallible>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = Infallible>,
+{
+    let id = RequestId::from_headers(req.headers());
+    req.headers_mut().insert(REQUEST_ID_HEADER, id.header_value());
+    req.extensions_mut().insert(id.clone());
+
+    let route = crate::metrics::route_label(req.uri().path());
+    let span = tracing::info_span!(
+        "http",
+        request_id = %id,
+        http.method = %req.method(),
+        http.route = %route,
+        remote.addr = %remote
+    );
+    let mut res = REQUEST_ID.scope(id.clone(), service.call(req)).instrument(span).await?;
+    res.headers_mut().insert(REQUEST_ID_HEADER, id.header_value());
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handle_rejection;
+    use crate::server::serve_with_shutdown;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+    use tokio::sync::Notify;
+    use warp::Filter;
+
+    #[test]
+    fn test_request_id_from_headers() {
+        let mut headers = HeaderMap::new();
+        assert!(uuid::Uuid::parse_str(RequestId::from_headers(&headers).as_str()).is_ok());
+
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_static("abc-123"));
+        assert_eq!("abc-123", RequestId::from_headers(&headers).as_str());
+
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_static("has spaces"));
+        assert_ne!("has spaces", RequestId::from_headers(&headers).as_str());
+
+        headers.insert(REQUEST_ID_HEADER, HeaderValue::from_str(&"a".repeat(200)).unwrap());
+        assert_eq!(36, RequestId::from_headers(&headers).as_str().len());
+    }
+
+    async fn get(addr: SocketAddr, path: &str, request_id: Option<&str>) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let header = request_id.map(|id| format!("X-Request-Id: {id}\r\n")).unwrap_or_default();
+        let request = format!("GET {path} HTTP/1.0\r\nHost: localhost\r\n{header}\r\n");
+        stream.write_all(request.as_bytes()).await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_request_id_echoed() {
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        let app = warp::path!("id")
+            .and(warp::ext::get::<RequestId>())
+            .map(|id: RequestId| id.to_string())
+            .recover(handle_rejection);
+        let stop = Arc::new(Notify::new());
+        let signal = {
+            let stop = stop.clone();
+            async move { stop.notified().await }
+        };
+        let server = tokio::spawn(serve_with_shutdown(app, addr, signal, Duration::from_secs(1)));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // A client id is used by the handlers and echoed back.
+        let res = get(addr, "/id", Some("client-id-1")).await.to_lowercase();
+        assert!(res.contains("x-request-id: client-id-1\r\n"), "{res}");
+        assert!(res.ends_with("client-id-1"), "{res}");
+
+        // Without one a fresh id is generated and shows up in error bodies.
+        let res = get(addr, "/missing", None).await;
+        let (head, body) = res.split_once("\r\n\r\n").unwrap();
+        let id = head
+            .lines()
+            .find_map(|line| line.to_lowercase().strip_prefix("x-request-id: ").map(str::to_string))
+            .unwrap();
+        assert!(uuid::Uuid::parse_str(&id).is_ok());
+        let body: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!("NOT_FOUND", body["error"]);
+        assert_eq!(id, body["request_id"]);
+
+        stop.notify_one();
+        server.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,220 @@
+//! Server lifecycle: serving until a shutdown signal and draining connections.
+
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::Notify;
+use warp::hyper::server::conn::AddrStream;
+use warp::hyper::service::{make_service_fn, service_fn};
+use warp::{reject::Rejection, Filter, Reply};
+
+use crate::request_id;
+
+/// Address of the connected client, in the request extensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RemoteAddr(pub SocketAddr);
+
+/// Errors from serving.
+#[derive(Debug)]
+pub enum ServeError {
+    /// The address couldn't be bound or the server failed.
+    Hyper(warp::hyper::Error),
+    /// The server task panicked.
+    Join(tokio::task::JoinError),
+}
+
+impl std::fmt::Display for ServeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Hyper(e) => write!(f, "server failed: {e}"),
+            Self::Join(e) => write!(f, "server task failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ServeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Hyper(e) => Some(e),
+            Self::Join(e) => Some(e),
+        }
+    }
+}
+
+impl From<warp::hyper::Error> for ServeError {
+    fn from(e: warp::hyper::Error) -> Self {
+        Self::Hyper(e)
+    }
+}
+
+impl From<tokio::task::JoinError> for ServeError {
+    fn from(e: tokio::task::JoinError) -> Self {
+        Self::Join(e)
+    }
+}
+
+/// Resolve when the process receives SIGINT or SIGTERM.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::error!("Failed to listen for ctrl-c: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                tracing::error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        () = ctrl_c => tracing::info!("Received SIGINT"),
+        () = terminate => tracing::info!("Received SIGTERM"),
+    }
+}
+
+/// Serve `app` on `addr` until `signal` resolves, then stop accepting connections
+/// and give in-flight requests up to `deadline` to finish.
+/// Every request is given a [`RequestId`](crate::RequestId) before it reaches `app`.
+///
+/// # Errors
+/// Will return `Err` if the address can't be bound or the server fails.
+pub async fn serve_with_shutdown<F>(
+    app: F,
+    addr: SocketAddr,
+    signal: impl Future<Output = ()> + Send,
+    deadline: Duration,
+) -> Result<(), ServeError>
+where
+    F: Filter<Error = Rejection> + Clone + Send + Sync + 'static,
+    F::Extract: Reply,
+{
+    let stop = Arc::new(Notify::new());
+    let stopped = stop.clone();
+    let service = warp::service(app);
+    let make_service = make_service_fn(move |conn: &AddrStream| {
+        let remote = conn.remote_addr();
+        let service = service.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |mut req: warp::hyper::Request<warp::hyper::Body>| {
+                req.extensions_mut().insert(RemoteAddr(remote));
+                request_id::handle(service.clone(), req, remote)
+            }))
+        }
+    });
+    let server = warp::hyper::Server::try_bind(&addr)?.serve(make_service);
+    tracing::info!("Listening on {}", server.local_addr());
+    let server = server.with_graceful_shutdown(async move { stopped.notified().await });
+    let mut server = tokio::spawn(server);
+
+    tokio::select! {
+        // The server only finishes on its own if it failed.
+        res = &mut server => {
+            tracing::error!("Server stopped unexpectedly");
+            return Ok(res??);
+        }
+        () = signal => {}
+    }
+
+    tracing::info!("Shutting down, draining connections for up to {:?}", deadline);
+    stop.notify_one();
+    match tokio::time::timeout(deadline, &mut server).await {
+        Ok(res) => res??,
+        Err(_) => {
+            tracing::warn!("Shutdown deadline passed, dropping remaining connections");
+            server.abort();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    /// Grab a free port for the test server.
+    fn free_addr() -> SocketAddr {
+        std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap()
+    }
+
+    fn slow_app(delay: Duration) -> impl Filter<Extract = (impl Reply,), Error = Rejection> + Clone {
+        warp::path!("slow").and_then(move || async move {
+            tokio::time::sleep(delay).await;
+            Ok::<_, Rejection>("done")
+        })
+    }
+
+    async fn get_slow(addr: SocketAddr) -> std::io::Result<String> {
+        let mut stream = TcpStream::connect(addr).await?;
+        stream.write_all(b"GET /slow HTTP/1.0\r\nHost: localhost\r\n\r\n").await?;
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await?;
+        Ok(response)
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_drains_in_flight_requests() {
+        let addr = free_addr();
+        let stop = Arc::new(Notify::new());
+        let signal = {
+            let stop = stop.clone();
+            async move { stop.notified().await }
+        };
+        let server = tokio::spawn(serve_with_shutdown(slow_app(Duration::from_millis(300)), addr, signal, Duration::from_secs(5)));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let request = tokio::spawn(get_slow(addr));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        stop.notify_one();
+
+        let response = request.await.unwrap().unwrap();
+        assert!(response.ends_with("done"), "{response}");
+        server.await.unwrap().unwrap();
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_bind_error() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let res = serve_with_shutdown(slow_app(Duration::ZERO), addr, std::future::pending(), Duration::ZERO).await;
+        assert!(matches!(res, Err(ServeError::Hyper(_))));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_deadline() {
+        let addr = free_addr();
+        let stop = Arc::new(Notify::new());
+        let signal = {
+            let stop = stop.clone();
+            async move { stop.notified().await }
+        };
+        let server = tokio::spawn(serve_with_shutdown(slow_app(Duration::from_secs(30)), addr, signal, Duration::from_millis(200)));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let _request = tokio::spawn(get_slow(addr));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        stop.notify_one();
+
+        tokio::time::timeout(Duration::from_secs(5), server)
+            .await
+            .expect("server should stop at the deadline")
+            .unwrap()
+            .unwrap();
+    }
+}
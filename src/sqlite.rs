@@ -0,0 +1,773 @@
+//! SQLite implementations of the [`CasinoContext`](crate::CasinoContext) queries.
+//!
+//! SQLite has no NUMERIC or UUID types, so the schema in `migrations_sqlite/`
+//! stores ids as 16 byte blobs and amounts as TEXT. The rows are decoded by
+//! hand here instead of through `query_as!`, which can only check against one
+//! database at compile time.
+
+use bigdecimal::BigDecimal;
+use chrono::{NaiveDate, NaiveDateTime};
+use sqlx::sqlite::{SqlitePool, SqliteRow};
+use sqlx::Row;
+use std::str::FromStr;
+use uuid::Uuid;
+
+use crate::export::Cursor;
+use crate::import::{Duplicates, ImportRecords};
+use crate::reconcile::{BankLink, RecordKind};
+use crate::{
+    CBUserId, Casino, CasinoSummary, DailyBonus, PlaySession, Redemption, StateFile, StateImportSummary,
+    Transaction, User, STATE_FILE_VERSION,
+};
+
+/// Decode a TEXT column holding a decimal.
+fn decimal(row: &SqliteRow, column: &str) -> Result<BigDecimal, sqlx::Error> {
+    let value: String = row.try_get(column)?;
+    BigDecimal::from_str(&value).map_err(|e| sqlx::Error::ColumnDecode {
+        index: column.to_string(),
+        source: Box::new(e),
+    })
+}
+
+fn casino(row: SqliteRow) -> Result<Casino, sqlx::Error> {
+    Ok(Casino {
+        id: row.try_get("id")?,
+        name: row.try_get("name")?,
+        url: row.try_get("url")?,
+        description: row.try_get("description")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+fn transaction(row: SqliteRow) -> Result<Transaction, sqlx::Error> {
+    Ok(Transaction {
+        id: row.try_get("id")?,
+        user_id: row.try_get("user_id")?,
+        casino_id: row.try_get("casino_id")?,
+        cost: decimal(&row, "cost")?,
+        benefit: decimal(&row, "benefit")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+        notes: row.try_get("notes")?,
+    })
+}
+
+fn redemption(row: SqliteRow) -> Result<Redemption, sqlx::Error> {
+    Ok(Redemption {
+        id: row.try_get("id")?,
+        user_id: row.try_get("user_id")?,
+        casino_id: row.try_get("casino_id")?,
+        amount: decimal(&row, "amount")?,
+        created_at: row.try_get("created_at")?,
+        received_at: row.try_get("received_at")?,
+    })
+}
+
+fn user(row: SqliteRow) -> Result<User, sqlx::Error> {
+    Ok(User {
+        id: row.try_get("id")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+fn daily_bonus(row: SqliteRow) -> Result<DailyBonus, sqlx::Error> {
+    Ok(DailyBonus {
+        id: row.try_get("id")?,
+        user_id: row.try_get("user_id")?,
+        casino_id: row.try_get("casino_id")?,
+        amount_sc: decimal(&row, "amount_sc")?,
+        amount_gc: decimal(&row, "amount_gc")?,
+        amount_other1: decimal(&row, "amount_other1")?,
+        amount_other2: decimal(&row, "amount_other2")?,
+        amount_other3: decimal(&row, "amount_other3")?,
+        amount_other4: decimal(&row, "amount_other4")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+fn play_session(row: SqliteRow) -> Result<PlaySession, sqlx::Error> {
+    Ok(PlaySession {
+        id: row.try_get("id")?,
+        user_id: row.try_get("user_id")?,
+        casino_id: row.try_get("casino_id")?,
+        game_id: row.try_get("game_id")?,
+        beg_amount: decimal(&row, "beg_amount")?,
+        end_amount: decimal(&row, "end_amount")?,
+        sc_per_spin: decimal(&row, "sc_per_spin")?,
+        num_spins: decimal(&row, "num_spins")?,
+        play_date: row.try_get("play_date")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+/// Check that the database is reachable.
+pub(crate) async fn ping(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT 1").execute(pool).await.map(|_| ())
+}
+
+/// Get all casinos
+pub(crate) async fn get_all_casinos(pool: &SqlitePool) -> Result<Vec<Casino>, sqlx::Error> {
+    sqlx::query("SELECT * FROM casino")
+        .try_map(casino)
+        .fetch_all(pool)
+        .await
+}
+
+/// Get all transactions for a user.
+pub(crate) async fn get_transactions(pool: &SqlitePool, user_id: Uuid) -> Result<Vec<Transaction>, sqlx::Error> {
+    sqlx::query(r#"SELECT * FROM "transaction" WHERE user_id = ?"#)
+        .bind(user_id)
+        .try_map(transaction)
+        .fetch_all(pool)
+        .await
+}
+
+/// Get all transactions, newest first.
+#[allow(dead_code)]
+pub(crate) async fn get_transactions_all(pool: &SqlitePool) -> Result<Vec<Transaction>, sqlx::Error> {
+    sqlx::query(r#"SELECT * FROM "transaction" ORDER BY created_at DESC"#)
+        .try_map(transaction)
+        .fetch_all(pool)
+        .await
+}
+
+/// Get a user by their id.
+pub(crate) async fn get_user(pool: &SqlitePool, user_id: Uuid) -> Result<Vec<User>, sqlx::Error> {
+    sqlx::query(r#"SELECT * FROM "user" WHERE id = ?"#)
+        .bind(user_id)
+        .try_map(user)
+        .fetch_all(pool)
+        .await
+}
+
+/// Create a new user.
+pub(crate) async fn create_user(pool: &SqlitePool) -> Result<CBUserId, sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    sqlx::query(r#"INSERT INTO "user" (id, created_at, updated_at) VALUES (?, ?, ?) RETURNING id"#)
+        .bind(Uuid::new_v4())
+        .bind(now)
+        .bind(now)
+        .try_map(|row: SqliteRow| Ok(CBUserId { id: row.try_get("id")? }))
+        .fetch_one(pool)
+        .await
+}
+
+/// Create a new transaction.
+pub(crate) async fn create_transaction(
+    pool: &SqlitePool,
+    user_id: Uuid,
+    casino_id: Uuid,
+    cost: BigDecimal,
+    benefit: BigDecimal,
+    notes: Option<String>,
+) -> Result<Transaction, sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    sqlx::query(
+        r#"INSERT INTO "transaction" (id, user_id, casino_id, cost, benefit, created_at, updated_at, notes)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?) RETURNING *"#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(user_id)
+    .bind(casino_id)
+    .bind(cost.to_string())
+    .bind(benefit.to_string())
+    .bind(now)
+    .bind(now)
+    .bind(notes)
+    .try_map(transaction)
+    .fetch_one(pool)
+    .await
+}
+
+/// Create a new redemption.
+pub(crate) async fn create_redemption(
+    pool: &SqlitePool,
+    user_id: Uuid,
+    casino_id: Uuid,
+    amount: BigDecimal,
+    received_at: Option<chrono::NaiveDateTime>,
+) -> Result<Redemption, sqlx::Error> {
+    sqlx::query(
+        r#"INSERT INTO redemption (id, user_id, casino_id, amount, created_at, received_at)
+        VALUES (?, ?, ?, ?, ?, ?) RETURNING *"#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(user_id)
+    .bind(casino_id)
+    .bind(amount.to_string())
+    .bind(chrono::Utc::now().naive_utc())
+    .bind(received_at)
+    .try_map(redemption)
+    .fetch_one(pool)
+    .await
+}
+
+/// Record a daily bonus claim.
+pub(crate) async fn create_daily_bonus(
+    pool: &SqlitePool,
+    user_id: Uuid,
+    casino_id: Uuid,
+    amount_sc: BigDecimal,
+    amount_gc: BigDecimal,
+) -> Result<DailyBonus, sqlx::Error> {
+    sqlx::query(
+        r#"INSERT INTO daily_bonus (id, user_id, casino_id, amount_sc, amount_gc, amount_other1, amount_other2, amount_other3, amount_other4, created_at)
+        VALUES (?, ?, ?, ?, ?, '0', '0', '0', '0', ?) RETURNING *"#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(user_id)
+    .bind(casino_id)
+    .bind(amount_sc.to_string())
+    .bind(amount_gc.to_string())
+    .bind(chrono::Utc::now().naive_utc())
+    .try_map(daily_bonus)
+    .fetch_one(pool)
+    .await
+}
+
+/// Get the spend and benefit of a user at every casino. SQLite sums the text
+/// decimals as REAL, so the transactions are summed here instead.
+pub(crate) async fn get_casino_summaries(pool: &SqlitePool, user_id: Uuid) -> Result<Vec<CasinoSummary>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"SELECT t.casino_id, c.name AS casino_name, t.cost, t.benefit, t.created_at
+        FROM "transaction" t JOIN casino c ON c.id = t.casino_id
+        WHERE t.user_id = ?
+        ORDER BY c.name, t.casino_id, t.created_at"#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut summaries: Vec<CasinoSummary> = vec![];
+    for row in rows {
+        let casino_id: Uuid = row.try_get("casino_id")?;
+        let (cost, benefit) = (decimal(&row, "cost")?, decimal(&row, "benefit")?);
+        let created_at: chrono::NaiveDateTime = row.try_get("created_at")?;
+        match summaries.last_mut().filter(|summary| summary.casino_id == casino_id) {
+            Some(summary) => {
+                summary.spend += cost;
+                summary.benefit += benefit;
+                summary.transactions += 1;
+                summary.last_transaction = created_at;
+            }
+            None => summaries.push(CasinoSummary {
+                casino_id,
+                casino_name: row.try_get("casino_name")?,
+                spend: cost,
+                benefit,
+                transactions: 1,
+                first_transaction: created_at,
+                last_transaction: created_at,
+            }),
+        }
+    }
+    Ok(summaries)
+}
+
+/// Get the redemptions of a user that haven't been received, oldest first.
+pub(crate) async fn get_pending_redemptions(pool: &SqlitePool, user_id: Uuid) -> Result<Vec<Redemption>, sqlx::Error> {
+    sqlx::query("SELECT * FROM redemption WHERE user_id = ? AND received_at IS NULL ORDER BY created_at")
+        .bind(user_id)
+        .try_map(redemption)
+        .fetch_all(pool)
+        .await
+}
+
+/// Get the casinos a user plays at without a daily bonus claimed in `[start, end)`.
+pub(crate) async fn get_unclaimed_bonuses(
+    pool: &SqlitePool,
+    user_id: Uuid,
+    start: chrono::NaiveDateTime,
+    end: chrono::NaiveDateTime,
+) -> Result<Vec<Casino>, sqlx::Error> {
+    sqlx::query(
+        r#"SELECT * FROM casino c
+        WHERE (
+            c.id IN (SELECT casino_id FROM user_casino WHERE user_id = ?1 AND NOT is_self_excluded)
+            OR c.id IN (SELECT casino_id FROM "transaction" WHERE user_id = ?1)
+        )
+        AND NOT EXISTS (
+            SELECT 1 FROM daily_bonus b
+            WHERE b.user_id = ?1 AND b.casino_id = c.id AND b.created_at >= ?2 AND b.created_at < ?3
+        )
+        ORDER BY c.name"#,
+    )
+    .bind(user_id)
+    .bind(start)
+    .bind(end)
+    .try_map(casino)
+    .fetch_all(pool)
+    .await
+}
+
+/// Get the latest `limit` transactions of a user, newest first.
+pub(crate) async fn get_recent_transactions(
+    pool: &SqlitePool,
+    user_id: Uuid,
+    limit: i64,
+) -> Result<Vec<Transaction>, sqlx::Error> {
+    sqlx::query(r#"SELECT * FROM "transaction" WHERE user_id = ? ORDER BY created_at DESC LIMIT ?"#)
+        .bind(user_id)
+        .bind(limit)
+        .try_map(transaction)
+        .fetch_all(pool)
+        .await
+}
+
+/// Insert the records that don't duplicate one of the user's in a single database transaction.
+pub(crate) async fn insert_records(
+    pool: &SqlitePool,
+    user_id: Uuid,
+    records: &ImportRecords,
+) -> Result<Duplicates, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let transactions = sqlx::query(r#"SELECT * FROM "transaction" WHERE user_id = ?"#)
+        .bind(user_id)
+        .try_map(transaction)
+        .fetch_all(&mut *tx)
+        .await?;
+    let redemptions = sqlx::query("SELECT * FROM redemption WHERE user_id = ?")
+        .bind(user_id)
+        .try_map(redemption)
+        .fetch_all(&mut *tx)
+        .await?;
+    let duplicates = Duplicates::find(records, &transactions, &redemptions);
+
+    for (row, _) in records.transactions.iter().zip(&duplicates.transactions).filter(|(_, d)| d.is_none()) {
+        sqlx::query(
+            r#"INSERT INTO "transaction" (id, user_id, casino_id, cost, benefit, created_at, updated_at, notes)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)"#,
+        )
+        .bind(row.id)
+        .bind(row.user_id)
+        .bind(row.casino_id)
+        .bind(row.cost.to_string())
+        .bind(row.benefit.to_string())
+        .bind(row.created_at)
+        .bind(row.updated_at)
+        .bind(&row.notes)
+        .execute(&mut *tx)
+        .await?;
+    }
+    for (row, _) in records.redemptions.iter().zip(&duplicates.redemptions).filter(|(_, d)| d.is_none()) {
+        sqlx::query(
+            "INSERT INTO redemption (id, user_id, casino_id, amount, created_at, received_at) VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(row.id)
+        .bind(row.user_id)
+        .bind(row.casino_id)
+        .bind(row.amount.to_string())
+        .bind(row.created_at)
+        .bind(row.received_at)
+        .execute(&mut *tx)
+        .await?;
+    }
+    for (id, received_at) in &duplicates.received {
+        sqlx::query("UPDATE redemption SET received_at = ? WHERE id = ? AND received_at IS NULL")
+            .bind(received_at)
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+    Ok(duplicates)
+}
+
+/// Create a new casino.
+pub(crate) async fn create_casino(
+    pool: &SqlitePool,
+    name: &str,
+    url: &str,
+    description: &str,
+) -> Result<Casino, sqlx::Error> {
+    let now = chrono::Utc::now().naive_utc();
+    sqlx::query(
+        r"INSERT INTO casino (id, name, url, description, created_at, updated_at)
+        VALUES (?, ?, ?, ?, ?, ?) RETURNING *",
+    )
+    .bind(Uuid::new_v4())
+    .bind(name)
+    .bind(url)
+    .bind(description)
+    .bind(now)
+    .bind(now)
+    .try_map(casino)
+    .fetch_one(pool)
+    .await
+}
+
+/// Snapshot every table that makes up an instance.
+pub(crate) async fn export_state(pool: &SqlitePool) -> Result<StateFile, sqlx::Error> {
+    Ok(StateFile {
+        version: STATE_FILE_VERSION,
+        exported_at: chrono::Utc::now().naive_utc(),
+        users: sqlx::query(r#"SELECT * FROM "user" ORDER BY created_at"#)
+            .try_map(user)
+            .fetch_all(pool)
+            .await?,
+        casinos: sqlx::query("SELECT * FROM casino ORDER BY created_at")
+            .try_map(casino)
+            .fetch_all(pool)
+            .await?,
+        transactions: sqlx::query(r#"SELECT * FROM "transaction" ORDER BY created_at"#)
+            .try_map(transaction)
+            .fetch_all(pool)
+            .await?,
+        redemptions: sqlx::query("SELECT * FROM redemption ORDER BY created_at")
+            .try_map(redemption)
+            .fetch_all(pool)
+            .await?,
+        bonuses: sqlx::query("SELECT * FROM daily_bonus ORDER BY created_at")
+            .try_map(daily_bonus)
+            .fetch_all(pool)
+            .await?,
+        sessions: sqlx::query("SELECT * FROM play_session ORDER BY created_at")
+            .try_map(play_session)
+            .fetch_all(pool)
+            .await?,
+        bank_links: sqlx::query("SELECT * FROM bank_link ORDER BY created_at, id")
+            .try_map(bank_link)
+            .fetch_all(pool)
+            .await?,
+    })
+}
+
+/// Import a state file in a single database transaction.
+pub(crate) async fn import_state(pool: &SqlitePool, state: &StateFile) -> Result<StateImportSummary, sqlx::Error> {
+    let mut summary = StateImportSummary::default();
+    let mut tx = pool.begin().await?;
+
+    for user in &state.users {
+        summary.users += sqlx::query(
+            r#"INSERT INTO "user" (id, created_at, updated_at) VALUES (?, ?, ?) ON CONFLICT (id) DO NOTHING"#,
+        )
+        .bind(user.id)
+        .bind(user.created_at)
+        .bind(user.updated_at)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+
+    for casino in &state.casinos {
+        summary.casinos += sqlx::query(
+            r#"INSERT INTO casino (id, name, url, description, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?) ON CONFLICT (id) DO NOTHING"#,
+        )
+        .bind(casino.id)
+        .bind(&casino.name)
+        .bind(&casino.url)
+        .bind(&casino.description)
+        .bind(casino.created_at)
+        .bind(casino.updated_at)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+
+    for transaction in &state.transactions {
+        summary.transactions += sqlx::query(
+            r#"INSERT INTO "transaction" (id, user_id, casino_id, cost, benefit, created_at, updated_at, notes)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?) ON CONFLICT (id) DO NOTHING"#,
+        )
+        .bind(transaction.id)
+        .bind(transaction.user_id)
+        .bind(transaction.casino_id)
+        .bind(transaction.cost.to_string())
+        .bind(transaction.benefit.to_string())
+        .bind(transaction.created_at)
+        .bind(transaction.updated_at)
+        .bind(&transaction.notes)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+
+    for redemption in &state.redemptions {
+        summary.redemptions += sqlx::query(
+            r"INSERT INTO redemption (id, user_id, casino_id, amount, created_at, received_at)
+            VALUES (?, ?, ?, ?, ?, ?) ON CONFLICT (id) DO NOTHING",
+        )
+        .bind(redemption.id)
+        .bind(redemption.user_id)
+        .bind(redemption.casino_id)
+        .bind(redemption.amount.to_string())
+        .bind(redemption.created_at)
+        .bind(redemption.received_at)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+
+    for bonus in &state.bonuses {
+        summary.bonuses += sqlx::query(
+            r"INSERT INTO daily_bonus
+            (id, user_id, casino_id, amount_sc, amount_gc, amount_other1, amount_other2, amount_other3, amount_other4, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?) ON CONFLICT (id) DO NOTHING",
+        )
+        .bind(bonus.id)
+        .bind(bonus.user_id)
+        .bind(bonus.casino_id)
+        .bind(bonus.amount_sc.to_string())
+        .bind(bonus.amount_gc.to_string())
+        .bind(bonus.amount_other1.to_string())
+        .bind(bonus.amount_other2.to_string())
+        .bind(bonus.amount_other3.to_string())
+        .bind(bonus.amount_other4.to_string())
+        .bind(bonus.created_at)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+
+    for session in &state.sessions {
+        summary.sessions += sqlx::query(
+            r"INSERT INTO play_session
+            (id, user_id, casino_id, game_id, beg_amount, end_amount, sc_per_spin, num_spins, play_date, created_at, updated_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) ON CONFLICT (id) DO NOTHING",
+        )
+        .bind(session.id)
+        .bind(session.user_id)
+        .bind(session.casino_id)
+        .bind(session.game_id)
+        .bind(session.beg_amount.to_string())
+        .bind(session.end_amount.to_string())
+        .bind(session.sc_per_spin.to_string())
+        .bind(session.num_spins.to_string())
+        .bind(session.play_date)
+        .bind(session.created_at)
+        .bind(session.updated_at)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+
+    for link in &state.bank_links {
+        summary.bank_links += sqlx::query(
+            r"INSERT INTO bank_link (id, user_id, kind, record_id, bank_date, bank_amount, bank_description, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?) ON CONFLICT DO NOTHING",
+        )
+        .bind(link.id)
+        .bind(link.user_id)
+        .bind(&link.kind)
+        .bind(link.record_id)
+        .bind(link.bank_date)
+        .bind(link.bank_amount.to_string())
+        .bind(&link.bank_description)
+        .bind(link.created_at)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+
+    tx.commit().await?;
+    Ok(summary)
+}
+
+/// Page query of a user's `table` ordered by `date` and id, see [`crate::export`].
+macro_rules! export_sql {
+    ($table:literal, $date:literal $(, $where:literal)?) => {
+        concat!(
+            "SELECT * FROM ", $table, " WHERE user_id = ?1", $(" AND ", $where,)? " AND (?2 IS NULL OR casino_id = ?2)",
+            " AND (?3 IS NULL OR ", $date, " >= ?3) AND (?4 IS NULL OR ", $date, " < ?4)",
+            " AND (?5 IS NULL OR (", $date, ", id) > (?5, ?6)) ORDER BY ", $date, ", id LIMIT ?7"
+        )
+    };
+}
+
+/// Bind the filter of an [`export_sql!`] query.
+fn export_page(
+    sql: &'static str,
+    user_id: Uuid,
+    casino_id: Option<Uuid>,
+    (from, to): (Option<NaiveDateTime>, Option<NaiveDateTime>),
+    after: Option<Cursor>,
+    limit: i64,
+) -> sqlx::query::Query<'static, sqlx::Sqlite, sqlx::sqlite::SqliteArguments<'static>> {
+    sqlx::query(sql)
+        .bind(user_id)
+        .bind(casino_id)
+        .bind(from)
+        .bind(to)
+        .bind(after.map(|(date, _)| date))
+        .bind(after.map(|(_, id)| id))
+        .bind(limit)
+}
+
+/// A page of a user's transactions, see [`crate::export`].
+pub(crate) async fn export_transactions(
+    pool: &SqlitePool,
+    user_id: Uuid,
+    casino_id: Option<Uuid>,
+    range: (Option<NaiveDateTime>, Option<NaiveDateTime>),
+    after: Option<Cursor>,
+    limit: i64,
+) -> Result<Vec<Transaction>, sqlx::Error> {
+    export_page(export_sql!(r#""transaction""#, "created_at"), user_id, casino_id, range, after, limit)
+        .try_map(transaction)
+        .fetch_all(pool)
+        .await
+}
+
+/// A page of a user's redemptions, see [`crate::export`].
+pub(crate) async fn export_redemptions(
+    pool: &SqlitePool,
+    user_id: Uuid,
+    casino_id: Option<Uuid>,
+    range: (Option<NaiveDateTime>, Option<NaiveDateTime>),
+    after: Option<Cursor>,
+    limit: i64,
+) -> Result<Vec<Redemption>, sqlx::Error> {
+    export_page(export_sql!("redemption", "created_at"), user_id, casino_id, range, after, limit)
+        .try_map(redemption)
+        .fetch_all(pool)
+        .await
+}
+
+/// A page of a user's received redemptions by receipt time, see [`crate::export`].
+pub(crate) async fn received_redemptions(
+    pool: &SqlitePool,
+    user_id: Uuid,
+    casino_id: Option<Uuid>,
+    range: (Option<NaiveDateTime>, Option<NaiveDateTime>),
+    after: Option<Cursor>,
+    limit: i64,
+) -> Result<Vec<Redemption>, sqlx::Error> {
+    export_page(
+        export_sql!("redemption", "received_at", "received_at IS NOT NULL"),
+        user_id,
+        casino_id,
+        range,
+        after,
+        limit,
+    )
+    .try_map(redemption)
+    .fetch_all(pool)
+    .await
+}
+
+/// A page of a user's daily bonus claims, see [`crate::export`].
+pub(crate) async fn export_bonuses(
+    pool: &SqlitePool,
+    user_id: Uuid,
+    casino_id: Option<Uuid>,
+    range: (Option<NaiveDateTime>, Option<NaiveDateTime>),
+    after: Option<Cursor>,
+    limit: i64,
+) -> Result<Vec<DailyBonus>, sqlx::Error> {
+    export_page(export_sql!("daily_bonus", "created_at"), user_id, casino_id, range, after, limit)
+        .try_map(daily_bonus)
+        .fetch_all(pool)
+        .await
+}
+
+/// A page of a user's play sessions, see [`crate::export`].
+pub(crate) async fn export_sessions(
+    pool: &SqlitePool,
+    user_id: Uuid,
+    casino_id: Option<Uuid>,
+    range: (Option<NaiveDateTime>, Option<NaiveDateTime>),
+    after: Option<Cursor>,
+    limit: i64,
+) -> Result<Vec<PlaySession>, sqlx::Error> {
+    export_page(export_sql!("play_session", "play_date"), user_id, casino_id, range, after, limit)
+        .try_map(play_session)
+        .fetch_all(pool)
+        .await
+}
+
+fn bank_link(row: SqliteRow) -> Result<BankLink, sqlx::Error> {
+    Ok(BankLink {
+        id: row.try_get("id")?,
+        user_id: row.try_get("user_id")?,
+        kind: row.try_get("kind")?,
+        record_id: row.try_get("record_id")?,
+        bank_date: row.try_get("bank_date")?,
+        bank_amount: decimal(&row, "bank_amount")?,
+        bank_description: row.try_get("bank_description")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+/// Get the bank rows a user linked by hand.
+pub(crate) async fn get_bank_links(pool: &SqlitePool, user_id: Uuid) -> Result<Vec<BankLink>, sqlx::Error> {
+    sqlx::query("SELECT * FROM bank_link WHERE user_id = ? ORDER BY created_at, id")
+        .bind(user_id)
+        .try_map(bank_link)
+        .fetch_all(pool)
+        .await
+}
+
+/// Get the transactions and redemptions of a user that have a bank link.
+pub(crate) async fn get_linked_records(
+    pool: &SqlitePool,
+    user_id: Uuid,
+) -> Result<(Vec<Transaction>, Vec<Redemption>), sqlx::Error> {
+    let transactions = sqlx::query(
+        r#"SELECT * FROM "transaction" WHERE user_id = ?1
+        AND id IN (SELECT record_id FROM bank_link WHERE user_id = ?1 AND kind = 'transaction')"#,
+    )
+    .bind(user_id)
+    .try_map(transaction)
+    .fetch_all(pool)
+    .await?;
+    let redemptions = sqlx::query(
+        "SELECT * FROM redemption WHERE user_id = ?1
+        AND id IN (SELECT record_id FROM bank_link WHERE user_id = ?1 AND kind = 'redemption')",
+    )
+    .bind(user_id)
+    .try_map(redemption)
+    .fetch_all(pool)
+    .await?;
+    Ok((transactions, redemptions))
+}
+
+/// Whether a transaction or redemption is one of a user's.
+pub(crate) async fn owns_record(
+    pool: &SqlitePool,
+    user_id: Uuid,
+    kind: RecordKind,
+    record_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let sql = match kind {
+        RecordKind::Transaction => r#"SELECT 1 FROM "transaction" WHERE id = ? AND user_id = ?"#,
+        RecordKind::Redemption => "SELECT 1 FROM redemption WHERE id = ? AND user_id = ?",
+    };
+    Ok(sqlx::query(sql).bind(record_id).bind(user_id).fetch_optional(pool).await?.is_some())
+}
+
+/// Link a bank row to a record, replacing an earlier link of the record.
+pub(crate) async fn insert_bank_link(
+    pool: &SqlitePool,
+    user_id: Uuid,
+    kind: RecordKind,
+    record_id: Uuid,
+    date: NaiveDate,
+    amount: &BigDecimal,
+    description: &str,
+) -> Result<BankLink, sqlx::Error> {
+    sqlx::query(
+        r#"INSERT INTO bank_link (id, user_id, kind, record_id, bank_date, bank_amount, bank_description, created_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT (user_id, kind, record_id) DO UPDATE SET
+            bank_date = excluded.bank_date,
+            bank_amount = excluded.bank_amount,
+            bank_description = excluded.bank_description,
+            created_at = excluded.created_at
+        RETURNING *"#,
+    )
+    .bind(Uuid::new_v4())
+    .bind(user_id)
+    .bind(kind.as_str())
+    .bind(record_id)
+    .bind(date)
+    .bind(amount.to_string())
+    .bind(description)
+    .bind(chrono::Utc::now().naive_utc())
+    .try_map(bank_link)
+    .fetch_one(pool)
+    .await
+}
@@ -0,0 +1,470 @@
+//! Versioned state file for moving a whole instance between machines.
+//!
+//! A state file is a JSON snapshot of every user, casino, transaction,
+//! redemption, daily bonus, play session and bank link. Rows keep their ids,
+//! so importing the same file twice is a no-op.
+
+use std::fmt::Display;
+use std::path::Path;
+
+use sqlx::PgPool;
+
+use crate::reconcile::BankLink;
+use crate::{Casino, CasinoContext, DailyBonus, Db, PlaySession, Redemption, Transaction, User};
+
+/// Version of the state file format written by this build.
+pub const STATE_FILE_VERSION: u32 = 1;
+
+/// Demo data loaded by `casino-buddy seed`.
+const DEMO_STATE: &str = include_str!("../seed/demo_state.json");
+
+/// Snapshot of an entire instance.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct StateFile {
+    pub version:        u32,
+    pub exported_at:    chrono::NaiveDateTime,
+    pub users:          Vec<User>,
+    pub casinos:        Vec<Casino>,
+    pub transactions:   Vec<Transaction>,
+    pub redemptions:    Vec<Redemption>,
+    pub bonuses:        Vec<DailyBonus>,
+    pub sessions:       Vec<PlaySession>,
+    /// Missing from files written before bank links were kept.
+    #[serde(default)]
+    pub bank_links:     Vec<BankLink>,
+}
+
+/// Number of rows inserted by a state import, rows that already existed are not counted.
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, PartialEq)]
+pub struct StateImportSummary {
+    pub users:          u64,
+    pub casinos:        u64,
+    pub transactions:   u64,
+    pub redemptions:    u64,
+    pub bonuses:        u64,
+    pub sessions:       u64,
+    pub bank_links:     u64,
+}
+
+/// Errors that can happen while reading, writing or importing a state file.
+#[derive(Debug)]
+pub enum StateError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /// The file was written by a newer (or unknown) version of the format.
+    UnsupportedVersion(u64),
+    /// The file has no `version` field at all.
+    MissingVersion,
+    Sqlx(sqlx::Error),
+}
+
+impl Display for StateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "state file io error: {e}"),
+            Self::Json(e) => write!(f, "state file is not valid json: {e}"),
+            Self::UnsupportedVersion(v) => write!(
+                f,
+                "state file version {v} is not supported (expected at most {STATE_FILE_VERSION})"
+            ),
+            Self::MissingVersion => f.write_str("state file has no version"),
+            Self::Sqlx(e) => write!(f, "state import failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+impl From<std::io::Error> for StateError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for StateError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Json(e)
+    }
+}
+
+impl From<sqlx::Error> for StateError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Sqlx(e)
+    }
+}
+
+impl StateFile {
+    /// Parse a state file, checking the version before looking at the rest of the document.
+    ///
+    /// # Errors
+    /// Will return `Err` if the json is invalid or the version is not supported.
+    pub fn from_json(json: &str) -> Result<Self, StateError> {
+        let value: serde_json::Value = serde_json::from_str(json)?;
+        let version = value
+            .get("version")
+            .and_then(serde_json::Value::as_u64)
+            .ok_or(StateError::MissingVersion)?;
+        if version == 0 || version > u64::from(STATE_FILE_VERSION) {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// The demo instance shipped with the binary.
+    ///
+    /// # Panics
+    /// Panics if the embedded demo state is invalid, which the tests guard against.
+    #[must_use]
+    pub fn demo() -> Self {
+        Self::from_json(DEMO_STATE).expect("embedded demo state is valid")
+    }
+
+    /// Serialize the state file to pretty printed json.
+    ///
+    /// # Errors
+    /// Will return `Err` if serialization fails.
+    pub fn to_json(&self) -> Result<String, StateError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Read a state file from disk.
+    ///
+    /// # Errors
+    /// Will return `Err` if the file can't be read or parsed.
+    pub fn read(path: impl AsRef<Path>) -> Result<Self, StateError> {
+        Self::from_json(&std::fs::read_to_string(path)?)
+    }
+
+    /// Write the state file to disk.
+    ///
+    /// # Errors
+    /// Will return `Err` if the file can't be written.
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<(), StateError> {
+        std::fs::write(path, self.to_json()?)?;
+        Ok(())
+    }
+}
+
+/// State file operations for [`CasinoContext`]
+impl CasinoContext {
+    /// Snapshot every table that makes up an instance.
+    ///
+    /// # Errors
+    /// Will return `Err` if any of the tables can't be read.
+    #[tracing::instrument(skip(self), fields(db.system = self.db.backend()), err)]
+    pub async fn export_state(&self) -> Result<StateFile, sqlx::Error> {
+        match &*self.db {
+            Db::Postgres(pool) => export_state_postgres(pool).await,
+            #[cfg(feature = "sqlite")]
+            Db::Sqlite(pool) => crate::sqlite::export_state(pool).await,
+        }
+    }
+
+    /// Import a state file in a single database transaction.
+    /// Rows whose id already exists are left untouched.
+    ///
+    /// # Errors
+    /// Will return `Err` if any row can't be inserted, nothing is imported in that case.
+    #[tracing::instrument(skip_all, fields(db.system = self.db.backend()), err)]
+    pub async fn import_state(&self, state: &StateFile) -> Result<StateImportSummary, sqlx::Error> {
+        let summary = match &*self.db {
+            Db::Postgres(pool) => import_state_postgres(pool, state).await?,
+            #[cfg(feature = "sqlite")]
+            Db::Sqlite(pool) => crate::sqlite::import_state(pool, state).await?,
+        };
+        tracing::info!("Imported state file: {:?}", summary);
+        Ok(summary)
+    }
+
+    /// Load the demo data, running it again doesn't duplicate anything.
+    ///
+    /// # Errors
+    /// Will return `Err` if the import fails.
+    pub async fn seed(&self) -> Result<StateImportSummary, sqlx::Error> {
+        self.import_state(&StateFile::demo()).await
+    }
+}
+
+/// Snapshot every table from Postgres.
+async fn export_state_postgres(pool: &PgPool) -> Result<StateFile, sqlx::Error> {
+    let users = sqlx::query_as!(User, r#"SELECT * FROM "user" ORDER BY created_at"#)
+        .fetch_all(pool)
+        .await?;
+    let casinos = sqlx::query_as!(Casino, "SELECT * FROM casino ORDER BY created_at")
+        .fetch_all(pool)
+        .await?;
+    let transactions = sqlx::query_as!(
+            Transaction,
+            r#"SELECT * FROM "transaction" ORDER BY created_at"#
+        )
+        .fetch_all(pool)
+        .await?;
+    let redemptions = sqlx::query_as!(Redemption, "SELECT * FROM redemption ORDER BY created_at")
+        .fetch_all(pool)
+        .await?;
+    let bonuses = sqlx::query_as!(DailyBonus, "SELECT * FROM daily_bonus ORDER BY created_at")
+        .fetch_all(pool)
+        .await?;
+    let sessions = sqlx::query_as!(PlaySession, "SELECT * FROM play_session ORDER BY created_at")
+        .fetch_all(pool)
+        .await?;
+    let bank_links = sqlx::query_as!(BankLink, "SELECT * FROM bank_link ORDER BY created_at, id")
+        .fetch_all(pool)
+        .await?;
+    Ok(StateFile {
+        version: STATE_FILE_VERSION,
+        exported_at: chrono::Utc::now().naive_utc(),
+        users,
+        casinos,
+        transactions,
+        redemptions,
+        bonuses,
+        sessions,
+        bank_links,
+    })
+}
+
+/// Import a state file into Postgres.
+async fn import_state_postgres(pool: &PgPool, state: &StateFile) -> Result<StateImportSummary, sqlx::Error> {
+    let mut summary = StateImportSummary::default();
+    let mut tx = pool.begin().await?;
+
+    for user in &state.users {
+        summary.users += sqlx::query!(
+            r#"INSERT INTO "user" (id, created_at, updated_at) VALUES ($1, $2, $3) ON CONFLICT (id) DO NOTHING"#,
+            user.id,
+            user.created_at,
+            user.updated_at
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+
+    for casino in &state.casinos {
+        summary.casinos += sqlx::query!(
+            r#"INSERT INTO casino (id, name, url, description, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (id) DO NOTHING"#,
+            casino.id,
+            casino.name,
+            casino.url,
+            casino.description,
+            casino.created_at,
+            casino.updated_at
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+
+    for transaction in &state.transactions {
+        summary.transactions += sqlx::query!(
+            r#"INSERT INTO "transaction" (id, user_id, casino_id, cost, benefit, created_at, updated_at, notes)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT (id) DO NOTHING"#,
+            transaction.id,
+            transaction.user_id,
+            transaction.casino_id,
+            transaction.cost,
+            transaction.benefit,
+            transaction.created_at,
+            transaction.updated_at,
+            transaction.notes
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+
+    for redemption in &state.redemptions {
+        summary.redemptions += sqlx::query!(
+            r#"INSERT INTO redemption (id, user_id, casino_id, amount, created_at, received_at)
+            VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT (id) DO NOTHING"#,
+            redemption.id,
+            redemption.user_id,
+            redemption.casino_id,
+            redemption.amount,
+            redemption.created_at,
+            redemption.received_at
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+
+    for bonus in &state.bonuses {
+        summary.bonuses += sqlx::query!(
+            r#"INSERT INTO daily_bonus
+            (id, user_id, casino_id, amount_sc, amount_gc, amount_other1, amount_other2, amount_other3, amount_other4, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10) ON CONFLICT (id) DO NOTHING"#,
+            bonus.id,
+            bonus.user_id,
+            bonus.casino_id,
+            bonus.amount_sc,
+            bonus.amount_gc,
+            bonus.amount_other1,
+            bonus.amount_other2,
+            bonus.amount_other3,
+            bonus.amount_other4,
+            bonus.created_at
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+
+    for session in &state.sessions {
+        summary.sessions += sqlx::query!(
+            r#"INSERT INTO play_session
+            (id, user_id, casino_id, game_id, beg_amount, end_amount, sc_per_spin, num_spins, play_date, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11) ON CONFLICT (id) DO NOTHING"#,
+            session.id,
+            session.user_id,
+            session.casino_id,
+            session.game_id,
+            session.beg_amount,
+            session.end_amount,
+            session.sc_per_spin,
+            session.num_spins,
+            session.play_date,
+            session.created_at,
+            session.updated_at
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+
+    // A link to a record that is already linked is left alone like a row with a known id.
+    for link in &state.bank_links {
+        summary.bank_links += sqlx::query!(
+            r#"INSERT INTO bank_link (id, user_id, kind, record_id, bank_date, bank_amount, bank_description, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8) ON CONFLICT DO NOTHING"#,
+            link.id,
+            link.user_id,
+            link.kind,
+            link.record_id,
+            link.bank_date,
+            link.bank_amount,
+            link.bank_description,
+            link.created_at
+        )
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+
+    tx.commit().await?;
+    Ok(summary)
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bigdecimal::BigDecimal;
+    use chrono::SubsecRound;
+    use uuid::Uuid;
+
+    backend_tests!(test_export_import_state, test_seed);
+
+    fn empty_state() -> StateFile {
+        StateFile {
+            version: STATE_FILE_VERSION,
+            exported_at: chrono::Utc::now().naive_utc(),
+            users: vec![],
+            casinos: vec![],
+            transactions: vec![],
+            redemptions: vec![],
+            bonuses: vec![],
+            sessions: vec![],
+            bank_links: vec![],
+        }
+    }
+
+    async fn test_seed(ctx: CasinoContext) -> sqlx::Result<()> {
+        let demo = StateFile::demo();
+        let summary = ctx.seed().await?;
+        assert_eq!(demo.casinos.len() as u64, summary.casinos);
+        assert_eq!(demo.transactions.len() as u64, summary.transactions);
+        assert_eq!(StateImportSummary::default(), ctx.seed().await?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_state_file_version_check() {
+        let mut json = serde_json::to_value(empty_state()).unwrap();
+        assert!(StateFile::from_json(&json.to_string()).is_ok());
+
+        // Files written before bank links were kept have none.
+        json.as_object_mut().unwrap().remove("bank_links");
+        assert!(StateFile::from_json(&json.to_string()).unwrap().bank_links.is_empty());
+
+        json["version"] = serde_json::json!(STATE_FILE_VERSION + 1);
+        assert!(matches!(
+            StateFile::from_json(&json.to_string()),
+            Err(StateError::UnsupportedVersion(_))
+        ));
+
+        json.as_object_mut().unwrap().remove("version");
+        assert!(matches!(StateFile::from_json(&json.to_string()), Err(StateError::MissingVersion)));
+    }
+
+    async fn test_export_import_state(ctx: CasinoContext) -> sqlx::Result<()> {
+        let state = ctx.export_state().await?;
+        assert!(state.users.iter().any(|user| user.id == Uuid::nil()));
+        assert!(state.casinos.iter().any(|casino| casino.id == Uuid::nil()));
+        assert_eq!(ctx._get_transactions_all().await?.len(), state.transactions.len());
+
+        // Re-importing the same snapshot doesn't duplicate anything.
+        let summary = ctx.import_state(&state).await?;
+        assert_eq!(StateImportSummary::default(), summary);
+
+        // Postgres only keeps microseconds.
+        let now = chrono::Utc::now().naive_utc().trunc_subsecs(6);
+        let user_id = Uuid::new_v4();
+        let casino_id = Uuid::new_v4();
+        let mut new_state = empty_state();
+        new_state.users.push(User { id: user_id, created_at: now, updated_at: now });
+        new_state.casinos.push(Casino {
+            id: casino_id,
+            name: "Imported".to_string(),
+            url: "imported.test".to_string(),
+            description: "Imported".to_string(),
+            created_at: now,
+            updated_at: now,
+        });
+        new_state.transactions.push(Transaction {
+            id: Uuid::new_v4(),
+            user_id,
+            casino_id,
+            cost: BigDecimal::from(20),
+            benefit: BigDecimal::from(30),
+            created_at: now,
+            updated_at: now,
+            notes: None,
+        });
+        new_state.bank_links.push(BankLink {
+            id: Uuid::new_v4(),
+            user_id,
+            kind: "transaction".to_string(),
+            record_id: new_state.transactions[0].id,
+            bank_date: now.date(),
+            bank_amount: BigDecimal::from(-20),
+            bank_description: "IMPORTED CASINO".to_string(),
+            created_at: now,
+        });
+        let new_state = StateFile::from_json(&new_state.to_json().unwrap()).unwrap();
+        let summary = ctx.import_state(&new_state).await?;
+        assert_eq!(1, summary.users);
+        assert_eq!(1, summary.casinos);
+        assert_eq!(1, summary.transactions);
+        assert_eq!(1, summary.bank_links);
+
+        let transactions = ctx.get_transactions(user_id).await?;
+        assert_eq!(new_state.transactions, transactions);
+        assert_eq!(new_state.bank_links, ctx.get_bank_links(user_id).await?);
+        assert_eq!(new_state.bank_links, ctx.export_state().await?.bank_links);
+        assert_eq!(StateImportSummary::default(), ctx.import_state(&new_state).await?);
+        Ok(())
+    }
+}
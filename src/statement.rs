@@ -0,0 +1,418 @@
+//! OFX and QIF statements for personal finance applications.
+//!
+//! The statement is the casino cash flow of a bank account: each purchase is a
+//! debit of its cost and each received redemption a credit of its amount,
+//! pending redemptions are left out until they are received. Every entry is
+//! identified by its row id as the OFX `FITID`, so importing an overlapping
+//! OFX statement again doesn't duplicate entries. QIF has no such id: the row
+//! id is written as the check number, which importers don't dedup on.
+
+use std::fmt::{Display, Write};
+
+use bigdecimal::{BigDecimal, Zero};
+use chrono::NaiveDateTime;
+use uuid::Uuid;
+
+use crate::export::ExportFilter;
+use crate::{Casino, CasinoContext, Redemption, Transaction};
+
+/// Statement file format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum StatementFormat {
+    /// OFX 1.0.2, read by Quicken, GnuCash, Moneydance and most banks' importers.
+    #[default]
+    Ofx,
+    /// Quicken Interchange Format with US dates.
+    Qif,
+}
+
+impl StatementFormat {
+    /// Content type of the reply.
+    #[must_use]
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Ofx => "application/x-ofx",
+            Self::Qif => "application/qif",
+        }
+    }
+
+    /// File extension of a download.
+    #[must_use]
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Ofx => "ofx",
+            Self::Qif => "qif",
+        }
+    }
+}
+
+/// Account id used when none is given.
+pub const DEFAULT_STATEMENT_ACCOUNT: &str = "CASINOBUDDY";
+
+/// Errors from a statement export.
+#[derive(Debug)]
+pub enum StatementError {
+    /// The account id isn't 1 to 22 letters, digits or dashes.
+    InvalidAccount(String),
+    /// `from` is after `to`.
+    InvalidRange,
+    Sqlx(sqlx::Error),
+}
+
+impl Display for StatementError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidAccount(account) => write!(f, "invalid account id {account:?}"),
+            Self::InvalidRange => f.write_str("from is after to"),
+            Self::Sqlx(e) => write!(f, "database error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for StatementError {}
+
+impl From<sqlx::Error> for StatementError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Sqlx(e)
+    }
+}
+
+impl crate::error::RequestError for StatementError {
+    fn into_sqlx(self) -> Result<sqlx::Error, Self> {
+        match self {
+            Self::Sqlx(e) => Ok(e),
+            e => Err(e),
+        }
+    }
+}
+
+/// Stable id of the entry for row `id`.
+#[must_use]
+pub fn fitid(id: Uuid) -> String {
+    id.simple().to_string().to_uppercase()
+}
+
+/// An entry of the statement.
+struct Entry<'a> {
+    posted: NaiveDateTime,
+    id:     Uuid,
+    /// Negative for debits.
+    amount: BigDecimal,
+    payee:  &'a str,
+    memo:   String,
+}
+
+/// `value` with two decimals.
+fn money(value: &BigDecimal) -> String {
+    value.round(2).with_scale(2).to_string()
+}
+
+/// `value` in the ASCII subset both formats can carry on one line.
+fn text(value: &str, max_len: usize) -> String {
+    value
+        .chars()
+        .map(|c| if c.is_ascii() && !c.is_ascii_control() { c } else { ' ' })
+        .take(max_len)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+/// `value` escaped for an OFX element.
+fn escaped(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// OFX date time.
+fn ofx_time(time: NaiveDateTime) -> String {
+    time.format("%Y%m%d%H%M%S").to_string()
+}
+
+/// Check an account id fits the OFX `ACCTID` element.
+fn validate_account(account: &str) -> Result<(), StatementError> {
+    if account.is_empty() || account.len() > 22 || !account.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+        return Err(StatementError::InvalidAccount(account.to_string()));
+    }
+    Ok(())
+}
+
+/// Render the purchases and received redemptions as a statement of `account`.
+/// The statement covers the filter's days, or the span of its entries.
+/// `generated_at` is the OFX server time.
+///
+/// # Errors
+/// Will return `Err` if the account id is invalid.
+pub fn render(
+    format: StatementFormat,
+    account: &str,
+    filter: &ExportFilter,
+    casinos: &[Casino],
+    transactions: &[Transaction],
+    redemptions: &[Redemption],
+    generated_at: NaiveDateTime,
+) -> Result<String, StatementError> {
+    validate_account(account)?;
+    let casino_name =
+        |id: Uuid| casinos.iter().find(|casino| casino.id == id).map_or("Unknown casino", |casino| casino.name.as_str());
+    let mut entries: Vec<Entry> = transactions
+        .iter()
+        .filter(|t| !t.cost.is_zero())
+        .map(|t| Entry {
+            posted: t.created_at,
+            id: t.id,
+            amount: -&t.cost,
+            payee: casino_name(t.casino_id),
+            memo: match t.notes.as_deref().filter(|notes| !notes.trim().is_empty()) {
+                Some(notes) => format!("Purchase of {} SC: {notes}", t.benefit.normalized()),
+                None => format!("Purchase of {} SC", t.benefit.normalized()),
+            },
+        })
+        .chain(redemptions.iter().filter(|r| !r.amount.is_zero()).filter_map(|r| {
+            Some(Entry {
+                posted: r.received_at?,
+                id: r.id,
+                amount: r.amount.clone(),
+                payee: casino_name(r.casino_id),
+                memo: "Redemption".to_string(),
+            })
+        }))
+        .collect();
+    entries.sort_by_key(|entry| (entry.posted, entry.id));
+
+    // Writing to a String doesn't fail.
+    let mut out = String::new();
+    match format {
+        StatementFormat::Ofx => {
+            let day = |date: chrono::NaiveDate| date.and_time(chrono::NaiveTime::MIN);
+            let start = filter.from.map(day).or(entries.first().map(|entry| entry.posted)).unwrap_or(generated_at);
+            let end = filter
+                .to
+                .and_then(|date| date.succ_opt())
+                .map(|date| day(date) - chrono::Duration::seconds(1))
+                .or(entries.last().map(|entry| entry.posted))
+                .unwrap_or(generated_at);
+            let balance: BigDecimal = entries.iter().map(|entry| &entry.amount).sum();
+            out.push_str(
+                "OFXHEADER:100\nDATA:OFXSGML\nVERSION:102\nSECURITY:NONE\nENCODING:USASCII\nCHARSET:1252\n\
+                COMPRESSION:NONE\nOLDFILEUID:NONE\nNEWFILEUID:NONE\n\n",
+            );
+            out.push_str("<OFX>\n<SIGNONMSGSRSV1>\n<SONRS>\n<STATUS>\n<CODE>0\n<SEVERITY>INFO\n</STATUS>\n");
+            writeln!(out, "<DTSERVER>{}\n<LANGUAGE>ENG\n</SONRS>\n</SIGNONMSGSRSV1>", ofx_time(generated_at)).unwrap();
+            out.push_str("<BANKMSGSRSV1>\n<STMTTRNRS>\n<TRNUID>0\n<STATUS>\n<CODE>0\n<SEVERITY>INFO\n</STATUS>\n");
+            out.push_str("<STMTRS>\n<CURDEF>USD\n<BANKACCTFROM>\n<BANKID>CASINOBUDDY\n");
+            writeln!(out, "<ACCTID>{account}\n<ACCTTYPE>CHECKING\n</BANKACCTFROM>").unwrap();
+            writeln!(out, "<BANKTRANLIST>\n<DTSTART>{}\n<DTEND>{}", ofx_time(start), ofx_time(end)).unwrap();
+            for entry in &entries {
+                let kind = if entry.amount < BigDecimal::zero() { "DEBIT" } else { "CREDIT" };
+                writeln!(out, "<STMTTRN>\n<TRNTYPE>{kind}\n<DTPOSTED>{}", ofx_time(entry.posted)).unwrap();
+                writeln!(out, "<TRNAMT>{}\n<FITID>{}", money(&entry.amount), fitid(entry.id)).unwrap();
+                writeln!(out, "<NAME>{}", escaped(&text(entry.payee, 32))).unwrap();
+                writeln!(out, "<MEMO>{}\n</STMTTRN>", escaped(&text(&entry.memo, 255))).unwrap();
+            }
+            writeln!(out, "</BANKTRANLIST>\n<LEDGERBAL>\n<BALAMT>{}", money(&balance)).unwrap();
+            writeln!(out, "<DTASOF>{}\n</LEDGERBAL>", ofx_time(end)).unwrap();
+            out.push_str("</STMTRS>\n</STMTTRNRS>\n</BANKMSGSRSV1>\n</OFX>\n");
+        }
+        StatementFormat::Qif => {
+            out.push_str("!Type:Bank\n");
+            for entry in &entries {
+                writeln!(out, "D{}\nT{}", entry.posted.format("%m/%d/%Y"), money(&entry.amount)).unwrap();
+                writeln!(out, "N{}\nP{}\nM{}\n^", fitid(entry.id), text(entry.payee, 64), text(&entry.memo, 255))
+                    .unwrap();
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Statement exports for [`CasinoContext`]
+impl CasinoContext {
+    /// Render the purchases and received redemptions of `user_id` matching
+    /// `filter` as a statement of `account`.
+    ///
+    /// # Errors
+    /// Will return `Err` if the account id or range is invalid or a query fails.
+    #[tracing::instrument(skip(self), err)]
+    pub async fn export_statement(
+        &self,
+        user_id: Uuid,
+        filter: &ExportFilter,
+        format: StatementFormat,
+        account: &str,
+    ) -> Result<String, StatementError> {
+        validate_account(account)?;
+        if matches!((filter.from, filter.to), (Some(from), Some(to)) if from > to) {
+            return Err(StatementError::InvalidRange);
+        }
+        let casinos = self.get_all_casinos().await?;
+        let (transactions, redemptions) = self.cash_flow(user_id, filter).await?;
+        let now = chrono::Utc::now().naive_utc();
+        render(format, account, filter, &casinos, &transactions, &redemptions, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    backend_tests!(test_export_statement);
+
+    /// The `STMTTRN` aggregates of an OFX 1.0.2 statement as element maps,
+    /// checking the header and that aggregates are closed in order.
+    fn parse_ofx(ofx: &str) -> Result<Vec<HashMap<String, String>>, String> {
+        let (header, body) = ofx.split_once("\n\n").ok_or("no header")?;
+        let header: HashMap<&str, &str> = header.lines().filter_map(|line| line.split_once(':')).collect();
+        if header.get("OFXHEADER") != Some(&"100") || header.get("VERSION") != Some(&"102") {
+            return Err(format!("invalid header {header:?}"));
+        }
+        let mut open: Vec<&str> = vec![];
+        let mut transactions = vec![];
+        for line in body.lines() {
+            let tag = line.strip_prefix('<').and_then(|line| line.split_once('>')).ok_or(format!("invalid line {line:?}"))?;
+            match tag {
+                (close, "") if close.starts_with('/') => {
+                    if open.pop() != Some(&close[1..]) {
+                        return Err(format!("unbalanced {close}"));
+                    }
+                }
+                (aggregate, "") => {
+                    open.push(aggregate);
+                    if aggregate == "STMTTRN" {
+                        transactions.push(HashMap::new());
+                    }
+                }
+                (element, value) => {
+                    if value.contains(['<', '>']) {
+                        return Err(format!("unescaped {value:?}"));
+                    }
+                    if open.last() == Some(&"STMTTRN") {
+                        let value = value.replace("&lt;", "<").replace("&gt;", ">").replace("&amp;", "&");
+                        transactions.last_mut().unwrap().insert(element.to_string(), value);
+                    }
+                }
+            }
+        }
+        if !open.is_empty() {
+            return Err(format!("unclosed {open:?}"));
+        }
+        Ok(transactions)
+    }
+
+    /// The records of a QIF file as field maps.
+    fn parse_qif(qif: &str) -> Vec<HashMap<char, String>> {
+        let body = qif.strip_prefix("!Type:Bank\n").expect("bank header");
+        body.split_terminator("^\n")
+            .map(|record| record.lines().map(|line| (line.chars().next().unwrap(), line[1..].to_string())).collect())
+            .collect()
+    }
+
+    fn time(day: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2026, 3, day).unwrap().and_hms_opt(14, 22, 10).unwrap()
+    }
+
+    #[test]
+    fn test_render() {
+        let casino = Casino {
+            id: Uuid::new_v4(),
+            name: "Fish & <Chips> Casino".to_string(),
+            url: String::new(),
+            description: String::new(),
+            created_at: time(1),
+            updated_at: time(1),
+        };
+        let purchase = Transaction {
+            id: Uuid::new_v4(),
+            user_id: Uuid::nil(),
+            casino_id: casino.id,
+            cost: BigDecimal::from_str("19.99").unwrap(),
+            benefit: BigDecimal::from(20),
+            created_at: time(2),
+            updated_at: time(2),
+            notes: Some("first\nbuy".to_string()),
+        };
+        let redeem = |received_at| Redemption {
+            id: Uuid::new_v4(),
+            user_id: Uuid::nil(),
+            casino_id: casino.id,
+            amount: BigDecimal::from(100),
+            created_at: time(1),
+            received_at,
+        };
+        let redemptions = [redeem(Some(time(5))), redeem(None)];
+        let (casinos, transactions) = ([casino.clone()], [purchase.clone()]);
+        let render = |format, generated_at| {
+            render(format, "CB-1", &ExportFilter::default(), &casinos, &transactions, &redemptions, generated_at).unwrap()
+        };
+
+        let ofx = render(StatementFormat::Ofx, time(9));
+        let transactions = parse_ofx(&ofx).unwrap_or_else(|e| panic!("{e}\n{ofx}"));
+        assert_eq!(2, transactions.len());
+        let debit = &transactions[0];
+        assert_eq!(("DEBIT", "-19.99"), (debit["TRNTYPE"].as_str(), debit["TRNAMT"].as_str()));
+        assert_eq!("20260302142210", debit["DTPOSTED"]);
+        assert_eq!(fitid(purchase.id), debit["FITID"]);
+        assert_eq!("Fish & <Chips> Casino", debit["NAME"]);
+        assert_eq!("Purchase of 20 SC: first buy", debit["MEMO"]);
+        let credit = &transactions[1];
+        assert_eq!(("CREDIT", "100.00"), (credit["TRNTYPE"].as_str(), credit["TRNAMT"].as_str()));
+        assert_eq!(fitid(redemptions[0].id), credit["FITID"]);
+        assert!(ofx.contains("<BALAMT>80.01\n"));
+        assert!(ofx.contains("<DTSTART>20260302142210\n<DTEND>20260305142210\n"));
+
+        // The ids only depend on the rows, not on when the statement is made.
+        let again = parse_ofx(&render(StatementFormat::Ofx, time(20))).unwrap();
+        let fitids = |transactions: &[HashMap<String, String>]| {
+            transactions.iter().map(|t| t["FITID"].clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(fitids(&transactions), fitids(&again));
+
+        let records = parse_qif(&render(StatementFormat::Qif, time(9)));
+        assert_eq!(2, records.len());
+        assert_eq!(("03/02/2026", "-19.99"), (records[0][&'D'].as_str(), records[0][&'T'].as_str()));
+        assert_eq!(fitid(purchase.id), records[0][&'N']);
+        assert_eq!("Fish & <Chips> Casino", records[0][&'P']);
+        assert_eq!(("03/05/2026", "100.00"), (records[1][&'D'].as_str(), records[1][&'T'].as_str()));
+
+        for account in ["", "has space", "A234567890123456789012X"] {
+            let err = super::render(StatementFormat::Ofx, account, &ExportFilter::default(), &[], &[], &[], time(1));
+            assert!(matches!(err, Err(StatementError::InvalidAccount(_))), "{account:?}");
+        }
+    }
+
+    async fn test_export_statement(ctx: CasinoContext) -> sqlx::Result<()> {
+        let user_id = Uuid::parse_str("d61b6bba-61ba-4cab-b8b7-74a880968ec6").unwrap();
+        let transaction = ctx.get_transactions(user_id).await?.remove(0);
+
+        let ofx = ctx.export_statement(user_id, &ExportFilter::default(), StatementFormat::Ofx, "CB").await.unwrap();
+        let transactions = parse_ofx(&ofx).unwrap();
+        assert_eq!(vec![fitid(transaction.id)], transactions.iter().map(|t| t["FITID"].clone()).collect::<Vec<_>>());
+
+        let day = transaction.created_at.date().succ_opt();
+        let filter = ExportFilter { from: day, to: day, ..ExportFilter::default() };
+        let ofx = ctx.export_statement(user_id, &filter, StatementFormat::Ofx, "CB").await.unwrap();
+        assert!(parse_ofx(&ofx).unwrap().is_empty());
+        let start = day.unwrap().format("%Y%m%d000000").to_string();
+        assert!(ofx.contains(&format!("<DTSTART>{start}\n")));
+
+        let backwards = ExportFilter { from: day, to: transaction.created_at.date().pred_opt(), ..ExportFilter::default() };
+        let err = ctx.export_statement(user_id, &backwards, StatementFormat::Qif, "CB").await.unwrap_err();
+        assert!(matches!(err, StatementError::InvalidRange));
+
+        // A redemption requested today and received two days later is posted on the later day only.
+        let received = transaction.created_at + chrono::Duration::days(2);
+        let redemption = ctx.create_redemption(user_id, Uuid::nil(), BigDecimal::from(25), Some(received)).await?;
+        let today = Some(transaction.created_at.date());
+        let filter = ExportFilter { from: today, to: today, ..ExportFilter::default() };
+        let qif = ctx.export_statement(user_id, &filter, StatementFormat::Qif, "CB").await.unwrap();
+        assert!(parse_qif(&qif).iter().all(|record| record[&'N'] != fitid(redemption.id)));
+        let day = Some(received.date());
+        let filter = ExportFilter { from: day, to: day, ..ExportFilter::default() };
+        let qif = ctx.export_statement(user_id, &filter, StatementFormat::Qif, "CB").await.unwrap();
+        assert_eq!(vec![fitid(redemption.id)], parse_qif(&qif).iter().map(|record| record[&'N'].clone()).collect::<Vec<_>>());
+        Ok(())
+    }
+}
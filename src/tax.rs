@@ -0,0 +1,551 @@
+//! Tax year report of net winnings per casino.
+//!
+//! Purchases count in the calendar year they were made and redemptions in the
+//! year they were received, pending redemptions aren't income yet and are left
+//! out. The net gain of a casino is what it paid out minus what was spent
+//! there. Single redemptions of at least the reporting threshold are listed on
+//! their own, as those are the payouts a casino may report.
+
+use std::collections::BTreeMap;
+use std::fmt::{Display, Write};
+
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{Datelike, NaiveDate, NaiveDateTime};
+use uuid::Uuid;
+
+use crate::export::ExportFilter;
+use crate::{Casino, CasinoContext, Redemption, Transaction};
+
+/// Smallest single redemption listed when no threshold is given.
+pub const DEFAULT_REPORTING_THRESHOLD: i64 = 600;
+
+/// Tax report format.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TaxFormat {
+    #[default]
+    Json,
+    /// A printable page.
+    Html,
+    /// The casino totals, then the large redemptions after an empty line.
+    Csv,
+}
+
+impl TaxFormat {
+    /// Content type of the reply.
+    #[must_use]
+    pub fn content_type(self) -> &'static str {
+        match self {
+            Self::Json => "application/json",
+            Self::Html => "text/html; charset=utf-8",
+            Self::Csv => "text/csv",
+        }
+    }
+}
+
+/// Purchases, redemptions and net gain of one casino in the year.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct CasinoTaxTotals {
+    pub casino_id:          Uuid,
+    pub casino_name:        String,
+    /// Sum of the purchase costs.
+    #[schema(value_type = String)]
+    pub purchases:          BigDecimal,
+    pub purchase_count:     usize,
+    /// Sum of the redemptions received.
+    #[schema(value_type = String)]
+    pub redemptions:        BigDecimal,
+    pub redemption_count:   usize,
+    /// Redemptions minus purchases, negative for a loss.
+    #[schema(value_type = String)]
+    pub net:                BigDecimal,
+}
+
+/// Totals of every casino in the year.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct TaxTotals {
+    #[schema(value_type = String)]
+    pub purchases:      BigDecimal,
+    #[schema(value_type = String)]
+    pub redemptions:    BigDecimal,
+    #[schema(value_type = String)]
+    pub net:            BigDecimal,
+}
+
+/// A redemption of at least the reporting threshold.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct ReportedRedemption {
+    pub id:             Uuid,
+    pub casino_id:      Uuid,
+    pub casino_name:    String,
+    #[schema(value_type = String)]
+    pub amount:         BigDecimal,
+    pub created_at:     NaiveDateTime,
+    pub received_at:    NaiveDateTime,
+}
+
+/// Everything a tax year report shows.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize, utoipa::ToSchema)]
+pub struct TaxReport {
+    pub year:               i32,
+    #[schema(value_type = String)]
+    pub threshold:          BigDecimal,
+    /// Casinos with purchases or received redemptions in the year, by name.
+    pub casinos:            Vec<CasinoTaxTotals>,
+    pub totals:             TaxTotals,
+    /// Redemptions of at least `threshold` received in the year, by date received.
+    pub large_redemptions:  Vec<ReportedRedemption>,
+}
+
+/// Errors from a tax report.
+#[derive(Debug)]
+pub enum TaxError {
+    /// The year has no calendar.
+    InvalidYear(i32),
+    /// The threshold is negative.
+    InvalidThreshold(BigDecimal),
+    Sqlx(sqlx::Error),
+}
+
+impl Display for TaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidYear(year) => write!(f, "invalid year {year}"),
+            Self::InvalidThreshold(threshold) => write!(f, "threshold {threshold} is negative"),
+            Self::Sqlx(e) => write!(f, "database error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for TaxError {}
+
+impl From<sqlx::Error> for TaxError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Sqlx(e)
+    }
+}
+
+impl crate::error::RequestError for TaxError {
+    fn into_sqlx(self) -> Result<sqlx::Error, Self> {
+        match self {
+            Self::Sqlx(e) => Ok(e),
+            e => Err(e),
+        }
+    }
+}
+
+/// The first and last day of `year`, if it has them.
+fn year_days(year: i32) -> Result<(NaiveDate, NaiveDate), TaxError> {
+    NaiveDate::from_ymd_opt(year, 1, 1)
+        .zip(NaiveDate::from_ymd_opt(year, 12, 31))
+        .ok_or(TaxError::InvalidYear(year))
+}
+
+/// Sum the purchases made and redemptions received in `year` per casino.
+///
+/// # Errors
+/// Will return `Err` if the year or threshold is invalid.
+pub fn summarize(
+    year: i32,
+    threshold: &BigDecimal,
+    casinos: &[Casino],
+    transactions: &[Transaction],
+    redemptions: &[Redemption],
+) -> Result<TaxReport, TaxError> {
+    year_days(year)?;
+    if *threshold < BigDecimal::zero() {
+        return Err(TaxError::InvalidThreshold(threshold.clone()));
+    }
+    let casino_name =
+        |id: Uuid| casinos.iter().find(|casino| casino.id == id).map_or_else(|| id.to_string(), |casino| casino.name.clone());
+    let received: Vec<(&Redemption, NaiveDateTime)> = redemptions
+        .iter()
+        .filter_map(|r| r.received_at.filter(|at| at.year() == year).map(|at| (r, at)))
+        .collect();
+
+    let mut per_casino: BTreeMap<Uuid, CasinoTaxTotals> = BTreeMap::new();
+    let empty = |id: Uuid| CasinoTaxTotals {
+        casino_id: id,
+        casino_name: casino_name(id),
+        purchases: BigDecimal::zero(),
+        purchase_count: 0,
+        redemptions: BigDecimal::zero(),
+        redemption_count: 0,
+        net: BigDecimal::zero(),
+    };
+    for transaction in transactions.iter().filter(|t| t.created_at.year() == year) {
+        let casino = per_casino.entry(transaction.casino_id).or_insert_with(|| empty(transaction.casino_id));
+        casino.purchases += &transaction.cost;
+        casino.purchase_count += 1;
+    }
+    for (redemption, _) in &received {
+        let casino = per_casino.entry(redemption.casino_id).or_insert_with(|| empty(redemption.casino_id));
+        casino.redemptions += &redemption.amount;
+        casino.redemption_count += 1;
+    }
+
+    let mut casinos: Vec<CasinoTaxTotals> = per_casino
+        .into_values()
+        .map(|casino| CasinoTaxTotals { net: &casino.redemptions - &casino.purchases, ..casino })
+        .collect();
+    casinos.sort_by_key(|casino| casino.casino_name.to_lowercase());
+    let purchases: BigDecimal = casinos.iter().map(|casino| &casino.purchases).sum();
+    let redemptions: BigDecimal = casinos.iter().map(|casino| &casino.redemptions).sum();
+    let mut large_redemptions: Vec<ReportedRedemption> = received
+        .into_iter()
+        .filter(|(r, _)| r.amount >= *threshold)
+        .map(|(r, received_at)| ReportedRedemption {
+            id: r.id,
+            casino_id: r.casino_id,
+            casino_name: casino_name(r.casino_id),
+            amount: r.amount.clone(),
+            created_at: r.created_at,
+            received_at,
+        })
+        .collect();
+    large_redemptions.sort_by_key(|r| (r.received_at, r.id));
+    Ok(TaxReport {
+        year,
+        threshold: threshold.clone(),
+        casinos,
+        totals: TaxTotals { net: &redemptions - &purchases, purchases, redemptions },
+        large_redemptions,
+    })
+}
+
+/// `value` with two decimals.
+fn money(value: &BigDecimal) -> String {
+    value.round(2).with_scale(2).to_string()
+}
+
+/// `value` escaped for HTML text and attributes.
+fn escaped(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// Rows written as one CSV table.
+fn csv_table<const N: usize>(header: [&str; N], rows: impl IntoIterator<Item = [String; N]>) -> String {
+    // Writing to a Vec doesn't fail, and the fields are strings.
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(header).unwrap();
+    for row in rows {
+        writer.write_record(&row).unwrap();
+    }
+    String::from_utf8(writer.into_inner().unwrap()).unwrap()
+}
+
+/// Render a report as `format`.
+#[must_use]
+pub fn render(format: TaxFormat, report: &TaxReport) -> String {
+    match format {
+        // The report is plain data, it serializes.
+        TaxFormat::Json => serde_json::to_string(report).unwrap(),
+        TaxFormat::Csv => {
+            let totals = csv_table(
+                ["casino", "purchases", "purchase_count", "redemptions", "redemption_count", "net"],
+                report
+                    .casinos
+                    .iter()
+                    .map(|casino| {
+                        [
+                            casino.casino_name.clone(),
+                            money(&casino.purchases),
+                            casino.purchase_count.to_string(),
+                            money(&casino.redemptions),
+                            casino.redemption_count.to_string(),
+                            money(&casino.net),
+                        ]
+                    })
+                    .chain([[
+                        "Total".to_string(),
+                        money(&report.totals.purchases),
+                        report.casinos.iter().map(|casino| casino.purchase_count).sum::<usize>().to_string(),
+                        money(&report.totals.redemptions),
+                        report.casinos.iter().map(|casino| casino.redemption_count).sum::<usize>().to_string(),
+                        money(&report.totals.net),
+                    ]]),
+            );
+            let large = csv_table(
+                ["redemption_id", "casino", "amount", "requested", "received"],
+                report.large_redemptions.iter().map(|r| {
+                    [
+                        r.id.to_string(),
+                        r.casino_name.clone(),
+                        money(&r.amount),
+                        r.created_at.date().to_string(),
+                        r.received_at.date().to_string(),
+                    ]
+                }),
+            );
+            format!("{totals}\n{large}")
+        }
+        TaxFormat::Html => {
+            // Writing to a String doesn't fail.
+            let mut out = String::new();
+            let year = report.year;
+            out.push_str("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+            writeln!(out, "<title>Casino Buddy tax report {year}</title>").unwrap();
+            out.push_str(
+                "<style>\nbody { font-family: sans-serif; margin: 2em; }\n\
+                table { border-collapse: collapse; margin-bottom: 2em; }\n\
+                th, td { border: 1px solid #999; padding: 0.3em 0.6em; text-align: left; }\n\
+                .num { text-align: right; }\n\
+                @media print { body { margin: 0; } }\n</style>\n</head>\n<body>\n",
+            );
+            writeln!(out, "<h1>Tax report {year}</h1>").unwrap();
+            writeln!(out, "<p>Purchases made and redemptions received from January 1 to December 31, {year}.</p>").unwrap();
+            out.push_str("<h2>Net gain per casino</h2>\n<table>\n<thead>\n<tr><th>Casino</th>");
+            out.push_str("<th class=\"num\">Purchases</th><th class=\"num\">Redemptions received</th>");
+            out.push_str("<th class=\"num\">Net gain</th></tr>\n</thead>\n<tbody>\n");
+            for casino in &report.casinos {
+                writeln!(
+                    out,
+                    "<tr><td>{}</td><td class=\"num\">{} ({})</td><td class=\"num\">{} ({})</td><td class=\"num\">{}</td></tr>",
+                    escaped(&casino.casino_name),
+                    money(&casino.purchases),
+                    casino.purchase_count,
+                    money(&casino.redemptions),
+                    casino.redemption_count,
+                    money(&casino.net),
+                )
+                .unwrap();
+            }
+            out.push_str("</tbody>\n<tfoot>\n");
+            writeln!(
+                out,
+                "<tr><th>Total</th><th class=\"num\">{}</th><th class=\"num\">{}</th><th class=\"num\">{}</th></tr>",
+                money(&report.totals.purchases),
+                money(&report.totals.redemptions),
+                money(&report.totals.net),
+            )
+            .unwrap();
+            out.push_str("</tfoot>\n</table>\n");
+            writeln!(out, "<h2>Redemptions of {} or more</h2>", money(&report.threshold)).unwrap();
+            if report.large_redemptions.is_empty() {
+                out.push_str("<p>None.</p>\n");
+            } else {
+                out.push_str("<table>\n<thead>\n<tr><th>Received</th><th>Requested</th><th>Casino</th>");
+                out.push_str("<th class=\"num\">Amount</th></tr>\n</thead>\n<tbody>\n");
+                for r in &report.large_redemptions {
+                    writeln!(
+                        out,
+                        "<tr><td>{}</td><td>{}</td><td>{}</td><td class=\"num\">{}</td></tr>",
+                        r.received_at.date(),
+                        r.created_at.date(),
+                        escaped(&r.casino_name),
+                        money(&r.amount),
+                    )
+                    .unwrap();
+                }
+                out.push_str("</tbody>\n</table>\n");
+            }
+            out.push_str("</body>\n</html>\n");
+            out
+        }
+    }
+}
+
+/// Tax reports for [`CasinoContext`]
+impl CasinoContext {
+    /// Sum the purchases and received redemptions of `user_id` in `year`,
+    /// listing the redemptions of at least `threshold`.
+    ///
+    /// # Errors
+    /// Will return `Err` if the year or threshold is invalid or a query fails.
+    #[tracing::instrument(skip(self), err)]
+    pub async fn tax_report(&self, user_id: Uuid, year: i32, threshold: &BigDecimal) -> Result<TaxReport, TaxError> {
+        // `cash_flow` filters redemptions by the day they were received, so a
+        // redemption requested in an earlier year and received in this one is kept.
+        let (first, last) = year_days(year)?;
+        let filter = ExportFilter { from: Some(first), to: Some(last), ..ExportFilter::default() };
+        let casinos = self.get_all_casinos().await?;
+        let (transactions, redemptions) = self.cash_flow(user_id, &filter).await?;
+        summarize(year, threshold, &casinos, &transactions, &redemptions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::parse_date;
+
+    backend_tests!(test_tax_report);
+
+    fn casino(name: &str) -> Casino {
+        Casino {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            url: format!("{}.com", name.to_lowercase()),
+            description: String::new(),
+            created_at: NaiveDateTime::default(),
+            updated_at: NaiveDateTime::default(),
+        }
+    }
+
+    fn purchase(casino: &Casino, cost: &str, date: &str) -> Transaction {
+        Transaction {
+            id: Uuid::new_v4(),
+            user_id: Uuid::nil(),
+            casino_id: casino.id,
+            cost: cost.parse().unwrap(),
+            benefit: cost.parse().unwrap(),
+            created_at: parse_date(date).unwrap(),
+            updated_at: parse_date(date).unwrap(),
+            notes: None,
+        }
+    }
+
+    fn redemption(casino: &Casino, amount: &str, created: &str, received: Option<&str>) -> Redemption {
+        Redemption {
+            id: Uuid::new_v4(),
+            user_id: Uuid::nil(),
+            casino_id: casino.id,
+            amount: amount.parse().unwrap(),
+            created_at: parse_date(created).unwrap(),
+            received_at: received.map(|date| parse_date(date).unwrap()),
+        }
+    }
+
+    fn fixture() -> (Vec<Casino>, Vec<Transaction>, Vec<Redemption>) {
+        let casinos = vec![casino("Stake"), casino("chumba & <Friends>")];
+        let (stake, chumba) = (&casinos[0], &casinos[1]);
+        let transactions = vec![
+            purchase(stake, "100", "2025-03-01"),
+            purchase(stake, "49.99", "2025-12-31 23:59"),
+            purchase(chumba, "20", "2025-06-01"),
+            // Other years.
+            purchase(stake, "500", "2024-12-31 23:59"),
+            purchase(chumba, "500", "2026-01-01"),
+        ];
+        let redemptions = vec![
+            // Requested the year before, received in the year.
+            redemption(stake, "600", "2024-12-20", Some("2025-01-03")),
+            redemption(stake, "75.50", "2025-05-01", Some("2025-05-04")),
+            redemption(chumba, "1000", "2025-11-01", Some("2025-11-02")),
+            // Received the year after, and pending.
+            redemption(chumba, "900", "2025-12-30", Some("2026-01-02")),
+            redemption(stake, "700", "2025-12-01", None),
+        ];
+        (casinos, transactions, redemptions)
+    }
+
+    #[test]
+    fn test_summarize() {
+        let (casinos, transactions, redemptions) = fixture();
+        let report = summarize(2025, &BigDecimal::from(600), &casinos, &transactions, &redemptions).unwrap();
+        let names: Vec<_> = report.casinos.iter().map(|casino| casino.casino_name.as_str()).collect();
+        assert_eq!(names, vec!["chumba & <Friends>", "Stake"]);
+        let chumba = &report.casinos[0];
+        assert_eq!((chumba.purchase_count, chumba.redemption_count), (1, 1));
+        assert_eq!(chumba.net, BigDecimal::from(980));
+        let stake = &report.casinos[1];
+        assert_eq!((stake.purchase_count, stake.redemption_count), (2, 2));
+        assert_eq!(stake.purchases, "149.99".parse::<BigDecimal>().unwrap());
+        assert_eq!(stake.net, "525.51".parse::<BigDecimal>().unwrap());
+        assert_eq!(report.totals.purchases, "169.99".parse::<BigDecimal>().unwrap());
+        assert_eq!(report.totals.redemptions, "1675.50".parse::<BigDecimal>().unwrap());
+        assert_eq!(report.totals.net, "1505.51".parse::<BigDecimal>().unwrap());
+        let large: Vec<_> = report.large_redemptions.iter().map(|r| r.id).collect();
+        assert_eq!(large, vec![redemptions[0].id, redemptions[2].id]);
+
+        let report = summarize(2025, &BigDecimal::from(1001), &casinos, &transactions, &redemptions).unwrap();
+        assert!(report.large_redemptions.is_empty());
+        let err = summarize(2025, &BigDecimal::from(-1), &casinos, &transactions, &redemptions).unwrap_err();
+        assert!(matches!(err, TaxError::InvalidThreshold(_)));
+        assert!(matches!(summarize(i32::MAX, &BigDecimal::zero(), &[], &[], &[]), Err(TaxError::InvalidYear(_))));
+    }
+
+    #[test]
+    fn test_render_csv() {
+        let (casinos, transactions, redemptions) = fixture();
+        let report = summarize(2025, &BigDecimal::from(600), &casinos, &transactions, &redemptions).unwrap();
+        let csv = render(TaxFormat::Csv, &report);
+        let (totals, large) = csv.split_once("\n\n").unwrap();
+
+        let mut reader = csv::Reader::from_reader(totals.as_bytes());
+        let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        assert_eq!(reader.headers().unwrap().len(), 6);
+        assert_eq!(rows.len(), 3);
+        assert_eq!(&rows[0][0], "chumba & <Friends>");
+        assert_eq!(rows[1].iter().collect::<Vec<_>>(), vec!["Stake", "149.99", "2", "675.50", "2", "525.51"]);
+        assert_eq!(rows[2].iter().collect::<Vec<_>>(), vec!["Total", "169.99", "3", "1675.50", "3", "1505.51"]);
+
+        let mut reader = csv::Reader::from_reader(large.as_bytes());
+        let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(
+            rows[0].iter().collect::<Vec<_>>(),
+            vec![redemptions[0].id.to_string().as_str(), "Stake", "600.00", "2024-12-20", "2025-01-03"]
+        );
+    }
+
+    #[test]
+    fn test_render_html() {
+        let (casinos, transactions, redemptions) = fixture();
+        let report = summarize(2025, &BigDecimal::from(600), &casinos, &transactions, &redemptions).unwrap();
+        let html = render(TaxFormat::Html, &report);
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("<td>chumba &amp; &lt;Friends&gt;</td>"));
+        assert!(!html.contains("<Friends>"));
+        assert!(html.contains("<h2>Redemptions of 600.00 or more</h2>"));
+        assert!(html.contains("<tr><th>Total</th><th class=\"num\">169.99</th>"));
+        // Every element opened is closed.
+        for tag in ["html", "head", "body", "table", "thead", "tbody", "tfoot", "tr", "td", "th", "h1", "h2", "p"] {
+            let opened = html.matches(&format!("<{tag}>")).count() + html.matches(&format!("<{tag} ")).count();
+            assert_eq!(opened, html.matches(&format!("</{tag}>")).count(), "{tag}");
+        }
+
+        let empty = summarize(2030, &BigDecimal::from(600), &casinos, &transactions, &redemptions).unwrap();
+        assert!(empty.casinos.is_empty());
+        assert!(render(TaxFormat::Html, &empty).contains("<p>None.</p>"));
+        let json: TaxReport = serde_json::from_str(&render(TaxFormat::Json, &report)).unwrap();
+        assert_eq!(json, report);
+    }
+
+    async fn test_tax_report(ctx: CasinoContext) -> sqlx::Result<()> {
+        let user_id = Uuid::parse_str("d61b6bba-61ba-4cab-b8b7-74a880968ec6").unwrap();
+        let transaction = ctx.get_transactions(user_id).await?.remove(0);
+        let year = transaction.created_at.year();
+        let received = transaction.created_at;
+        let large = ctx.create_redemption(user_id, Uuid::nil(), BigDecimal::from(650), Some(received)).await?;
+        ctx.create_redemption(user_id, Uuid::nil(), BigDecimal::from(50), Some(received)).await?;
+        ctx.create_redemption(user_id, Uuid::nil(), BigDecimal::from(5000), None).await?;
+
+        let report = ctx.tax_report(user_id, year, &BigDecimal::from(600)).await.unwrap();
+        assert_eq!(report.casinos.len(), 1);
+        assert_eq!(report.casinos[0].casino_name, "Test");
+        assert_eq!((report.casinos[0].purchase_count, report.casinos[0].redemption_count), (1, 2));
+        assert_eq!(report.totals.net, BigDecimal::from(600));
+        assert_eq!(report.large_redemptions.iter().map(|r| r.id).collect::<Vec<_>>(), vec![large.id]);
+
+        let report = ctx.tax_report(user_id, year - 1, &BigDecimal::from(600)).await.unwrap();
+        assert!(report.casinos.is_empty() && report.large_redemptions.is_empty());
+
+        // A purchase of last year is left out, a redemption requested last year and received this year isn't.
+        let last_year = received.with_year(year - 1).unwrap();
+        let mut state = ctx.export_state().await?;
+        state.users.clear();
+        state.casinos.clear();
+        state.transactions = vec![Transaction { id: Uuid::new_v4(), created_at: last_year, updated_at: last_year, ..transaction }];
+        state.redemptions = vec![Redemption {
+            id: Uuid::new_v4(),
+            user_id,
+            casino_id: Uuid::nil(),
+            amount: BigDecimal::from(700),
+            created_at: last_year,
+            received_at: Some(received),
+        }];
+        ctx.import_state(&state).await?;
+        let report = ctx.tax_report(user_id, year, &BigDecimal::from(600)).await.unwrap();
+        assert_eq!((report.casinos[0].purchase_count, report.casinos[0].redemption_count), (1, 3));
+        assert_eq!(report.totals.net, BigDecimal::from(1300));
+        let report = ctx.tax_report(user_id, year - 1, &BigDecimal::from(600)).await.unwrap();
+        assert_eq!((report.casinos[0].purchase_count, report.casinos[0].redemption_count), (1, 0));
+        Ok(())
+    }
+}
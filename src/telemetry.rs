@@ -0,0 +1,152 @@
+//! OpenTelemetry trace export over OTLP, behind the `otel` feature.
+//!
+//! When `otlp_endpoint` is configured every `tracing` span, including the
+//! request spans and the spans around each [`CasinoContext`](crate::CasinoContext)
+//! query, is exported to the collector listening there.
+
+use tracing::Subscriber;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::Config;
+
+/// Layer added to the subscriber, `None` when export is off.
+pub(crate) type OtelLayer<S> = Option<Box<dyn Layer<S> + Send + Sync>>;
+
+/// Handle on the exporter, flushes pending spans on [`Telemetry::shutdown`].
+#[derive(Debug, Default)]
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::TracerProvider>,
+}
+
+impl Telemetry {
+    /// Flush and stop the exporter.
+    pub async fn shutdown(self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider {
+            // Shutting down blocks on the batch exporter, keep it off the runtime threads.
+            match tokio::task::spawn_blocking(move || provider.shutdown()).await {
+                Ok(Ok(())) => {}
+                Ok(Err(e)) => eprintln!("Failed to flush traces: {e}"),
+                Err(e) => eprintln!("Failed to flush traces: {e}"),
+            }
+        }
+    }
+}
+
+/// Build the OTLP export layer for `config`.
+///
+/// # Errors
+/// Will return `Err` if the exporter can't be created.
+#[cfg(feature = "otel")]
+pub(crate) fn layer<S>(config: &Config) -> Result<(OtelLayer<S>, Telemetry), opentelemetry::trace::TraceError>
+where
+    S: Subscriber + for<'a> LookupSpan<'a> + Send + Sync,
+{
+    use opentelemetry::KeyValue;
+    use opentelemetry_otlp::WithExportConfig;
+
+    let Some(endpoint) = &config.otlp_endpoint else {
+        return Ok((None, Telemetry::default()));
+    };
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()?;
+    let provider = opentelemetry_sdk::trace::TracerProvider::builder()
+        .with_batch_exporter(exporter, opentelemetry_sdk::runtime::Tokio)
+        .with_resource(opentelemetry_sdk::Resource::new([
+            KeyValue::new("service.name", env!("CARGO_PKG_NAME")),
+            KeyValue::new("service.version", env!("CARGO_PKG_VERSION")),
+        ]))
+        .build();
+    Ok((Some(provider_layer(&provider)), Telemetry { provider: Some(provider) }))
+}
+
+/// The layer sending the spans of this crate and warp to `provider`.
+#[cfg(feature = "otel")]
+fn provider_layer<S>(provider: &opentelemetry_sdk::trace::TracerProvider) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: Subscriber + for<'a> LookupSpan<'a> + Send + Sync,
+{
+    use opentelemetry::trace::TracerProvider as _;
+
+    // Filtered on its own so the log filter doesn't decide what gets exported.
+    let targets = tracing_subscriber::filter::Targets::new()
+        .with_target("casino_buddy", tracing::Level::INFO)
+        .with_target("warp", tracing::Level::INFO);
+    tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer(env!("CARGO_PKG_NAME")))
+        .with_filter(targets)
+        .boxed()
+}
+
+/// Without the `otel` feature there is nothing to export to, [`Config::validate`]
+/// rejects an `otlp_endpoint`.
+#[cfg(not(feature = "otel"))]
+#[allow(clippy::unnecessary_wraps)]
+pub(crate) fn layer<S>(_config: &Config) -> Result<(OtelLayer<S>, Telemetry), std::convert::Infallible>
+where
+    S: Subscriber + for<'a> LookupSpan<'a> + Send + Sync,
+{
+    Ok((None, Telemetry::default()))
+}
+
+#[cfg(all(test, feature = "otel"))]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[tokio::test]
+    async fn test_otel_layer() {
+        let (otel, telemetry) = layer::<tracing_subscriber::Registry>(&Config::default()).unwrap();
+        assert!(otel.is_none());
+        telemetry.shutdown().await;
+
+        // Nothing listens here, the exporter connects lazily and drops what it can't send.
+        let config = Config {
+            otlp_endpoint: Some("http://127.0.0.1:4317".to_string()),
+            ..Config::default()
+        };
+        let (otel, telemetry) = layer::<tracing_subscriber::Registry>(&config).unwrap();
+        assert!(otel.is_some());
+        let subscriber = tracing_subscriber::registry().with(otel);
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("test_span").in_scope(|| tracing::info!("inside"));
+        });
+        telemetry.shutdown().await;
+    }
+
+    #[test]
+    fn test_otel_export() {
+        use opentelemetry::Value;
+        use opentelemetry_sdk::testing::trace::InMemorySpanExporter;
+
+        let exporter = InMemorySpanExporter::default();
+        let provider =
+            opentelemetry_sdk::trace::TracerProvider::builder().with_simple_exporter(exporter.clone()).build();
+        let subscriber = tracing_subscriber::registry().with(provider_layer(&provider));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info_span!("get_transactions", db.system = "postgres", user_id = 7)
+                .in_scope(|| tracing::info!("inside"));
+            // Other crates' spans and spans below INFO aren't exported.
+            tracing::info_span!(target: "sqlx::query", "query").in_scope(|| {});
+            tracing::debug_span!("verbose").in_scope(|| {});
+        });
+        for result in provider.force_flush() {
+            result.unwrap();
+        }
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let names: Vec<&str> = spans.iter().map(|span| span.name.as_ref()).collect();
+        assert_eq!(vec!["get_transactions"], names);
+        let attribute = |key: &str| {
+            spans[0].attributes.iter().find(|kv| kv.key.as_str() == key).map(|kv| kv.value.clone())
+        };
+        assert_eq!(Some(Value::from("postgres")), attribute("db.system"));
+        assert_eq!(Some(Value::I64(7)), attribute("user_id"));
+        assert_eq!(1, spans[0].events.len());
+        provider.shutdown().unwrap();
+    }
+}
@@ -0,0 +1,35 @@
+//! Shared test harness, every database test runs once per compiled in backend.
+//!
+//! The schema comes from the same embedded migrations the server runs, with
+//! the test users, casino and transactions from `test_fixtures/` on top.
+
+/// Generate a `#[sqlx::test]` per backend for each of the given test functions.
+/// The functions take a [`CasinoContext`](crate::CasinoContext) and live in the invoking module.
+macro_rules! backend_tests {
+    ($($name:ident),* $(,)?) => {
+        mod postgres {
+            $(
+                #[sqlx::test(
+                    migrator = "crate::migrate::MIGRATOR",
+                    fixtures(path = "../test_fixtures", scripts("basic_user"))
+                )]
+                async fn $name(pool: sqlx::PgPool) -> sqlx::Result<()> {
+                    super::$name(crate::CasinoContext::new(pool)).await
+                }
+            )*
+        }
+
+        #[cfg(feature = "sqlite")]
+        mod sqlite {
+            $(
+                #[sqlx::test(
+                    migrator = "crate::migrate::SQLITE_MIGRATOR",
+                    fixtures(path = "../test_fixtures/sqlite", scripts("basic_user"))
+                )]
+                async fn $name(pool: sqlx::SqlitePool) -> sqlx::Result<()> {
+                    super::$name(crate::CasinoContext::new(pool)).await
+                }
+            )*
+        }
+    };
+}
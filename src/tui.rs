@@ -0,0 +1,400 @@
+//! Terminal dashboard, behind the `tui` feature.
+//!
+//! [`App`] holds the [`Dashboard`] of a user and the entry form being typed,
+//! turns key presses into [`Action`]s and draws itself with ratatui. The
+//! `casino-buddy-tui` binary runs the event loop and logs the submitted
+//! [`Entry`]s with [`log_entry`].
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use bigdecimal::BigDecimal;
+use chrono::NaiveDate;
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, Paragraph, Row, Table};
+use ratatui::Frame;
+use uuid::Uuid;
+
+use crate::cli::{bonus_percent, resolve_casino, CliError};
+use crate::dashboard::Dashboard;
+use crate::{Casino, CasinoContext};
+
+/// Key help shown when no form is open.
+const HELP: &str = "b buy  e redeem  c claim bonus  r refresh  q quit";
+
+/// An entry typed into the dashboard, with the casino as typed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Entry {
+    Buy { casino: String, cost: BigDecimal, benefit: BigDecimal },
+    Redeem { casino: String, amount: BigDecimal },
+    Bonus { casino: String, sc: BigDecimal },
+}
+
+/// What the event loop should do after a key press.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    Refresh,
+    Submit(Entry),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EntryKind {
+    Buy,
+    Redeem,
+    Bonus,
+}
+
+impl EntryKind {
+    fn title(self) -> &'static str {
+        match self {
+            Self::Buy => "Buy",
+            Self::Redeem => "Redeem",
+            Self::Bonus => "Claim bonus",
+        }
+    }
+
+    fn labels(self) -> &'static [&'static str] {
+        match self {
+            Self::Buy => &["casino", "cost", "benefit"],
+            Self::Redeem => &["casino", "amount"],
+            Self::Bonus => &["casino", "sc"],
+        }
+    }
+}
+
+/// An entry being typed, one text field per label.
+#[derive(Debug, Clone)]
+struct Form {
+    kind: EntryKind,
+    values: Vec<String>,
+    focus: usize,
+}
+
+impl Form {
+    fn new(kind: EntryKind, casino: String) -> Self {
+        let mut values = vec![String::new(); kind.labels().len()];
+        values[0] = casino;
+        Self { kind, values, focus: 0 }
+    }
+
+    /// Parse the fields into an entry, naming the first bad one.
+    fn entry(&self) -> Result<Entry, String> {
+        let casino = self.values[0].trim().to_string();
+        if casino.is_empty() {
+            return Err("casino is required".to_string());
+        }
+        let amount = |i: usize| {
+            BigDecimal::from_str(self.values[i].trim())
+                .map_err(|_| format!("{} {:?} is not a number", self.kind.labels()[i], self.values[i]))
+        };
+        Ok(match self.kind {
+            EntryKind::Buy => Entry::Buy { casino, cost: amount(1)?, benefit: amount(2)? },
+            EntryKind::Redeem => Entry::Redeem { casino, amount: amount(1)? },
+            EntryKind::Bonus => Entry::Bonus { casino, sc: amount(1)? },
+        })
+    }
+}
+
+/// State of the dashboard.
+#[derive(Debug, Clone, Default)]
+pub struct App {
+    pub dashboard: Dashboard,
+    casino_names: HashMap<Uuid, String>,
+    form: Option<Form>,
+    status: Option<String>,
+}
+
+impl App {
+    #[must_use]
+    pub fn new(dashboard: Dashboard, casinos: &[Casino]) -> Self {
+        let mut app = Self::default();
+        app.update(dashboard, casinos);
+        app
+    }
+
+    /// Replace the dashboard and the casino names shown in it.
+    pub fn update(&mut self, dashboard: Dashboard, casinos: &[Casino]) {
+        self.dashboard = dashboard;
+        self.casino_names = casinos.iter().map(|casino| (casino.id, casino.name.clone())).collect();
+    }
+
+    /// Reload the dashboard of `user_id` for `day`.
+    ///
+    /// # Errors
+    /// Will return `Err` if any of the queries fail.
+    pub async fn refresh(&mut self, ctx: &CasinoContext, user_id: Uuid, day: NaiveDate) -> Result<(), sqlx::Error> {
+        let casinos = ctx.get_all_casinos().await?;
+        let dashboard = ctx.dashboard(user_id, day).await?;
+        self.update(dashboard, &casinos);
+        Ok(())
+    }
+
+    /// Show `status` in the footer until the next key press.
+    pub fn set_status(&mut self, status: impl Into<String>) {
+        self.status = Some(status.into());
+    }
+
+    /// Handle a key press, returning what the event loop should do.
+    pub fn handle_key(&mut self, key: KeyEvent) -> Option<Action> {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Some(Action::Quit);
+        }
+        self.status = None;
+        let Some(form) = &mut self.form else {
+            let kind = match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Some(Action::Quit),
+                KeyCode::Char('r') => return Some(Action::Refresh),
+                KeyCode::Char('b') => EntryKind::Buy,
+                KeyCode::Char('e') => EntryKind::Redeem,
+                KeyCode::Char('c') => EntryKind::Bonus,
+                _ => return None,
+            };
+            // The first unclaimed bonus is the likeliest casino to claim at next.
+            let casino = match kind {
+                EntryKind::Bonus => self.dashboard.unclaimed_bonuses.first().map(|casino| casino.name.clone()),
+                _ => None,
+            };
+            self.form = Some(Form::new(kind, casino.unwrap_or_default()));
+            return None;
+        };
+        match key.code {
+            KeyCode::Esc => self.form = None,
+            KeyCode::Char(c) => form.values[form.focus].push(c),
+            KeyCode::Backspace => {
+                form.values[form.focus].pop();
+            }
+            KeyCode::BackTab | KeyCode::Up => form.focus = form.focus.saturating_sub(1),
+            KeyCode::Tab | KeyCode::Down => form.focus = (form.focus + 1).min(form.values.len() - 1),
+            KeyCode::Enter if form.focus + 1 < form.values.len() => form.focus += 1,
+            KeyCode::Enter => match form.entry() {
+                Ok(entry) => {
+                    self.form = None;
+                    return Some(Action::Submit(entry));
+                }
+                Err(e) => self.status = Some(e),
+            },
+            _ => {}
+        }
+        None
+    }
+
+    fn casino_name(&self, casino_id: Uuid) -> &str {
+        self.casino_names.get(&casino_id).map_or("?", String::as_str)
+    }
+
+    /// Draw the dashboard over the whole frame.
+    pub fn render(&self, frame: &mut Frame) {
+        let [main, footer] = Layout::vertical([Constraint::Min(0), Constraint::Length(3)]).areas(frame.area());
+        let [top, bottom] = Layout::vertical([Constraint::Percentage(50); 2]).areas(main);
+        let [summary, pending] =
+            Layout::horizontal([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(top);
+        let [unclaimed, recent] =
+            Layout::horizontal([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(bottom);
+
+        self.render_summary(frame, summary);
+        self.render_pending(frame, pending);
+        self.render_unclaimed(frame, unclaimed);
+        self.render_recent(frame, recent);
+        self.render_footer(frame, footer);
+    }
+
+    fn render_summary(&self, frame: &mut Frame, area: Rect) {
+        let header = Row::new(["casino", "spend", "benefit", "bonus", "count"])
+            .style(Style::default().add_modifier(Modifier::BOLD));
+        let rows = self.dashboard.summaries.iter().map(|summary| {
+            Row::new([
+                summary.casino_name.clone(),
+                summary.spend.round(2).to_string(),
+                summary.benefit.round(2).to_string(),
+                bonus_percent(&summary.spend, &summary.benefit),
+                summary.transactions.to_string(),
+            ])
+        });
+        let widths = [
+            Constraint::Min(12),
+            Constraint::Length(10),
+            Constraint::Length(10),
+            Constraint::Length(8),
+            Constraint::Length(6),
+        ];
+        let table = Table::new(rows, widths).header(header).block(block("Spend per casino"));
+        frame.render_widget(table, area);
+    }
+
+    fn render_pending(&self, frame: &mut Frame, area: Rect) {
+        let items = self.dashboard.pending_redemptions.iter().map(|redemption| {
+            ListItem::new(format!(
+                "{}  {:<16} {:>10}",
+                redemption.created_at.format("%Y-%m-%d"),
+                self.casino_name(redemption.casino_id),
+                redemption.amount.round(2)
+            ))
+        });
+        frame.render_widget(List::new(items).block(block("Pending redemptions")), area);
+    }
+
+    fn render_unclaimed(&self, frame: &mut Frame, area: Rect) {
+        let items = self.dashboard.unclaimed_bonuses.iter().map(|casino| ListItem::new(casino.name.clone()));
+        frame.render_widget(List::new(items).block(block("Unclaimed daily bonuses")), area);
+    }
+
+    fn render_recent(&self, frame: &mut Frame, area: Rect) {
+        let items = self.dashboard.recent_transactions.iter().map(|transaction| {
+            ListItem::new(format!(
+                "{}  {:<16} {:>10} -> {:>10}",
+                transaction.created_at.format("%Y-%m-%d %H:%M"),
+                self.casino_name(transaction.casino_id),
+                transaction.cost.round(2),
+                transaction.benefit.round(2)
+            ))
+        });
+        frame.render_widget(List::new(items).block(block("Recent transactions")), area);
+    }
+
+    fn render_footer(&self, frame: &mut Frame, area: Rect) {
+        let Some(form) = &self.form else {
+            let text = self.status.as_deref().unwrap_or(HELP);
+            frame.render_widget(Paragraph::new(text).block(block("casino-buddy")), area);
+            return;
+        };
+        let mut spans = Vec::new();
+        for (i, (label, value)) in form.kind.labels().iter().zip(&form.values).enumerate() {
+            let style = if i == form.focus {
+                Style::default().add_modifier(Modifier::REVERSED)
+            } else {
+                Style::default()
+            };
+            spans.push(Span::raw(format!("{label}: ")));
+            spans.push(Span::styled(format!("[{value}]"), style));
+            spans.push(Span::raw("  "));
+        }
+        if let Some(status) = &self.status {
+            spans.push(Span::raw(status.clone()));
+        }
+        let title = format!("{} (enter next/submit, esc cancel)", form.kind.title());
+        frame.render_widget(Paragraph::new(Line::from(spans)).block(block(&title)), area);
+    }
+}
+
+fn block(title: &str) -> Block<'_> {
+    Block::default().borders(Borders::ALL).title(title)
+}
+
+/// Log `entry` for `user_id`, returning a line describing what was logged.
+///
+/// # Errors
+/// Will return `Err` if the casino doesn't resolve or the insert fails.
+pub async fn log_entry(ctx: &CasinoContext, user_id: Uuid, entry: Entry) -> Result<String, CliError> {
+    let casinos = ctx.get_all_casinos().await?;
+    Ok(match entry {
+        Entry::Buy { casino, cost, benefit } => {
+            let casino = resolve_casino(&casinos, &casino)?;
+            let transaction = ctx.create_transaction(user_id, casino.id, cost, benefit, None).await?;
+            format!("Bought {} for {} at {}", transaction.benefit, transaction.cost, casino.name)
+        }
+        Entry::Redeem { casino, amount } => {
+            let casino = resolve_casino(&casinos, &casino)?;
+            let redemption = ctx.create_redemption(user_id, casino.id, amount, None).await?;
+            format!("Redeemed {} at {}", redemption.amount, casino.name)
+        }
+        Entry::Bonus { casino, sc } => {
+            let casino = resolve_casino(&casinos, &casino)?;
+            let bonus = ctx.create_daily_bonus(user_id, casino.id, sc, BigDecimal::from(0)).await?;
+            format!("Claimed {} SC at {}", bonus.amount_sc, casino.name)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    fn press(app: &mut App, keys: &str) -> Option<Action> {
+        let mut action = None;
+        for c in keys.chars() {
+            let code = match c {
+                '\n' => KeyCode::Enter,
+                '\t' => KeyCode::Tab,
+                '\x1b' => KeyCode::Esc,
+                '\x08' => KeyCode::Backspace,
+                c => KeyCode::Char(c),
+            };
+            action = app.handle_key(KeyEvent::new(code, KeyModifiers::NONE));
+        }
+        action
+    }
+
+    fn screen(app: &App) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(120, 30)).unwrap();
+        terminal.draw(|frame| app.render(frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        buffer
+            .content()
+            .chunks(buffer.area.width as usize)
+            .map(|line| line.iter().map(|cell| cell.symbol()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_handle_key() {
+        let mut app = App::default();
+        assert_eq!(Some(Action::Refresh), press(&mut app, "r"));
+        assert_eq!(None, press(&mut app, "x"));
+
+        let buy = Entry::Buy {
+            casino: "chumba".to_string(),
+            cost: BigDecimal::from_str("19.99").unwrap(),
+            benefit: BigDecimal::from(25),
+        };
+        assert_eq!(Some(Action::Submit(buy)), press(&mut app, "bchumbx\x08a\n19.99\t25\n"));
+
+        // A bad amount keeps the form open with the error shown.
+        assert_eq!(None, press(&mut app, "echumba\nlots\n"));
+        assert!(app.status.as_deref().unwrap().contains("amount"));
+        let redeem = Entry::Redeem { casino: "chumba".to_string(), amount: BigDecimal::from(100) };
+        assert_eq!(Some(Action::Submit(redeem)), press(&mut app, "\x08\x08\x08\x08100\n"));
+
+        // Esc closes the form first, then quits.
+        assert_eq!(None, press(&mut app, "c\x1b"));
+        assert!(app.form.is_none());
+        assert_eq!(Some(Action::Quit), press(&mut app, "q"));
+        let ctrl_c = KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL);
+        assert_eq!(Some(Action::Quit), app.handle_key(ctrl_c));
+    }
+
+    backend_tests!(test_dashboard_app);
+
+    async fn test_dashboard_app(ctx: CasinoContext) -> sqlx::Result<()> {
+        let user_id = Uuid::parse_str("d61b6bba-61ba-4cab-b8b7-74a880968ec6").unwrap();
+        let today = chrono::Utc::now().date_naive();
+        let mut app = App::default();
+        app.refresh(&ctx, user_id, today).await?;
+        let casino = app.dashboard.unclaimed_bonuses[0].name.clone();
+        assert!(screen(&app).contains(&casino));
+
+        // The bonus form starts at the first unclaimed casino.
+        let Some(Action::Submit(entry)) = press(&mut app, "c\n0.3\n") else { panic!("bonus not submitted") };
+        assert_eq!(Entry::Bonus { casino: casino.clone(), sc: BigDecimal::from_str("0.3").unwrap() }, entry);
+        let logged = log_entry(&ctx, user_id, entry).await.unwrap();
+        assert!(logged.contains(&casino));
+        let redeem = Entry::Redeem { casino: casino.clone(), amount: BigDecimal::from(100) };
+        log_entry(&ctx, user_id, redeem).await.unwrap();
+        let unknown = Entry::Buy { casino: "no such casino".to_string(), cost: 1.into(), benefit: 1.into() };
+        assert!(matches!(log_entry(&ctx, user_id, unknown).await, Err(CliError::Casino(_))));
+
+        app.refresh(&ctx, user_id, today).await?;
+        assert!(app.dashboard.unclaimed_bonuses.is_empty());
+        assert_eq!(1, app.dashboard.pending_redemptions.len());
+        let screen = screen(&app);
+        assert!(screen.contains("Pending redemptions"));
+        assert!(screen.contains("100"));
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+//! API versions and the deprecated unversioned aliases.
+//!
+//! The API is served under `/v1` and `/v2`. Each version is a tree of filters
+//! that may share routes with an older one and only replace the routes whose
+//! reply bodies changed. The original unversioned paths (`/user`, `/casino`, ...)
+//! still serve their `/v1` routes, with a `Deprecation` header and a `Link` to
+//! the replacement; routes added since are only served under a version.
+//! Operational routes like `/health` and `/metrics` aren't versioned.
+
+use warp::http::header::{HeaderValue, LINK};
+use warp::path::FullPath;
+use warp::{Filter, Rejection, Reply};
+
+/// Path prefixes of the served API versions.
+pub(crate) const API_VERSIONS: &[&str] = &["v1", "v2"];
+
+/// Header marking a deprecated route, see RFC 9745.
+pub const DEPRECATION_HEADER: &str = "deprecation";
+
+/// When the unversioned paths were deprecated, 2026-10-19 as an RFC 9745 date.
+const LEGACY_DEPRECATION: &str = "@1792368000";
+
+/// Serve the pre-versioning `v1` routes at the unversioned paths, marking every
+/// reply as deprecated.
+pub(crate) fn legacy<F, R>(v1: F) -> impl Filter<Extract = (warp::reply::Response,), Error = Rejection> + Clone
+where
+    F: Filter<Extract = (R,), Error = Rejection> + Clone + Send + Sync + 'static,
+    R: Reply,
+{
+    warp::path::full().and(v1).map(|path: FullPath, reply: R| {
+        tracing::debug!("Deprecated path {} used", path.as_str());
+        let mut res = reply.into_response();
+        let headers = res.headers_mut();
+        headers.insert(DEPRECATION_HEADER, HeaderValue::from_static(LEGACY_DEPRECATION));
+        // Paths that matched a route are made of valid header characters.
+        if let Ok(link) = HeaderValue::from_str(&format!("</v1{}>; rel=\"successor-version\"", path.as_str())) {
+            headers.insert(LINK, link);
+        }
+        res
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CasinoContext;
+    use warp::http::StatusCode;
+
+    backend_tests!(test_req_versions);
+
+    async fn test_req_versions(ctx: CasinoContext) -> sqlx::Result<()> {
+        let app = crate::get_app(&ctx).await;
+        let user = uuid::Uuid::new_v4();
+
+        let res = warp::test::request().path("/v1/casino").reply(&app).await;
+        assert_eq!(StatusCode::OK, res.status());
+        assert!(res.headers().get(DEPRECATION_HEADER).is_none());
+
+        // The unversioned path serves v1 and points at it.
+        let legacy = warp::test::request().path("/casino").reply(&app).await;
+        assert_eq!(StatusCode::OK, legacy.status());
+        assert_eq!(res.body(), legacy.body());
+        assert_eq!(LEGACY_DEPRECATION, legacy.headers()[DEPRECATION_HEADER]);
+        assert_eq!("</v1/casino>; rel=\"successor-version\"", legacy.headers()[LINK]);
+
+        // v1 keeps its `{}` for a user without transactions, v2 replies with an empty list.
+        let res = warp::test::request().path(&format!("/v1/transaction/{user}")).reply(&app).await;
+        assert_eq!(serde_json::json!({}), serde_json::from_slice::<serde_json::Value>(res.body()).unwrap());
+        let res = warp::test::request().path(&format!("/v2/transaction/{user}")).reply(&app).await;
+        assert_eq!(StatusCode::OK, res.status());
+        let body: crate::TransactionsReplyBody = serde_json::from_slice(res.body()).unwrap();
+        assert!(body.body.is_empty());
+        let res = warp::test::request().path("/v2/transaction/not-a-uuid").reply(&app).await;
+        assert_eq!(StatusCode::BAD_REQUEST, res.status());
+
+        // Routes v2 doesn't replace are shared with v1.
+        let res = warp::test::request().path("/v2/casino").reply(&app).await;
+        assert_eq!(StatusCode::OK, res.status());
+        let res = warp::test::request().path("/v3/casino").reply(&app).await;
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+
+        // Routes added after versioning have no unversioned alias.
+        let res = warp::test::request().path(&format!("/v1/summary/{user}")).reply(&app).await;
+        assert_eq!(StatusCode::OK, res.status());
+        let res = warp::test::request().path(&format!("/summary/{user}")).reply(&app).await;
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+        let res = warp::test::request().path(&format!("/export/transactions?user_id={user}")).reply(&app).await;
+        assert_eq!(StatusCode::NOT_FOUND, res.status());
+        Ok(())
+    }
+}
@@ -1,19 +1,26 @@
--- Insert a basic test user into the database
+-- The nil test user and "Test" casino with a transaction, and a test user with a single transaction.
 INSERT INTO "user"
     (id, created_at, updated_at)
 VALUES
-    ('d61b6bba-61ba-4cab-b8b7-74a880968ec6'::UUID, NOW(), NOW()),
     (uuid_nil(), NOW(), NOW());
 
--- Insert a basic test casino into the database
 INSERT INTO "casino"
     (id, name, url, description, created_at, updated_at)
 VALUES
     (uuid_nil(), 'Test', 'testcasino.com', 'Test', NOW(), NOW());
 
+INSERT INTO "transaction"
+    (user_id, casino_id, cost, benefit, created_at, updated_at, notes)
+VALUES
+    (uuid_nil(), uuid_nil(), 100, 100, NOW(), NOW(), 'fun');
+
+INSERT INTO "user"
+    (id, created_at, updated_at)
+VALUES
+    ('d61b6bba-61ba-4cab-b8b7-74a880968ec6'::UUID, NOW(), NOW());
 
 --- Insert a basic test transaction into the database
 INSERT INTO "transaction"
     (user_id, casino_id, cost, benefit, created_at, updated_at, notes)
 VALUES
-    ('d61b6bba-61ba-4cab-b8b7-74a880968ec6'::UUID, uuid_nil(), 100, 100, NOW(), NOW(), 'fun');
\ No newline at end of file
+    ('d61b6bba-61ba-4cab-b8b7-74a880968ec6'::UUID, uuid_nil(), 100, 100, NOW(), NOW(), 'fun');
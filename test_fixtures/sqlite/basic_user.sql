@@ -0,0 +1,25 @@
+-- SQLite version of test_fixtures/basic_user.sql.
+INSERT INTO "user"
+    (id, created_at, updated_at)
+VALUES
+    (zeroblob(16), CURRENT_TIMESTAMP, CURRENT_TIMESTAMP);
+
+INSERT INTO "casino"
+    (id, name, url, description, created_at, updated_at)
+VALUES
+    (zeroblob(16), 'Test', 'testcasino.com', 'Test', CURRENT_TIMESTAMP, CURRENT_TIMESTAMP);
+
+INSERT INTO "transaction"
+    (user_id, casino_id, cost, benefit, created_at, updated_at, notes)
+VALUES
+    (zeroblob(16), zeroblob(16), '100', '100', CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, 'fun');
+
+INSERT INTO "user"
+    (id, created_at, updated_at)
+VALUES
+    (X'd61b6bba61ba4cabb8b774a880968ec6', CURRENT_TIMESTAMP, CURRENT_TIMESTAMP);
+
+INSERT INTO "transaction"
+    (user_id, casino_id, cost, benefit, created_at, updated_at, notes)
+VALUES
+    (X'd61b6bba61ba4cabb8b774a880968ec6', zeroblob(16), '100', '100', CURRENT_TIMESTAMP, CURRENT_TIMESTAMP, 'fun');